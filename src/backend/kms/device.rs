@@ -36,6 +36,7 @@ use std::{
 use tracing::{debug, error, info, warn};
 
 use crate::backend::render::{element::SwlElement, GlMultiRenderer};
+use crate::wayland::globals::Globals;
 
 /// EGL context and display for rendering
 #[derive(Debug)]
@@ -121,6 +122,14 @@ impl Device {
         }
     }
 
+    /// Force or stop forcing the offscreen render path for the given output
+    #[cfg(feature = "dbus")]
+    pub fn force_offscreen(&self, output: &Output, force: bool) {
+        for surface in self.surface_manager.surfaces_for_output(output) {
+            surface.force_offscreen(force);
+        }
+    }
+
     /// Scan for connected outputs and create them
     pub fn scan_outputs(
         &mut self,
@@ -129,6 +138,7 @@ impl Device {
         gpu_manager: &mut GpuManager<crate::backend::render::GbmGlowBackend<DrmDeviceFd>>,
         shell: Arc<std::sync::RwLock<crate::shell::Shell>>,
         seat: smithay::input::Seat<crate::State>,
+        globals: &Globals,
     ) -> Result<Vec<Output>> {
         use smithay::reexports::drm::control::Device as ControlDevice;
 
@@ -152,12 +162,15 @@ impl Device {
                     continue;
                 };
 
-                match create_output_for_conn(self.drm.device_mut(), conn, display_handle) {
+                match create_output_for_conn(self.drm.device_mut(), conn, display_handle, globals) {
                     Ok(output) => {
-                        if let Err(err) = populate_modes(self.drm.device_mut(), &output, conn) {
-                            warn!(?err, ?conn, "Failed to populate modes");
-                            continue;
-                        }
+                        let drm_mode = match populate_modes(self.drm.device_mut(), &output, conn) {
+                            Ok(mode) => mode,
+                            Err(err) => {
+                                warn!(?err, ?conn, "Failed to populate modes");
+                                continue;
+                            }
+                        };
 
                         let output_name = output.name();
                         info!(
@@ -204,9 +217,7 @@ impl Device {
                             surface.add_node(self.render_node, allocator, shared_ctx);
                         }
 
-                        // create DRM compositor for the output
-                        // we need to get the DRM mode, not the output mode
-                        let drm_mode = conn_info.modes()[0]; // use first available mode
+                        // create DRM compositor for the output, with the DRM mode picked above
 
                         // get renderer from GPU manager
                         match gpu_manager.single_renderer(&self.render_node) {
@@ -478,6 +489,7 @@ fn create_output_for_conn(
     drm: &mut DrmDevice,
     conn: connector::Handle,
     display_handle: &DisplayHandle,
+    globals: &Globals,
 ) -> Result<Output> {
     use smithay::reexports::drm::control::Device as ControlDevice;
 
@@ -488,12 +500,22 @@ fn create_output_for_conn(
     let edid_info = super::drm_helpers::edid_info(drm, conn)
         .inspect_err(|err| warn!(?err, "failed to get EDID for {}", interface))
         .ok();
-    let (phys_w, phys_h) = conn_info.size().unwrap_or((0, 0));
+    let quirks = super::quirks::lookup(
+        &interface,
+        super::drm_helpers::edid_blob(&*drm, conn).as_deref(),
+    );
+    let physical_size = quirks.physical_size_or(conn_info.size());
+
+    if let Some(bpc) = quirks.max_bpc {
+        if let Err(err) = super::drm_helpers::set_max_bpc(&*drm, conn, bpc) {
+            warn!(?err, "Failed to set max bpc {} on {}", bpc, interface);
+        }
+    }
 
     let output = Output::new(
         interface,
         PhysicalProperties {
-            size: (phys_w as i32, phys_h as i32).into(),
+            size: physical_size.into(),
             subpixel: match conn_info.subpixel() {
                 connector::SubPixel::HorizontalRgb => Subpixel::HorizontalRgb,
                 connector::SubPixel::HorizontalBgr => Subpixel::HorizontalBgr,
@@ -517,23 +539,49 @@ fn create_output_for_conn(
         },
     );
 
+    quirks.attach(&output);
+
     // create the global to advertise this output to Wayland clients
-    let _global = output.create_global::<crate::state::State>(display_handle);
+    let global = output.create_global::<crate::state::State>(display_handle);
+    globals.register(display_handle, &global, None);
     tracing::info!("Created wl_output global for {}", output.name());
 
     Ok(output)
 }
 
 /// Populate available modes for an output
-fn populate_modes(drm: &mut DrmDevice, output: &Output, conn: connector::Handle) -> Result<()> {
+///
+/// Returns the DRM mode to drive the output with: the quirk mode if one is set and
+/// available, else the preferred one.
+fn populate_modes(
+    drm: &mut DrmDevice,
+    output: &Output,
+    conn: connector::Handle,
+) -> Result<smithay::reexports::drm::control::Mode> {
     use smithay::reexports::drm::control::Device as ControlDevice;
 
+    let quirks = super::quirks::output_quirks(output);
     let conn_info = drm.get_connector(conn, false)?;
-    let Some(mode) = conn_info
-        .modes()
-        .iter()
-        .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
-        .copied()
+    let quirk_mode = quirks.mode.and_then(|(w, h, refresh)| {
+        let mode = conn_info.modes().iter().copied().find(|mode| {
+            mode.size() == (w, h)
+                && refresh.is_none_or(|refresh| {
+                    (super::drm_helpers::calculate_refresh_rate(*mode) + 500) / 1000 == refresh
+                })
+        });
+        if mode.is_none() {
+            warn!("Quirk mode {}x{} not available on {}", w, h, output.name());
+        }
+        mode
+    });
+    let Some(mode) = quirk_mode
+        .or_else(|| {
+            conn_info
+                .modes()
+                .iter()
+                .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+                .copied()
+        })
         .or(conn_info.modes().get(0).copied())
     else {
         anyhow::bail!("No mode found");
@@ -568,7 +616,7 @@ fn populate_modes(drm: &mut DrmDevice, output: &Output, conn: connector::Handle)
     output.set_preferred(output_mode);
 
     // set initial configuration
-    let scale = 1.0; // simplified - could have more complex scale calculation
+    let scale = quirks.scale.unwrap_or(1.0); // simplified - could have more complex scale calculation
     let transform = Transform::Normal; // simplified - could read panel orientation
     output.change_current_state(
         Some(output_mode),
@@ -577,5 +625,5 @@ fn populate_modes(drm: &mut DrmDevice, output: &Output, conn: connector::Handle)
         Some(Point::from((0, 0))), // global coordinates - simplified position calculation
     );
 
-    Ok(())
+    Ok(mode)
 }
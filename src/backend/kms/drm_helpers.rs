@@ -161,6 +161,61 @@ impl EdidInfo {
     }
 }
 
+/// Read the raw EDID blob of a connector
+pub fn edid_blob(device: &impl ControlDevice, conn: connector::Handle) -> Option<Vec<u8>> {
+    let props = device.get_properties(conn).ok()?;
+    let (ids, values) = props.as_props_and_values();
+
+    for (&id, &value) in ids.iter().zip(values.iter()) {
+        let Ok(info) = device.get_property(id) else {
+            continue;
+        };
+        if info.name().to_bytes() == b"EDID" {
+            if value == 0 {
+                return None;
+            }
+            return device.get_property_blob(value).ok();
+        }
+    }
+
+    None
+}
+
+/// Limit the bits per color channel of a connector through its "max bpc" property
+pub fn set_max_bpc(device: &impl ControlDevice, conn: connector::Handle, bpc: u64) -> Result<()> {
+    let props = device.get_properties(conn)?;
+    let (ids, _) = props.as_props_and_values();
+
+    for &id in ids {
+        let info = device.get_property(id)?;
+        if info.name().to_bytes() == b"max bpc" {
+            device.set_property(conn, id, bpc)?;
+            return Ok(());
+        }
+    }
+
+    anyhow::bail!("Connector has no max bpc property")
+}
+
+/// Vertical refresh range (min, max) in Hz from the EDID display range limits descriptor
+pub fn edid_refresh_range(edid: &[u8]) -> Option<(u32, u32)> {
+    if edid.len() < 128 {
+        return None;
+    }
+
+    // the base block has four 18 byte descriptors starting at offset 54
+    (0..4)
+        .map(|i| &edid[54 + i * 18..72 + i * 18])
+        .find(|descriptor| descriptor[0..3] == [0, 0, 0] && descriptor[3] == 0xfd)
+        .map(|descriptor| {
+            // offset flags add 255 to the min/max vertical rates
+            let flags = descriptor[4];
+            let min = descriptor[5] as u32 + if flags & 0x01 != 0 { 255 } else { 0 };
+            let max = descriptor[6] as u32 + if flags & 0x02 != 0 { 255 } else { 0 };
+            (min, max)
+        })
+}
+
 /// Calculate refresh rate from a DRM mode
 pub fn calculate_refresh_rate(mode: Mode) -> u32 {
     let htotal = mode.hsync().2 as u32;
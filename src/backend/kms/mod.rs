@@ -2,6 +2,7 @@
 
 mod device;
 mod drm_helpers;
+pub mod quirks;
 pub mod surface;
 
 use crate::{
@@ -55,6 +56,14 @@ impl KmsState {
         }
     }
 
+    /// Force or stop forcing the offscreen render path for the given output
+    #[cfg(feature = "dbus")]
+    pub fn force_offscreen(&mut self, output: &Output, force: bool) {
+        for device in self.drm_devices.values() {
+            device.force_offscreen(output, force);
+        }
+    }
+
     /// Import a dmabuf and verify it can be used
     pub fn dmabuf_imported(&mut self, _global: &DmabufGlobal, dmabuf: Dmabuf) -> Result<DrmNode> {
         // find device with EGL support to validate the dmabuf
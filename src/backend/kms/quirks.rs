@@ -0,0 +1,355 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Per-output overrides for capabilities monitors report wrongly.
+//!
+//! A quirk matches an output by connector name ("HDMI-A-1") or by EDID
+//! identity ("GSM:5b09", manufacturer id and product code) and replaces what
+//! the backend would otherwise derive from the connector and EDID. The
+//! built-in table applies first, SWL_OUTPUT_QUIRKS on top of it, e.g.
+//! "HDMI-A-1:size=1210x680,scale=1;GSM:5b09:vrr=48-120,bpc=8,mode=3840x2160@60".
+//! The quirks of an output are kept in its user data.
+
+use std::fmt;
+
+use smithay::output::Output;
+use tracing::{info, warn};
+
+/// Known broken monitors, entries use the SWL_OUTPUT_QUIRKS format
+const BUILTIN_QUIRKS: &[&str] = &[];
+
+/// VRR override
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VrrQuirk {
+    /// never enable adaptive sync
+    Disabled,
+    /// refresh range (min, max) in Hz replacing the EDID one
+    Range(u32, u32),
+}
+
+/// Overrides for a single output, unset fields keep the reported values
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputQuirks {
+    /// physical size in millimeters
+    pub physical_size: Option<(i32, i32)>,
+    pub scale: Option<f64>,
+    pub vrr: Option<VrrQuirk>,
+    pub max_bpc: Option<u64>,
+    /// preferred mode as width, height and optionally refresh rate in Hz
+    pub mode: Option<(u16, u16, Option<u32>)>,
+}
+
+impl OutputQuirks {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether adaptive sync may be enabled at all
+    pub fn allows_vrr(&self) -> bool {
+        self.vrr != Some(VrrQuirk::Disabled)
+    }
+
+    /// Physical size in millimeters to advertise, the connector reported one unless overridden
+    pub fn physical_size_or(&self, reported: Option<(u32, u32)>) -> (i32, i32) {
+        self.physical_size
+            .or(reported.map(|(w, h)| (w as i32, h as i32)))
+            .unwrap_or((0, 0))
+    }
+
+    /// Keep the quirks in the user data of the output they apply to
+    pub fn attach(self, output: &Output) {
+        // consumed by mode selection and the surface thread
+        if !self.is_empty() {
+            output.user_data().insert_if_missing_threadsafe(|| self);
+        }
+    }
+
+    /// Take over every field the other quirks set
+    fn merge(&mut self, other: OutputQuirks) {
+        self.physical_size = other.physical_size.or(self.physical_size);
+        self.scale = other.scale.or(self.scale);
+        self.vrr = other.vrr.or(self.vrr);
+        self.max_bpc = other.max_bpc.or(self.max_bpc);
+        self.mode = other.mode.or(self.mode);
+    }
+
+    /// Parse "key=value,..." settings
+    fn parse(settings: &str) -> Option<Self> {
+        let mut quirks = Self::default();
+
+        for setting in settings.split(',').map(str::trim) {
+            let (key, value) = setting.split_once('=')?;
+            match key.trim() {
+                "size" => {
+                    let (w, h) = value.split_once('x')?;
+                    quirks.physical_size = Some((w.parse().ok()?, h.parse().ok()?));
+                }
+                "scale" => {
+                    quirks.scale = Some(value.parse().ok().filter(|scale: &f64| *scale > 0.0)?);
+                }
+                "vrr" => {
+                    quirks.vrr = Some(match value.split_once('-') {
+                        Some((min, max)) => VrrQuirk::Range(min.parse().ok()?, max.parse().ok()?),
+                        None if matches!(value, "off" | "0" | "false") => VrrQuirk::Disabled,
+                        None => return None,
+                    });
+                }
+                "bpc" => quirks.max_bpc = Some(value.parse().ok()?),
+                "mode" => {
+                    let (size, refresh) = match value.split_once('@') {
+                        Some((size, refresh)) => (size, Some(refresh.parse().ok()?)),
+                        None => (value, None),
+                    };
+                    let (w, h) = size.split_once('x')?;
+                    quirks.mode = Some((w.parse().ok()?, h.parse().ok()?, refresh));
+                }
+                _ => return None,
+            }
+        }
+
+        Some(quirks)
+    }
+}
+
+impl fmt::Display for OutputQuirks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut settings = Vec::new();
+        if let Some((w, h)) = self.physical_size {
+            settings.push(format!("size={}x{}", w, h));
+        }
+        if let Some(scale) = self.scale {
+            settings.push(format!("scale={}", scale));
+        }
+        match self.vrr {
+            Some(VrrQuirk::Disabled) => settings.push("vrr=off".to_string()),
+            Some(VrrQuirk::Range(min, max)) => settings.push(format!("vrr={}-{}", min, max)),
+            None => {}
+        }
+        if let Some(bpc) = self.max_bpc {
+            settings.push(format!("bpc={}", bpc));
+        }
+        if let Some((w, h, refresh)) = self.mode {
+            match refresh {
+                Some(refresh) => settings.push(format!("mode={}x{}@{}", w, h, refresh)),
+                None => settings.push(format!("mode={}x{}", w, h)),
+            }
+        }
+        write!(f, "{}", settings.join(","))
+    }
+}
+
+/// EDID identity used to match quirks: three letter manufacturer id and product code
+pub fn edid_identity(edid: &[u8]) -> Option<String> {
+    if edid.len() < 128 {
+        return None;
+    }
+
+    // manufacturer id is three 5 bit letters, big endian, product code is little endian
+    let id = u16::from_be_bytes([edid[8], edid[9]]);
+    let manufacturer: String = [10, 5, 0]
+        .iter()
+        .map(|shift| (b'A' - 1 + ((id >> shift) & 0x1f) as u8) as char)
+        .collect();
+    let product = u16::from_le_bytes([edid[10], edid[11]]);
+
+    Some(format!("{}:{:04x}", manufacturer, product))
+}
+
+/// Quirks applying to a connector, from the built-in table and SWL_OUTPUT_QUIRKS
+pub fn lookup(connector: &str, edid: Option<&[u8]>) -> OutputQuirks {
+    let identity = edid.and_then(edid_identity);
+    let config = std::env::var("SWL_OUTPUT_QUIRKS").unwrap_or_default();
+    lookup_in(BUILTIN_QUIRKS, &config, connector, identity.as_deref())
+}
+
+/// Quirks matching a connector or EDID identity, later entries override earlier ones
+fn lookup_in(
+    builtin: &[&str],
+    config: &str,
+    connector: &str,
+    identity: Option<&str>,
+) -> OutputQuirks {
+    let mut quirks = OutputQuirks::default();
+    for entry in builtin
+        .iter()
+        .copied()
+        .chain(config.split(';'))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        // the target may contain ':' itself, it ends at the ':' before the first setting
+        let target_end = entry
+            .find('=')
+            .and_then(|settings_start| entry[..settings_start].rfind(':'));
+        let parsed = target_end.and_then(|end| {
+            OutputQuirks::parse(&entry[end + 1..]).map(|quirks| (&entry[..end], quirks))
+        });
+        let Some((target, entry_quirks)) = parsed else {
+            warn!("Ignoring invalid output quirk: {}", entry);
+            continue;
+        };
+
+        if target == connector || identity == Some(target) {
+            info!("Applying output quirk to {}: {}", connector, entry_quirks);
+            quirks.merge(entry_quirks);
+        }
+    }
+
+    quirks
+}
+
+/// Quirks stored on an output when it was created
+pub fn output_quirks(output: &Output) -> OutputQuirks {
+    output
+        .user_data()
+        .get::<OutputQuirks>()
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::{
+        output::{PhysicalProperties, Subpixel},
+        utils::Transform,
+    };
+
+    use super::*;
+    use crate::{
+        backend::kms::surface::{allowed_vrr_mode, AdaptiveSync},
+        utils::testing,
+    };
+
+    /// EDID base block with a manufacturer id and product code
+    fn edid(manufacturer: &str, product: u16) -> Vec<u8> {
+        let mut edid = vec![0; 128];
+        let id = manufacturer
+            .bytes()
+            .fold(0u16, |id, letter| (id << 5) | (letter - b'A' + 1) as u16);
+        edid[8..10].copy_from_slice(&id.to_be_bytes());
+        edid[10..12].copy_from_slice(&product.to_le_bytes());
+        edid
+    }
+
+    #[test]
+    fn parses_every_setting() {
+        let quirks =
+            OutputQuirks::parse("size=1210x680,scale=1.5,vrr=48-120,bpc=8,mode=3840x2160@60")
+                .unwrap();
+        assert_eq!(
+            quirks,
+            OutputQuirks {
+                physical_size: Some((1210, 680)),
+                scale: Some(1.5),
+                vrr: Some(VrrQuirk::Range(48, 120)),
+                max_bpc: Some(8),
+                mode: Some((3840, 2160, Some(60))),
+            }
+        );
+        assert_eq!(
+            quirks.to_string(),
+            "size=1210x680,scale=1.5,vrr=48-120,bpc=8,mode=3840x2160@60"
+        );
+    }
+
+    #[test]
+    fn parses_vrr_off_and_modes_without_refresh() {
+        let quirks = OutputQuirks::parse("vrr=off, mode=1920x1080").unwrap();
+        assert_eq!(quirks.vrr, Some(VrrQuirk::Disabled));
+        assert_eq!(quirks.mode, Some((1920, 1080, None)));
+        assert!(!quirks.allows_vrr());
+        assert!(OutputQuirks::default().allows_vrr());
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        for settings in [
+            "size=1210",
+            "scale=0",
+            "scale=-1",
+            "vrr=maybe",
+            "vrr=48-",
+            "bpc=eight",
+            "mode=4k",
+            "color=red",
+            "size",
+        ] {
+            assert_eq!(OutputQuirks::parse(settings), None, "{settings}");
+        }
+    }
+
+    #[test]
+    fn edid_identity_reads_manufacturer_and_product() {
+        assert_eq!(
+            edid_identity(&edid("GSM", 0x5b09)).as_deref(),
+            Some("GSM:5b09")
+        );
+        assert_eq!(edid_identity(&[0; 64]), None);
+    }
+
+    #[test]
+    fn lookup_matches_connector_or_identity() {
+        let config = "HDMI-A-1:scale=1;GSM:5b09:vrr=off;DP-2:bpc=10";
+        assert_eq!(lookup_in(&[], config, "HDMI-A-1", None).scale, Some(1.0));
+        let quirks = lookup_in(&[], config, "DP-1", Some("GSM:5b09"));
+        assert_eq!(quirks.vrr, Some(VrrQuirk::Disabled));
+        assert_eq!(quirks.scale, None);
+        assert!(lookup_in(&[], config, "DP-3", Some("DEL:a0b1")).is_empty());
+    }
+
+    #[test]
+    fn config_overrides_the_builtin_table() {
+        let builtin = ["GSM:5b09:vrr=off,bpc=8"];
+        let quirks = lookup_in(&builtin, "GSM:5b09:vrr=48-144", "DP-1", Some("GSM:5b09"));
+        assert_eq!(quirks.vrr, Some(VrrQuirk::Range(48, 144)));
+        assert_eq!(quirks.max_bpc, Some(8));
+    }
+
+    #[test]
+    fn lookup_skips_invalid_entries() {
+        let config = "DP-1:bogus=1;DP-1;;DP-1:scale=2";
+        assert_eq!(lookup_in(&[], config, "DP-1", None).scale, Some(2.0));
+    }
+
+    #[test]
+    fn quirks_reach_the_output_and_the_vrr_gate() {
+        let config = "GSM:5b09:size=1210x680,vrr=off";
+        let quirks = lookup_in(&[], config, "DP-1", Some("GSM:5b09"));
+        let output = Output::new(
+            "DP-1".to_string(),
+            PhysicalProperties {
+                size: quirks.physical_size_or(Some((600, 340))).into(),
+                subpixel: Subpixel::Unknown,
+                make: "GSM".to_string(),
+                model: "test".to_string(),
+                serial_number: "0".to_string(),
+            },
+        );
+        quirks.attach(&output);
+
+        assert_eq!(output.physical_properties().size, (1210, 680).into());
+        assert!(!output_quirks(&output).allows_vrr());
+        for mode in [AdaptiveSync::Enabled, AdaptiveSync::Force] {
+            assert_eq!(allowed_vrr_mode(&output, mode), AdaptiveSync::Disabled);
+        }
+
+        // a range only moves the refresh floor, adaptive sync stays allowed
+        let output = testing::output("DP-2", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        lookup_in(&[], "DP-2:vrr=48-120", "DP-2", None).attach(&output);
+        assert_eq!(output_quirks(&output).vrr, Some(VrrQuirk::Range(48, 120)));
+        assert_eq!(
+            allowed_vrr_mode(&output, AdaptiveSync::Enabled),
+            AdaptiveSync::Enabled
+        );
+    }
+
+    #[test]
+    fn reported_physical_size_is_kept_without_a_quirk() {
+        let quirks = OutputQuirks::default();
+        assert_eq!(quirks.physical_size_or(Some((600, 340))), (600, 340));
+        assert_eq!(quirks.physical_size_or(None), (0, 0));
+
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        quirks.attach(&output);
+        assert!(output.user_data().get::<OutputQuirks>().is_none());
+    }
+}
@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Skipping composition of frames identical to the last queued one.
+//!
+//! A frame is fingerprinted from the output mode, scale and transform, the
+//! render path and, for every element in render order, its id, geometry,
+//! alpha, the buffer it shows and its commit counter. Re-attaching a buffer
+//! with damage means its contents changed, even when it is the same
+//! wl_buffer, and bumps the counter: only frames where no element got a new
+//! commit since the last queued frame are skipped, e.g. a client committing
+//! just to get frame callbacks. The number of skipped frames is kept in the
+//! output's user data, so it can be queried from the main thread.
+
+use std::sync::Mutex;
+
+use smithay::{
+    backend::renderer::{element::Id, utils::CommitCounter},
+    output::{Mode, Output},
+    reexports::wayland_server::backend::ObjectId,
+    utils::{Physical, Rectangle, Transform},
+};
+
+/// One element of a frame
+#[derive(Debug, PartialEq)]
+pub struct ElementFingerprint<B> {
+    pub id: Id,
+    /// buffer shown, None for elements not backed by a client buffer
+    pub buffer: Option<B>,
+    /// bumped by every commit bringing new contents
+    pub commit: CommitCounter,
+    pub geometry: Rectangle<i32, Physical>,
+    /// bits of the alpha value
+    pub alpha: u32,
+}
+
+/// Everything that makes up a composited frame
+///
+/// Compared exactly rather than hashed, a collision would swallow a real frame.
+#[derive(Debug, PartialEq)]
+pub struct FrameFingerprint<B = ObjectId> {
+    pub mode: Option<Mode>,
+    /// bits of the fractional scale
+    pub scale: u64,
+    pub transform: Transform,
+    pub direct: bool,
+    /// elements in render order
+    pub elements: Vec<ElementFingerprint<B>>,
+}
+
+/// Fingerprint of the last queued frame of an output
+#[derive(Debug)]
+pub struct IdenticalFrames<B = ObjectId> {
+    last: Option<FrameFingerprint<B>>,
+    /// frames skipped since the last frequency log
+    pub skipped: u32,
+    skipped_total: u64,
+}
+
+impl<B> Default for IdenticalFrames<B> {
+    fn default() -> Self {
+        Self {
+            last: None,
+            skipped: 0,
+            skipped_total: 0,
+        }
+    }
+}
+
+impl<B: PartialEq> IdenticalFrames<B> {
+    /// Whether a frame repeats the last queued one
+    ///
+    /// Nothing is remembered about a skipped frame, a later one is still compared
+    /// against the last queued frame and renders every commit made since.
+    pub fn is_repeat(&self, fingerprint: &FrameFingerprint<B>) -> bool {
+        self.last.as_ref() == Some(fingerprint)
+    }
+
+    /// Count a skipped frame, returns the frames skipped since the output got its surface
+    pub fn count_skipped(&mut self) -> u64 {
+        self.skipped += 1;
+        self.skipped_total += 1;
+        self.skipped_total
+    }
+
+    /// A frame got queued and is the one to compare against from now on
+    pub fn queued(&mut self, fingerprint: FrameFingerprint<B>) {
+        self.last = Some(fingerprint);
+    }
+
+    /// Compare the next frame against nothing, e.g. because it is about to be rendered
+    pub fn forget(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Skipped frames kept in the user data of an output
+#[derive(Debug, Default)]
+struct OutputSkippedFrames(Mutex<u64>);
+
+/// Frames of an output skipped as identical to the last queued one
+pub fn skipped_frames(output: &Output) -> u64 {
+    output
+        .user_data()
+        .get::<OutputSkippedFrames>()
+        .map(|skipped| *skipped.0.lock().unwrap())
+        .unwrap_or_default()
+}
+
+pub fn publish_skipped_frames(output: &Output, skipped: u64) {
+    output
+        .user_data()
+        .insert_if_missing_threadsafe(OutputSkippedFrames::default);
+    if let Some(published) = output.user_data().get::<OutputSkippedFrames>() {
+        *published.0.lock().unwrap() = skipped;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing;
+
+    fn frame(elements: &[(&Id, Option<u32>, CommitCounter)]) -> FrameFingerprint<u32> {
+        FrameFingerprint {
+            mode: None,
+            scale: 1.0f64.to_bits(),
+            transform: Transform::Normal,
+            direct: true,
+            elements: elements
+                .iter()
+                .map(|(id, buffer, commit)| ElementFingerprint {
+                    id: (*id).clone(),
+                    buffer: *buffer,
+                    commit: *commit,
+                    geometry: Rectangle::from_size((800, 600).into()),
+                    alpha: 1.0f32.to_bits(),
+                })
+                .collect(),
+        }
+    }
+
+    fn next(commit: &mut CommitCounter) -> CommitCounter {
+        commit.increment();
+        *commit
+    }
+
+    /// Run frames through the check like the surface thread does, returns the skipped ones
+    fn skipped(
+        identical: &mut IdenticalFrames<u32>,
+        frames: Vec<FrameFingerprint<u32>>,
+    ) -> Vec<bool> {
+        frames
+            .into_iter()
+            .map(|frame| {
+                let repeat = identical.is_repeat(&frame);
+                if repeat {
+                    identical.count_skipped();
+                } else {
+                    identical.queued(frame);
+                }
+                repeat
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_buffer_recommitted_with_damage_is_composited() {
+        // a single-buffer shm client repainting into its buffer every frame
+        let id = Id::new();
+        let mut commit = CommitCounter::default();
+        let frames = (0..60)
+            .map(|_| frame(&[(&id, Some(1), next(&mut commit))]))
+            .collect();
+        let skipped = skipped(&mut IdenticalFrames::default(), frames);
+        assert!(skipped.iter().all(|skipped| !skipped));
+    }
+
+    #[test]
+    fn commits_without_new_contents_do_no_gpu_work() {
+        // a client committing at 60Hz just for frame callbacks, over one second
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let id = Id::new();
+        let commit = CommitCounter::default();
+        let mut identical = IdenticalFrames::default();
+        let frames = (0..60).map(|_| frame(&[(&id, Some(1), commit)])).collect();
+
+        let skipped = skipped(&mut identical, frames);
+        publish_skipped_frames(&output, identical.skipped_total);
+        let rendered = skipped.iter().filter(|skipped| !**skipped).count();
+        assert_eq!(rendered, 1);
+        assert_eq!(skipped_frames(&output), 59);
+    }
+
+    #[test]
+    fn new_commit_after_skipped_frames_is_composited() {
+        // skipped frames don't move the reference, the commit is compared to the queued one
+        let id = Id::new();
+        let mut commit = CommitCounter::default();
+        let first = commit;
+        let frames = vec![
+            frame(&[(&id, Some(1), first)]),
+            frame(&[(&id, Some(1), first)]),
+            frame(&[(&id, Some(1), next(&mut commit))]),
+            frame(&[(&id, Some(1), commit)]),
+        ];
+        let skipped = skipped(&mut IdenticalFrames::default(), frames);
+        assert_eq!(skipped, [false, true, false, true]);
+    }
+
+    #[test]
+    fn new_buffer_is_composited() {
+        let id = Id::new();
+        let commit = CommitCounter::default();
+        let frames = vec![
+            frame(&[(&id, Some(1), commit)]),
+            frame(&[(&id, Some(2), commit)]),
+            frame(&[(&id, Some(1), commit)]),
+        ];
+        let skipped = skipped(&mut IdenticalFrames::default(), frames);
+        assert_eq!(skipped, [false, false, false]);
+    }
+
+    #[test]
+    fn forgotten_frame_is_composited() {
+        let id = Id::new();
+        let commit = CommitCounter::default();
+        let mut identical = IdenticalFrames::default();
+        identical.queued(frame(&[(&id, Some(1), commit)]));
+        identical.forget();
+        assert!(!identical.is_repeat(&frame(&[(&id, Some(1), commit)])));
+    }
+
+    #[test]
+    fn skipped_frames_are_published() {
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        assert_eq!(skipped_frames(&output), 0);
+
+        let mut identical = IdenticalFrames::<u32>::default();
+        for _ in 0..3 {
+            identical.count_skipped();
+        }
+        // the frequency log resets its count, not the total
+        identical.skipped = 0;
+        publish_skipped_frames(&output, identical.count_skipped());
+        assert_eq!(skipped_frames(&output), 4);
+    }
+}
@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Holding back the first flip after an output got enabled.
+//!
+//! A freshly enabled output would otherwise flash the bare clear color while
+//! clients are still drawing. The gate is armed on resume and lets a frame
+//! through as soon as it holds a client surface, or once the timeout passed,
+//! whatever there is to show by then.
+
+use std::time::{Duration, Instant};
+
+use smithay::backend::renderer::{ImportAll, ImportMem, Renderer};
+
+use crate::backend::render::element::{AsGlowRenderer, SwlElement};
+
+/// Longest the first flip after enabling an output waits for client content
+pub const FIRST_CONTENT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Whether frames are held back, and since when
+#[derive(Debug, Default)]
+pub struct FirstContentGate {
+    armed_at: Option<Instant>,
+}
+
+impl FirstContentGate {
+    /// Start holding back frames, SWL_FIRST_CONTENT_GATE=0 turns this off
+    pub fn arm(&mut self, now: Instant) {
+        self.armed_at = enabled().then_some(now);
+    }
+
+    /// Stop holding back frames
+    pub fn clear(&mut self) {
+        self.armed_at = None;
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed_at.is_some()
+    }
+
+    /// Whether a frame may be presented, the first one allowed through clears the gate
+    pub fn allows(&mut self, has_content: bool, now: Instant) -> bool {
+        let Some(armed_at) = self.armed_at else {
+            return true;
+        };
+        if has_content || now.saturating_duration_since(armed_at) >= FIRST_CONTENT_TIMEOUT {
+            self.armed_at = None;
+            return true;
+        }
+        false
+    }
+}
+
+/// Whether a frame shows a client surface, rather than only what swl draws itself
+pub fn has_client_content<R>(elements: &[SwlElement<R>]) -> bool
+where
+    R: AsGlowRenderer + Renderer + ImportAll + ImportMem,
+    R::TextureId: 'static,
+{
+    elements
+        .iter()
+        .any(|element| matches!(element, SwlElement::Surface(_)))
+}
+
+fn enabled() -> bool {
+    !matches!(
+        std::env::var("SWL_FIRST_CONTENT_GATE").as_deref(),
+        Ok("0") | Ok("off") | Ok("false")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::Transform;
+
+    use super::*;
+    use crate::utils::{test_client::Harness, testing};
+
+    fn armed(now: Instant) -> FirstContentGate {
+        FirstContentGate {
+            armed_at: Some(now),
+        }
+    }
+
+    #[test]
+    fn unarmed_gate_lets_frames_through() {
+        let mut gate = FirstContentGate::default();
+        assert!(gate.allows(false, Instant::now()));
+    }
+
+    #[test]
+    fn background_only_frames_are_held_back() {
+        let now = Instant::now();
+        let mut gate = armed(now);
+        assert!(!gate.allows(false, now));
+        assert!(!gate.allows(false, now + Duration::from_millis(499)));
+        assert!(gate.is_armed());
+    }
+
+    #[test]
+    fn first_content_clears_the_gate() {
+        let now = Instant::now();
+        let mut gate = armed(now);
+        assert!(gate.allows(true, now + Duration::from_millis(16)));
+        assert!(!gate.is_armed());
+        // later frames don't wait again, content or not
+        assert!(gate.allows(false, now + Duration::from_millis(32)));
+    }
+
+    #[test]
+    fn timeout_presents_without_content() {
+        let now = Instant::now();
+        let mut gate = armed(now);
+        assert!(gate.allows(false, now + FIRST_CONTENT_TIMEOUT));
+        assert!(!gate.is_armed());
+    }
+
+    #[test]
+    fn clearing_disarms_the_gate() {
+        let now = Instant::now();
+        let mut gate = armed(now);
+        gate.clear();
+        assert!(gate.allows(false, now));
+    }
+
+    #[test]
+    fn first_frame_waits_for_a_client_surface() {
+        let Some(mut renderer) = testing::headless_renderer() else {
+            eprintln!("No EGL device to render on, skipping");
+            return;
+        };
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let mut harness = Harness::new(&[output.clone()], None);
+        let now = Instant::now();
+        let mut gate = armed(now);
+
+        // a prompt is drawn by swl, it doesn't count as content
+        harness
+            .state
+            .shell
+            .write()
+            .unwrap()
+            .osd
+            .show("Press super+shift+e again within 3s to quit".into(), 3000);
+        let elements = harness
+            .state
+            .shell
+            .read()
+            .unwrap()
+            .render_elements(&output, &mut renderer);
+        assert!(!elements.is_empty());
+        let has_content = has_client_content(&elements);
+        assert!(!gate.allows(has_content, now + Duration::from_millis(16)));
+
+        harness.map_window();
+        harness.arrange();
+        let elements = harness
+            .state
+            .shell
+            .read()
+            .unwrap()
+            .render_elements(&output, &mut renderer);
+        let has_content = has_client_content(&elements);
+        assert!(gate.allows(has_content, now + Duration::from_millis(32)));
+        assert!(!gate.is_armed());
+    }
+}
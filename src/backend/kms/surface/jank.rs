@@ -0,0 +1,349 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Frame pacing attribution.
+//!
+//! Tells a client missing the compositor's deadline apart from the compositor
+//! missing vblank. Commits are timestamped on the root surface; a window has
+//! a new buffer in a presented frame when it committed between the previous
+//! frame's render start and this one's. Frame callbacks go out right after a
+//! frame is queued, so the buffer following one shown in frame N was meant
+//! for frame N+1 and is judged against its render start: showing up in a
+//! later frame makes it a late buffer. After a longer pause the client is
+//! taken to have been idle rather than late. The output itself missed its
+//! deadline when submission happened after the targeted vblank, and missed
+//! vblank when the flip landed more than half a refresh interval after it.
+//! Outcomes are counted over the last frames and kept in the user data of
+//! windows and outputs, so they can be queried from the main thread.
+
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use smithay::{
+    desktop::Window,
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Clock, Monotonic, Time},
+    wayland::compositor::{get_parent, with_states},
+};
+use tracing::debug;
+
+use super::timings::Frame;
+
+/// Frames the counts are taken over
+const JANK_WINDOW: usize = 300;
+
+/// Gap between buffers past which a client is taken to have been idle, not late
+const IDLE_GAP: Duration = Duration::from_millis(100);
+
+/// Time of the last commit in a surface tree, kept in the root surface's data
+struct LastCommit(Mutex<Time<Monotonic>>);
+
+/// Outcomes of the last frames, true for a miss
+#[derive(Debug, Default)]
+struct History(VecDeque<bool>);
+
+impl History {
+    fn push(&mut self, missed: bool) {
+        self.0.push_back(missed);
+        if self.0.len() > JANK_WINDOW {
+            self.0.pop_front();
+        }
+    }
+
+    fn missed(&self) -> usize {
+        self.0.iter().filter(|missed| **missed).count()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Late buffers of a window, kept in its user data
+#[derive(Debug, Default)]
+struct WindowJank(Mutex<WindowHistory>);
+
+#[derive(Debug, Default)]
+struct WindowHistory {
+    late: History,
+    /// render start of the frame that showed the window's last buffer
+    shown_at: Option<Time<Monotonic>>,
+}
+
+/// Missed deadlines and vblanks of an output, kept in its user data
+#[derive(Debug, Default)]
+struct OutputJank(Mutex<OutputHistory>);
+
+#[derive(Debug, Default)]
+struct OutputHistory {
+    missed_deadline: History,
+    missed_vblank: History,
+}
+
+/// Output counts over the last frames
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputJankStats {
+    pub frames: usize,
+    pub missed_deadline: usize,
+    pub missed_vblank: usize,
+}
+
+/// Record a commit, called from the compositor commit handler
+pub fn record_commit(surface: &WlSurface) {
+    let mut root = surface.clone();
+    while let Some(parent) = get_parent(&root) {
+        root = parent;
+    }
+
+    let now = Clock::<Monotonic>::new().now();
+    with_states(&root, |states| {
+        let data = states
+            .data_map
+            .get_or_insert_threadsafe(|| LastCommit(Mutex::new(now)));
+        *data.0.lock().unwrap() = now;
+    });
+}
+
+fn last_commit(window: &Window) -> Option<Time<Monotonic>> {
+    let toplevel = window.toplevel()?;
+    with_states(toplevel.wl_surface(), |states| {
+        states
+            .data_map
+            .get::<LastCommit>()
+            .map(|data| *data.0.lock().unwrap())
+    })
+}
+
+/// Whether a window's buffer made it into a frame late, None without a new buffer
+///
+/// `shown_at` is the render start of the frame that showed the window's previous buffer,
+/// the buffer is on time when that was the frame right before this one.
+fn buffer_late(
+    commit: Time<Monotonic>,
+    render_start: Time<Monotonic>,
+    previous_render_start: Option<Time<Monotonic>>,
+    shown_at: Option<Time<Monotonic>>,
+) -> Option<bool> {
+    let new_buffer =
+        previous_render_start.is_none_or(|previous| commit > previous) && commit <= render_start;
+    if !new_buffer {
+        return None;
+    }
+
+    let late = match (shown_at, previous_render_start) {
+        (Some(shown_at), Some(previous)) => {
+            shown_at < previous && Time::elapsed(&shown_at, previous) <= IDLE_GAP
+        }
+        _ => false,
+    };
+    Some(late)
+}
+
+/// Whether an output missed its submission deadline and the targeted vblank
+fn output_misses(frame: &Frame, refresh_interval: Duration) -> (bool, bool) {
+    // without a targeted vblank (unknown refresh or VRR presenting right away) nothing can be missed
+    match frame.target_presentation {
+        Some(target) => (
+            frame.presentation_submitted > target,
+            Time::elapsed(&target, frame.presentation_presented) > refresh_interval / 2,
+        ),
+        None => (false, false),
+    }
+}
+
+/// Attribute a presented frame to the windows shown in it and to the output
+pub(super) fn frame_presented(
+    output: &Output,
+    windows: &[Window],
+    frame: &Frame,
+    previous_render_start: Option<Time<Monotonic>>,
+    refresh_interval: Duration,
+) {
+    for window in windows {
+        let Some(commit) = last_commit(window) else {
+            continue;
+        };
+
+        window
+            .user_data()
+            .insert_if_missing_threadsafe(WindowJank::default);
+        if let Some(jank) = window.user_data().get::<WindowJank>() {
+            let mut history = jank.0.lock().unwrap();
+            let verdict = buffer_late(
+                commit,
+                frame.render_start,
+                previous_render_start,
+                history.shown_at,
+            );
+            if let Some(late) = verdict {
+                history.late.push(late);
+                history.shown_at = Some(frame.render_start);
+            }
+        }
+    }
+
+    let (missed_deadline, missed_vblank) = output_misses(frame, refresh_interval);
+    if missed_deadline || missed_vblank {
+        debug!(
+            "[JANK] {} missed deadline: {}, missed vblank: {}",
+            output.name(),
+            missed_deadline,
+            missed_vblank
+        );
+    }
+
+    output
+        .user_data()
+        .insert_if_missing_threadsafe(OutputJank::default);
+    if let Some(jank) = output.user_data().get::<OutputJank>() {
+        let mut history = jank.0.lock().unwrap();
+        history.missed_deadline.push(missed_deadline);
+        history.missed_vblank.push(missed_vblank);
+    }
+}
+
+/// Late buffers of a window as (late, frames with a new buffer)
+pub fn window_late_buffers(window: &Window) -> (usize, usize) {
+    window
+        .user_data()
+        .get::<WindowJank>()
+        .map(|jank| {
+            let history = jank.0.lock().unwrap();
+            (history.late.missed(), history.late.len())
+        })
+        .unwrap_or_default()
+}
+
+/// Missed deadlines and vblanks of an output over the last frames
+pub fn output_jank(output: &Output) -> OutputJankStats {
+    output
+        .user_data()
+        .get::<OutputJank>()
+        .map(|jank| {
+            let history = jank.0.lock().unwrap();
+            OutputJankStats {
+                frames: history.missed_deadline.len(),
+                missed_deadline: history.missed_deadline.missed(),
+                missed_vblank: history.missed_vblank.missed(),
+            }
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 60Hz refresh interval
+    const REFRESH: Duration = Duration::from_nanos(16_666_667);
+    /// Time from render start until the frame is queued and frame callbacks go out
+    const CALLBACK_DELAY: Duration = Duration::from_millis(2);
+
+    fn at(time: Duration) -> Time<Monotonic> {
+        Time::from(time)
+    }
+
+    /// Verdicts on the buffers of a client drawing for `draw` after each frame callback
+    fn simulate(draw: Duration, frames: u32) -> Vec<bool> {
+        let mut commit = Duration::from_millis(1);
+        let mut shown_at = None;
+        let mut verdicts = Vec::new();
+        for n in 1..=frames {
+            let render_start = REFRESH * n;
+            let previous = (n > 1).then(|| at(REFRESH * (n - 1)));
+            if let Some(late) = buffer_late(at(commit), at(render_start), previous, shown_at) {
+                verdicts.push(late);
+                shown_at = Some(at(render_start));
+                commit = render_start + CALLBACK_DELAY + draw;
+            }
+        }
+        verdicts
+    }
+
+    /// Frame presented on the vblank it targeted
+    fn frame_on_time(n: u32) -> Frame {
+        let render_start = REFRESH * n;
+        Frame {
+            render_start: at(render_start),
+            target_presentation: Some(at(render_start + REFRESH)),
+            render_duration_elements: Duration::from_millis(1),
+            render_duration_draw: Duration::from_millis(1),
+            presentation_submitted: at(render_start + CALLBACK_DELAY),
+            presentation_presented: at(render_start + REFRESH),
+        }
+    }
+
+    #[test]
+    fn healthy_client_has_no_late_buffers() {
+        let verdicts = simulate(Duration::from_millis(5), 300);
+        // a new buffer in every frame, none of them late
+        assert_eq!(verdicts.len(), 300);
+        assert!(verdicts.iter().all(|late| !late));
+    }
+
+    #[test]
+    fn commit_before_the_presentation_belongs_to_the_next_frame() {
+        // shown in frame 1, the next buffer is committed before frame 1 got presented
+        let frame = frame_on_time(1);
+        let commit = at(REFRESH + Duration::from_millis(7));
+        assert!(commit > frame.render_start && commit <= frame.presentation_presented);
+        let shown_at = Some(frame.render_start);
+        assert_eq!(
+            buffer_late(commit, frame.render_start, None, shown_at),
+            None
+        );
+
+        // judged against the render start of frame 2, which it made
+        let next = frame_on_time(2);
+        assert_eq!(
+            buffer_late(
+                commit,
+                next.render_start,
+                Some(frame.render_start),
+                shown_at
+            ),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn throttled_client_is_late_while_the_output_keeps_up() {
+        let verdicts = simulate(Duration::from_millis(25), 300);
+        let late = verdicts.iter().filter(|late| **late).count();
+        // every buffer but the first misses the frame it was meant for
+        assert_eq!(late, verdicts.len() - 1);
+        assert!(late * 100 / verdicts.len() > 90);
+
+        for n in 1..=300 {
+            assert_eq!(output_misses(&frame_on_time(n), REFRESH), (false, false));
+        }
+    }
+
+    #[test]
+    fn client_resuming_after_idle_is_not_late() {
+        let shown_at = at(REFRESH);
+        let previous = at(REFRESH * 60);
+        let commit = at(REFRESH * 60 + Duration::from_millis(3));
+        assert_eq!(
+            buffer_late(commit, at(REFRESH * 61), Some(previous), Some(shown_at)),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn first_buffer_is_not_late() {
+        let commit = at(Duration::from_millis(1));
+        assert_eq!(buffer_late(commit, at(REFRESH), None, None), Some(false));
+    }
+
+    #[test]
+    fn late_flip_misses_vblank() {
+        let mut frame = frame_on_time(1);
+        frame.presentation_presented = at(REFRESH * 3);
+        assert_eq!(output_misses(&frame, REFRESH), (false, true));
+
+        let mut frame = frame_on_time(1);
+        frame.presentation_submitted = at(REFRESH * 3);
+        frame.target_presentation = Some(at(REFRESH * 2));
+        assert_eq!(output_misses(&frame, REFRESH), (true, false));
+    }
+}
@@ -1,5 +1,9 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
+pub mod fingerprint;
+mod first_content;
+pub mod jank;
+pub mod render_path;
 mod timings;
 
 use anyhow::{Context, Result};
@@ -20,7 +24,7 @@ use smithay::{
             element::{
                 texture::{TextureRenderBuffer, TextureRenderElement},
                 utils::{Relocate, RelocateRenderElement},
-                Kind, RenderElementStates,
+                Element, Kind, RenderElement, RenderElementStates, UnderlyingStorage,
             },
             gles::GlesTexture,
             glow::GlowRenderer,
@@ -37,17 +41,31 @@ use smithay::{
             LoopHandle, RegistrationToken,
         },
         drm::control::{connector, crtc},
+        wayland_server::Resource,
     },
-    utils::{Clock, Monotonic, Rectangle, Size, Transform},
+    utils::{Buffer as BufferCoords, Clock, Monotonic, Rectangle, Scale, Time, Transform},
     wayland::dmabuf::{DmabufFeedback, DmabufFeedbackBuilder},
 };
 
-use self::timings::Timings;
+use self::{
+    fingerprint::{publish_skipped_frames, ElementFingerprint, FrameFingerprint, IdenticalFrames},
+    first_content::{FirstContentGate, FIRST_CONTENT_TIMEOUT},
+    render_path::{
+        force_offscreen_configured, publish_render_path, RenderPath, OFFSCREEN_FALLBACK_FRAMES,
+    },
+    timings::Timings,
+};
 use crate::{
-    backend::render::{
-        cursor,
-        element::{AsGlowRenderer, SwlElement},
-        GlMultiRenderer,
+    backend::{
+        kms::{
+            drm_helpers,
+            quirks::{self, VrrQuirk},
+        },
+        render::{
+            cursor,
+            element::{AsGlowRenderer, SwlElement},
+            GlMultiRenderer,
+        },
     },
     shell::Shell,
 };
@@ -55,7 +73,7 @@ use std::{
     collections::HashMap,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, RwLock,
+        Arc, Mutex, RwLock,
     },
     time::{Duration, Instant},
 };
@@ -70,6 +88,12 @@ pub type GbmDrmOutput = DrmOutput<
     DrmDeviceFd,
 >;
 
+/// Minimum refresh rate under VRR when neither config nor EDID provide one
+const DEFAULT_MIN_REFRESH_HZ: f64 = 30.0;
+
+/// Added to the EDID reported VRR minimum, panels tend to flicker right at their limit
+const EDID_MIN_REFRESH_MARGIN_HZ: f64 = 5.0;
+
 /// Adaptive sync (VRR) configuration modes
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum AdaptiveSync {
@@ -87,6 +111,37 @@ impl Default for AdaptiveSync {
     }
 }
 
+/// VRR state of an output, as last applied by its surface thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VrrStatus {
+    /// frames are currently presented with VRR
+    pub active: bool,
+    /// refresh rate kept up with repeat frames while VRR is active, in Hz
+    pub min_refresh_hz: f64,
+}
+
+/// VRR state kept in the user data of an output
+#[derive(Debug, Default)]
+struct OutputVrr(Mutex<VrrStatus>);
+
+/// VRR state of an output, inactive until its surface thread rendered
+pub fn vrr_status(output: &Output) -> VrrStatus {
+    output
+        .user_data()
+        .get::<OutputVrr>()
+        .map(|vrr| *vrr.0.lock().unwrap())
+        .unwrap_or_default()
+}
+
+fn update_vrr_status(output: &Output, update: impl FnOnce(&mut VrrStatus)) {
+    output
+        .user_data()
+        .insert_if_missing_threadsafe(OutputVrr::default);
+    if let Some(vrr) = output.user_data().get::<OutputVrr>() {
+        update(&mut vrr.0.lock().unwrap());
+    }
+}
+
 /// Commands sent to the surface render thread
 #[derive(Debug)]
 #[allow(dead_code)] // variants will be used when we connect the render loop
@@ -112,6 +167,8 @@ pub enum ThreadCommand {
     UseAdaptiveSync(AdaptiveSync),
     /// Render element states from a successful render
     RenderStates(RenderElementStates),
+    /// Force the offscreen render path instead of rendering directly
+    ForceOffscreen(bool),
     /// End the thread
     End,
 }
@@ -176,6 +233,84 @@ impl PostprocessState {
             damage_tracker,
         })
     }
+
+    /// Render `elements` into the offscreen texture, returns the damage in buffer
+    /// coordinates of an output with `transform`
+    fn draw<R, E>(
+        &mut self,
+        renderer: &mut R,
+        elements: &[E],
+        transform: Transform,
+    ) -> Result<Vec<Rectangle<i32, BufferCoords>>>
+    where
+        R: Renderer + Bind<GlesTexture>,
+        E: RenderElement<R>,
+    {
+        let damage_tracker = &mut self.damage_tracker;
+        let mut frame_damage = Vec::new();
+        self.texture.render().draw(|texture| {
+            // bind the texture as our render target
+            let mut fb = renderer
+                .bind(texture)
+                .map_err(|e| anyhow::anyhow!("Failed to bind texture: {:?}", e))?;
+
+            // buffer age tells us how many frames ago this buffer was last used
+            // for offscreen textures, we use age 1 (always redraw everything for now)
+            let age = 1; // TODO: track this properly
+
+            // use OutputDamageTracker to render with damage tracking
+            let res = match damage_tracker.render_output(
+                renderer,
+                &mut fb,
+                age,
+                elements,
+                crate::backend::render::CLEAR_COLOR,
+            ) {
+                Ok(res) => res,
+                Err(RenderError::Rendering(err)) => {
+                    return Err(anyhow::anyhow!("Render error: {:?}", err))
+                }
+                Err(RenderError::OutputNoMode(_)) => unreachable!("Output has mode"),
+            };
+
+            // wait for rendering to complete
+            renderer
+                .wait(&res.sync)
+                .map_err(|e| anyhow::anyhow!("Failed to wait for sync: {:?}", e))?;
+
+            // unbind the texture
+            std::mem::drop(fb);
+
+            // return and accumulate damage regions
+            let area = texture.size().to_logical(1, transform);
+
+            let damage = res
+                .damage
+                .cloned()
+                .map(|v| {
+                    v.into_iter()
+                        .map(|r| r.to_logical(1).to_buffer(1, transform, &area))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            frame_damage.clone_from(&damage);
+
+            Ok(damage)
+        })?;
+        Ok(frame_damage)
+    }
+
+    /// The offscreen texture, to composite on the output
+    fn element(&self) -> TextureRenderElement<GlesTexture> {
+        TextureRenderElement::from_texture_render_buffer(
+            (0.0, 0.0), // location at origin
+            &self.texture,
+            None, // no alpha
+            None, // no src crop
+            None, // no size override
+            Kind::Unspecified,
+        )
+    }
 }
 
 /// Plane assignment for hardware composition
@@ -233,6 +368,8 @@ struct SurfaceThreadState {
 
     // offscreen rendering and damage tracking
     postprocess: Option<PostprocessState>,
+    identical_frames: IdenticalFrames,
+    render_path: RenderPath,
     last_frame_damage: Option<Vec<Rectangle<i32, smithay::utils::Buffer>>>,
     frame_count: u32, // track frame count for buffer age
 
@@ -243,6 +380,13 @@ struct SurfaceThreadState {
 
     // adaptive sync
     vrr_mode: AdaptiveSync,
+    min_refresh_hz: f64,
+    repeat_frame_timer: Option<RegistrationToken>,
+    repeat_frame: bool,
+
+    // first flip after resume is held back until there is content to show
+    first_content: FirstContentGate,
+    first_content_timer: Option<RegistrationToken>,
 
     // output info
     output: Output,
@@ -367,6 +511,14 @@ impl Surface {
         let _ = self.thread_command.send(ThreadCommand::ScheduleRender);
     }
 
+    /// Force the offscreen render path, e.g. to tell direct path artifacts apart
+    #[cfg(feature = "dbus")]
+    pub fn force_offscreen(&self, force: bool) {
+        let _ = self
+            .thread_command
+            .send(ThreadCommand::ForceOffscreen(force));
+    }
+
     /// Resume the surface with a compositor
     pub fn resume(&self, compositor: GbmDrmOutput) {
         info!("Resuming surface for output {}", self.output.name());
@@ -607,6 +759,16 @@ fn surface_thread(
         active,
         compositor: None,
         postprocess: None,
+        identical_frames: IdenticalFrames::default(),
+        render_path: {
+            let force = force_offscreen_configured(&output.name());
+            if force {
+                info!("Forcing offscreen rendering for {}", output.name());
+            }
+            let path = RenderPath::new(force);
+            publish_render_path(&output, path);
+            path
+        },
         last_frame_damage: None,
         frame_count: 0,
         state: QueueState::Idle,
@@ -626,9 +788,15 @@ fn surface_thread(
                     }
                 })
                 .unwrap_or(AdaptiveSync::Enabled); // default to Enabled (opportunistic VRR)
+            let mode = allowed_vrr_mode(&output, mode);
             debug!("VRR mode for {}: {:?}", output.name(), mode);
             mode
         },
+        min_refresh_hz: DEFAULT_MIN_REFRESH_HZ,
+        repeat_frame_timer: None,
+        repeat_frame: false,
+        first_content: FirstContentGate::default(),
+        first_content_timer: None,
         output,
         shell,
         seat,
@@ -675,7 +843,17 @@ fn surface_thread(
                 }
             }
             Event::Msg(ThreadCommand::UseAdaptiveSync(vrr)) => {
-                _state.vrr_mode = vrr;
+                _state.vrr_mode = allowed_vrr_mode(&_state.output, vrr);
+            }
+            Event::Msg(ThreadCommand::ForceOffscreen(force)) => {
+                info!(
+                    "{} offscreen rendering for {}",
+                    if force { "Forcing" } else { "No longer forcing" },
+                    _state.output.name()
+                );
+                _state.render_path.forced = force;
+                publish_render_path(&_state.output, _state.render_path);
+                _state.queue_redraw();
             }
             Event::Msg(ThreadCommand::RenderStates(_)) => {
                 // RenderStates are handled in the main thread, not the surface thread
@@ -710,11 +888,20 @@ impl SurfaceThreadState {
         );
         self.timings.set_refresh_interval(Some(interval));
 
-        // set minimum refresh interval (30Hz minimum)
-        const _SAFETY_MARGIN: u32 = 2; // magic two frames margin from kwin (unused for now)
-        let min_min_refresh_interval = Duration::from_secs_f64(1.0 / 30.0); // 30Hz
+        // set minimum refresh interval, the floor never exceeds the mode's refresh rate
+        let refresh_hz = drm_helpers::calculate_refresh_rate(mode) as f64 / 1000.0;
+        self.min_refresh_hz = self.min_refresh_floor(&compositor).min(refresh_hz);
+        info!(
+            "VRR refresh floor for {}: {:.1}Hz",
+            self.output.name(),
+            self.min_refresh_hz
+        );
         self.timings
-            .set_min_refresh_interval(Some(min_min_refresh_interval));
+            .set_min_refresh_interval(Some(Duration::from_secs_f64(1.0 / self.min_refresh_hz)));
+        let min_refresh_hz = self.min_refresh_hz;
+        update_vrr_status(&self.output, |status| {
+            status.min_refresh_hz = min_refresh_hz
+        });
 
         // check VRR support on this output
         let vrr_support = compositor
@@ -756,6 +943,9 @@ impl SurfaceThreadState {
         }
 
         self.compositor = Some(compositor);
+        // the new compositor has never shown the last frame
+        self.identical_frames.forget();
+        self.arm_first_content_gate();
         debug!(
             "Surface {} calling queue_redraw for initial render",
             self.output.name()
@@ -764,6 +954,91 @@ impl SurfaceThreadState {
         debug!("Surface {} resume complete", self.output.name());
     }
 
+    /// Hold back the first flip until a client surface is ready, see [`first_content`]
+    fn arm_first_content_gate(&mut self) {
+        self.clear_first_content_gate();
+        self.first_content.arm(Instant::now());
+        if !self.first_content.is_armed() {
+            return;
+        }
+
+        // wakes up a redraw that has nothing but background to show when time runs out
+        let timer = Timer::from_duration(FIRST_CONTENT_TIMEOUT);
+        self.first_content_timer = self
+            .loop_handle
+            .insert_source(timer, |_, _, state| {
+                state.first_content_timer = None;
+                if state.first_content.is_armed() {
+                    debug!(
+                        "No content on {} after {:?}, presenting anyway",
+                        state.output.name(),
+                        FIRST_CONTENT_TIMEOUT
+                    );
+                    state.queue_redraw();
+                }
+                TimeoutAction::Drop
+            })
+            .ok();
+    }
+
+    fn clear_first_content_gate(&mut self) {
+        self.first_content.clear();
+        if let Some(token) = self.first_content_timer.take() {
+            self.loop_handle.remove(token);
+        }
+    }
+
+    /// Lowest refresh rate the panel may run at while VRR is active
+    ///
+    /// SWL_VRR_MIN_REFRESH wins, then the quirk or EDID range minimum plus a margin, then 30Hz.
+    fn min_refresh_floor(&self, compositor: &GbmDrmOutput) -> f64 {
+        if let Some(rate) = configured_min_refresh(&self.output.name()) {
+            return rate;
+        }
+        if let Some(VrrQuirk::Range(min, _)) = quirks::output_quirks(&self.output).vrr {
+            return min as f64 + EDID_MIN_REFRESH_MARGIN_HZ;
+        }
+
+        compositor
+            .with_compositor(|c| {
+                let conn = c.pending_connectors().into_iter().next()?;
+                let edid = drm_helpers::edid_blob(c.surface(), conn)?;
+                drm_helpers::edid_refresh_range(&edid)
+            })
+            .map(|(min, _)| min as f64 + EDID_MIN_REFRESH_MARGIN_HZ)
+            .unwrap_or(DEFAULT_MIN_REFRESH_HZ)
+    }
+
+    /// Keep the panel above its refresh floor while VRR is active
+    ///
+    /// When content stalls, the last frame is presented again before the floor would be
+    /// crossed, instead of letting the panel drop to its physical minimum.
+    fn schedule_repeat_frame(&mut self) {
+        if let Some(token) = self.repeat_frame_timer.take() {
+            self.loop_handle.remove(token);
+        }
+        let Some(delay) = self.timings.time_to_min_refresh_deadline(&self.clock) else {
+            return;
+        };
+
+        self.repeat_frame_timer = self
+            .loop_handle
+            .insert_source(Timer::from_duration(delay), |_, _, state| {
+                state.repeat_frame_timer = None;
+                // anything already queued or in flight flips on its own
+                if matches!(
+                    state.state,
+                    QueueState::Idle | QueueState::WaitingForEstimatedVBlank(_)
+                ) {
+                    trace!("Repeating last frame on {}", state.output.name());
+                    state.repeat_frame = true;
+                    state.queue_redraw();
+                }
+                TimeoutAction::Drop
+            })
+            .ok();
+    }
+
     /// Select the appropriate render node for the output
     /// simplified version - just uses primary or target node
     #[allow(dead_code)] // used in redraw method
@@ -774,17 +1049,25 @@ impl SurfaceThreadState {
     }
 
     /// check if we can use direct rendering (bypass offscreen)
-    fn can_use_direct_render(&self) -> bool {
+    fn can_use_direct_render(&mut self) -> bool {
         // enable direct rendering when conditions are met
         // direct rendering is possible when:
         // 1. No screen filters active (we don't have any yet)
         // 2. No output mirroring (we don't support mirroring yet)
         // 3. No transform/scaling mismatch (not implemented)
         // 4. Simple rendering scenario
+        // 5. Not forced offscreen, by config or after a failed direct render
 
-        // enable direct rendering for Phase 5a
-        // this will give us proper buffer age from the DRM swapchain
-        true
+        // direct rendering gives us proper buffer age from the DRM swapchain
+        let previous = self.render_path;
+        let direct = self.render_path.select(self.postprocess.is_some());
+        if self.render_path.fallback_ended() {
+            info!("Retrying direct rendering for {}", self.output.name());
+        }
+        if self.render_path != previous {
+            publish_render_path(&self.output, self.render_path);
+        }
+        direct
     }
 
     /// check if elements can use hardware planes
@@ -867,6 +1150,10 @@ impl SurfaceThreadState {
 
         let estimated_presentation = self.timings.next_presentation_time(&self.clock);
         let render_start = self.timings.next_render_time(&self.clock);
+        let target_presentation = (!estimated_presentation.is_zero()).then(|| {
+            let now: Duration = self.clock.now().into();
+            Time::from(now + estimated_presentation)
+        });
 
         let timer = if render_start.is_zero() {
             // debug!("{}: Running late for frame, using immediate timer", self.output.name());
@@ -880,7 +1167,9 @@ impl SurfaceThreadState {
             .loop_handle
             .insert_source(timer, move |_time, _, state| {
                 // debug!("Timer fired for {}, starting render", state.output.name());
-                state.timings.start_render(&state.clock);
+                state
+                    .timings
+                    .start_render(&state.clock, target_presentation);
                 if let Err(err) = state.redraw(estimated_presentation) {
                     let name = state.output.name();
                     warn!(?name, "Failed to submit rendering: {:?}", err);
@@ -979,6 +1268,7 @@ impl SurfaceThreadState {
             }
 
             self.timings.presented(clock);
+            self.attribute_jank();
         }
 
         // extract redraw_needed from current state and transition to Idle
@@ -1010,12 +1300,39 @@ impl SurfaceThreadState {
 
         if needs_render {
             self.queue_redraw();
+        } else {
+            self.schedule_repeat_frame();
         }
 
         // note: frame callbacks are already sent in redraw() when we successfully queue_frame
         // or in on_estimated_vblank() when we don't render
     }
 
+    /// Attribute the frame just presented to late clients or to the compositor
+    fn attribute_jank(&self) {
+        let mut frames = self.timings.previous_frames.iter().rev();
+        let Some(frame) = frames.next() else {
+            return;
+        };
+        let previous_render_start = frames.next().map(|frame| frame.render_start);
+
+        let windows: Vec<_> = self
+            .shell
+            .read()
+            .unwrap()
+            .space
+            .elements_for_output(&self.output)
+            .cloned()
+            .collect();
+        jank::frame_presented(
+            &self.output,
+            &windows,
+            frame,
+            previous_render_start,
+            self.timings.refresh_interval(),
+        );
+    }
+
     /// Send frame callbacks to all windows on this output
     /// This allows clients to continue their animations (like cursor blinking)
     fn send_frame_callbacks(&mut self) {
@@ -1061,11 +1378,13 @@ impl SurfaceThreadState {
             let elapsed_secs = now.duration_since(self.last_freq_log).as_secs_f64();
             let freq = self.render_count as f64 / elapsed_secs;
             info!(
-                "[RENDER_FREQ] {} renders/sec for output {}",
+                "[RENDER_FREQ] {} renders/sec for output {}, {} identical frames skipped",
                 freq.round(),
-                self.output.name()
+                self.output.name(),
+                self.identical_frames.skipped
             );
             self.render_count = 0;
+            self.identical_frames.skipped = 0;
             self.last_freq_log = now;
         }
 
@@ -1121,29 +1440,41 @@ impl SurfaceThreadState {
             shell.render_elements(&self.output, &mut renderer)
         };
 
+        // nothing but background yet, keep the previous scanout until a client buffer shows up
+        if self.first_content.is_armed() {
+            let has_content = first_content::has_client_content(&elements);
+            if !self.first_content.allows(has_content, Instant::now()) {
+                trace!("Deferring first frame on {}", self.output.name());
+                let estimated_presentation = self.timings.next_presentation_time(&self.clock);
+                self.queue_estimated_vblank(estimated_presentation, false);
+                return Ok(());
+            }
+            self.clear_first_content_gate();
+        }
+
         // add cursor elements
         // add cursor rendering - software cursor for now
         // TODO: Hardware cursor via DRM planes will be added later in this phase
 
         // get cursor info from shell (which is updated by input handler)
-        let (cursor_position, cursor_status) = {
+        // the cursor position is global logical, so is the output geometry in the space
+        let (cursor_position, cursor_status, output_geometry) = {
             let shell = self.shell.read().unwrap();
-            (shell.cursor_position, shell.cursor_status.clone())
+            (
+                shell.cursor_position,
+                shell.cursor_images.current().clone(),
+                shell.space.output_geometry(&self.output),
+            )
         };
 
-        // check if cursor is on this output
-        let output_loc = self.output.current_location();
-        let output_size = self
-            .output
-            .current_mode()
-            .map(|m| Size::from((m.size.w as i32, m.size.h as i32)))
-            .unwrap_or_default();
-        let output_rect = Rectangle::new(output_loc, output_size);
-
         // for now, only render cursor on the output that contains the cursor hotspot
         // this avoids duplicate cursors when outputs overlap at the same position
         // TODO: once we have proper multi-monitor positioning, check for cursor rect overlap instead
-        let cursor_elements = if output_rect.contains(cursor_position.to_i32_round()) {
+        let output_scale = Scale::from(self.output.current_scale().fractional_scale());
+        let cursor_location = output_geometry.and_then(|geometry| {
+            cursor::location_on_output(cursor_position, geometry, output_scale)
+        });
+        let cursor_elements = if let Some(location) = cursor_location {
             // get cursor state from seat user data
             let cursor_state = self
                 .seat
@@ -1155,16 +1486,12 @@ impl SurfaceThreadState {
             // get current time for animated cursors
             let now = self.clock.now();
 
-            // draw cursor (relative to this output)
-            let relative_pos = cursor_position - output_loc.to_f64();
-            // debug!("Rendering cursor on {} at relative position {:?}", self.output.name(), relative_pos);
-
             cursor::draw_cursor(
                 &mut renderer,
                 &mut *cursor_state_ref,
                 &cursor_status,
-                relative_pos,
-                self.output.current_scale().fractional_scale().into(),
+                location,
+                output_scale,
                 now.as_millis() as u32,
             )
         } else {
@@ -1188,6 +1515,52 @@ impl SurfaceThreadState {
             elements.insert(0, swl_elem); // insert at beginning
         }
 
+        // a frame matching the last presented one exactly, without a new commit, would only
+        // produce the same image, skip composition and keep frame callbacks going
+        let scale = self.output.current_scale().fractional_scale();
+        let fingerprint = FrameFingerprint {
+            mode: self.output.current_mode(),
+            scale: scale.to_bits(),
+            transform: self.output.current_transform(),
+            direct: use_direct_render,
+            elements: elements
+                .iter()
+                .map(|element| {
+                    let buffer = match element.underlying_storage(&mut renderer) {
+                        Some(UnderlyingStorage::Wayland(buffer)) => Some(buffer.id()),
+                        _ => None,
+                    };
+                    ElementFingerprint {
+                        id: element.id().clone(),
+                        buffer,
+                        commit: element.current_commit(),
+                        geometry: element.geometry(scale.into()),
+                        alpha: element.alpha().to_bits(),
+                    }
+                })
+                .collect(),
+        };
+        let repeat_frame = std::mem::take(&mut self.repeat_frame);
+        let identical = self.identical_frames.is_repeat(&fingerprint);
+        if identical && !repeat_frame {
+            trace!("Identical frame on {}, skipping", self.output.name());
+            let skipped = self.identical_frames.count_skipped();
+            publish_skipped_frames(&self.output, skipped);
+            let estimated_presentation = self.timings.next_presentation_time(&self.clock);
+            self.queue_estimated_vblank(estimated_presentation, false);
+            return Ok(());
+        }
+        // only a frame that got queued becomes the reference
+        self.identical_frames.forget();
+
+        // a repeat frame presents the last image again: with the buffer ages reset the
+        // compositor repaints it in full and has a frame to flip, even without new damage
+        if repeat_frame {
+            if let Some(compositor) = self.compositor.as_mut() {
+                compositor.with_compositor(|c| c.reset_buffers());
+            }
+        }
+
         // mark element gathering done
         self.timings.elements_done(&self.clock);
 
@@ -1211,6 +1584,9 @@ impl SurfaceThreadState {
         }
 
         // update timings for VRR
+        if vrr != self.timings.vrr() {
+            update_vrr_status(&self.output, |status| status.active = vrr);
+        }
         self.timings.set_vrr(vrr);
 
         // choose between direct and offscreen rendering
@@ -1231,7 +1607,19 @@ impl SurfaceThreadState {
                     crate::backend::render::CLEAR_COLOR, // grey background
                     FrameFlags::DEFAULT,                 // includes cursor plane scanout
                 )
-                .map_err(|e| anyhow::anyhow!("Failed to render frame: {:?}", e))?;
+                .map_err(|e| {
+                    // the failed frame gets redrawn right away, take the offscreen path for a while
+                    if self.postprocess.is_some() {
+                        warn!(
+                            "[DIRECT] Render failed for {}, falling back to offscreen for {} frames",
+                            self.output.name(),
+                            OFFSCREEN_FALLBACK_FRAMES
+                        );
+                        self.render_path.direct_failed(true);
+                        publish_render_path(&self.output, self.render_path);
+                    }
+                    anyhow::anyhow!("Failed to render frame: {:?}", e)
+                })?;
 
             // debug!("[DIRECT] Render result for {}: is_empty={}, cursor_element={:?}, overlay_elements={}",
             //        self.output.name(),
@@ -1269,6 +1657,7 @@ impl SurfaceThreadState {
                     // for direct rendering, we don't have damage tracking yet
                     // TODO: add proper damage tracking with swapchain
                     self.last_frame_damage = None;
+                    self.identical_frames.queued(fingerprint);
 
                     // send frame callbacks now since we queued a frame
                     self.frame_callback_seq = self.frame_callback_seq.wrapping_add(1);
@@ -1316,81 +1705,24 @@ impl SurfaceThreadState {
         let postprocess = self.postprocess.as_mut().unwrap();
         let transform = self.output.current_transform();
 
-        let _damage = postprocess
-            .texture
-            .render()
-            .draw(|texture| {
-                // bind the texture as our render target
-                let mut fb = renderer
-                    .bind(texture)
-                    .map_err(|e| anyhow::anyhow!("Failed to bind texture: {:?}", e))?;
-
-                // buffer age tells us how many frames ago this buffer was last used
-                // for offscreen textures, we use age 1 (always redraw everything for now)
-                let age = 1; // TODO: track this properly
-
-                // use OutputDamageTracker to render with damage tracking
-                let res = match postprocess.damage_tracker.render_output(
-                    &mut renderer,
-                    &mut fb,
-                    age,
-                    &elements,
-                    crate::backend::render::CLEAR_COLOR,
-                ) {
-                    Ok(res) => res,
-                    Err(RenderError::Rendering(err)) => {
-                        return Err(anyhow::anyhow!("Render error: {:?}", err))
-                    }
-                    Err(RenderError::OutputNoMode(_)) => unreachable!("Output has mode"),
-                };
-
-                // wait for rendering to complete
-                renderer
-                    .wait(&res.sync)
-                    .map_err(|e| anyhow::anyhow!("Failed to wait for sync: {:?}", e))?;
-
-                // unbind the texture
-                std::mem::drop(fb);
-
-                // mark drawing done
-                self.timings.draw_done(&self.clock);
-
-                // return and accumulate damage regions
-                let area = texture.size().to_logical(1, transform);
+        let damage = postprocess
+            .draw(&mut renderer, &elements, transform)
+            .context("Failed to draw to offscreen render target")?;
 
-                let damage = res
-                    .damage
-                    .cloned()
-                    .map(|v| {
-                        v.into_iter()
-                            .map(|r| r.to_logical(1).to_buffer(1, transform, &area))
-                            .collect::<Vec<_>>()
-                    })
-                    .unwrap_or_default();
+        // mark drawing done
+        self.timings.draw_done(&self.clock);
 
-                // Store damage for next frame
-                self.last_frame_damage = Some(damage.clone());
-                self.frame_count += 1;
-
-                Ok(damage)
-            })
-            .context("Failed to draw to offscreen render target")?;
+        // Store damage for next frame
+        self.last_frame_damage = Some(damage);
+        self.frame_count += 1;
 
         // NOTE: We can't skip on empty damage yet because we use age 1
         // which forces full redraw. This will be fixed when we implement
         // proper buffer age tracking in Phase 2je
 
         // composite the offscreen texture to the display
-        // Create a texture element from our offscreen buffer
         // This is a simplified version of postprocess_elements()
-        let texture_element = TextureRenderElement::from_texture_render_buffer(
-            (0.0, 0.0), // location at origin
-            &postprocess.texture,
-            None, // no alpha
-            None, // no src crop
-            None, // no size override
-            Kind::Unspecified,
-        );
+        let texture_element = postprocess.element();
 
         // wrap in SwlElement for proper rendering
         let postprocess_elements: Vec<SwlElement<GlMultiRenderer>> =
@@ -1437,6 +1769,7 @@ impl SurfaceThreadState {
                 self.state = QueueState::WaitingForVBlank {
                     redraw_needed: false,
                 };
+                self.identical_frames.queued(fingerprint);
 
                 // send frame callbacks now since we queued a frame
                 self.frame_callback_seq = self.frame_callback_seq.wrapping_add(1);
@@ -1589,3 +1922,32 @@ impl SurfaceThreadState {
         self.send_frame_callbacks();
     }
 }
+
+/// VRR mode an output may use, a monitor known to misbehave under VRR never gets it
+pub(super) fn allowed_vrr_mode(output: &Output, mode: AdaptiveSync) -> AdaptiveSync {
+    if quirks::output_quirks(output).allows_vrr() {
+        mode
+    } else {
+        AdaptiveSync::Disabled
+    }
+}
+
+/// Per-output VRR refresh floor from SWL_VRR_MIN_REFRESH
+///
+/// Accepts a single rate for all outputs ("80") or rates per connector ("DP-1:80,HDMI-A-1:48").
+fn configured_min_refresh(output_name: &str) -> Option<f64> {
+    let config = std::env::var("SWL_VRR_MIN_REFRESH").ok()?;
+    let mut fallback = None;
+
+    for entry in config.split(',').map(str::trim) {
+        match entry.split_once(':') {
+            Some((name, rate)) if name == output_name => {
+                return rate.trim().parse::<f64>().ok().filter(|rate| *rate > 0.0);
+            }
+            Some(_) => {}
+            None => fallback = entry.parse::<f64>().ok().filter(|rate| *rate > 0.0),
+        }
+    }
+
+    fallback
+}
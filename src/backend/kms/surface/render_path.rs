@@ -0,0 +1,275 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Choosing between direct and offscreen rendering.
+//!
+//! Frames are rendered straight into the DRM compositor's buffers when
+//! possible. An output can be forced onto the offscreen path, through
+//! SWL_FORCE_OFFSCREEN or over D-Bus, to tell direct path artifacts apart,
+//! and a failed direct render falls back to it for a while before direct
+//! rendering is tried again. The state is mirrored in the output's user data
+//! so it can be queried from the main thread.
+
+use std::sync::Mutex;
+
+use smithay::output::Output;
+
+/// Frames rendered offscreen after the direct path failed, before trying it again
+pub const OFFSCREEN_FALLBACK_FRAMES: u32 = 120;
+
+/// Render path of an output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderPath {
+    /// the last frame was rendered directly
+    pub direct: bool,
+    /// offscreen rendering is forced by config or over D-Bus
+    pub forced: bool,
+    /// offscreen frames left after a failed direct render
+    pub fallback_frames: u32,
+}
+
+impl RenderPath {
+    pub fn new(forced: bool) -> Self {
+        Self {
+            forced,
+            ..Self::default()
+        }
+    }
+
+    /// Pick the path for the next frame, counting down a fallback
+    ///
+    /// Without offscreen state there is nothing to fall back to, the frame is rendered
+    /// directly whatever was asked for.
+    pub fn select(&mut self, can_render_offscreen: bool) -> bool {
+        self.direct = !can_render_offscreen || (!self.forced && self.fallback_frames == 0);
+        if !self.direct {
+            self.fallback_frames = self.fallback_frames.saturating_sub(1);
+        }
+        self.direct
+    }
+
+    /// Whether this offscreen frame was the last of a fallback
+    pub fn fallback_ended(&self) -> bool {
+        !self.direct && !self.forced && self.fallback_frames == 0
+    }
+
+    /// A direct render failed, take the offscreen path for a while if there is one
+    pub fn direct_failed(&mut self, can_render_offscreen: bool) {
+        if can_render_offscreen {
+            self.fallback_frames = OFFSCREEN_FALLBACK_FRAMES;
+        }
+    }
+}
+
+/// Render path kept in the user data of an output
+#[derive(Debug, Default)]
+struct OutputRenderPath(Mutex<RenderPath>);
+
+/// Render path of an output, as of its last rendered frame
+pub fn render_path(output: &Output) -> RenderPath {
+    output
+        .user_data()
+        .get::<OutputRenderPath>()
+        .map(|path| *path.0.lock().unwrap())
+        .unwrap_or_default()
+}
+
+pub fn publish_render_path(output: &Output, path: RenderPath) {
+    output
+        .user_data()
+        .insert_if_missing_threadsafe(OutputRenderPath::default);
+    if let Some(published) = output.user_data().get::<OutputRenderPath>() {
+        *published.0.lock().unwrap() = path;
+    }
+}
+
+/// Whether SWL_FORCE_OFFSCREEN covers an output
+pub fn force_offscreen_configured(output_name: &str) -> bool {
+    std::env::var("SWL_FORCE_OFFSCREEN")
+        .is_ok_and(|config| force_offscreen_matches(&config, output_name))
+}
+
+/// Accepts "1" for all outputs or a list of connectors ("DP-1,HDMI-A-1")
+fn force_offscreen_matches(config: &str, output_name: &str) -> bool {
+    config
+        .split(',')
+        .map(str::trim)
+        .any(|entry| matches!(entry, "1" | "all") || entry == output_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::{
+        backend::{
+            allocator::Fourcc,
+            renderer::{
+                damage::OutputDamageTracker,
+                element::{solid::SolidColorRenderElement, Id, Kind},
+                gles::GlesTexture,
+                glow::GlowRenderer,
+                utils::CommitCounter,
+                Bind, Color32F, ExportMem, Offscreen, Renderer,
+            },
+        },
+        utils::{Physical, Rectangle, Transform},
+    };
+
+    use super::{super::PostprocessState, *};
+    use crate::{backend::render::CLEAR_COLOR, utils::testing};
+
+    /// Paths taken over `frames` frames, true for direct
+    fn run(path: &mut RenderPath, can_render_offscreen: bool, frames: usize) -> Vec<bool> {
+        (0..frames)
+            .map(|_| path.select(can_render_offscreen))
+            .collect()
+    }
+
+    #[test]
+    fn direct_by_default() {
+        let mut path = RenderPath::new(false);
+        assert!(run(&mut path, true, 3).into_iter().all(|direct| direct));
+    }
+
+    #[test]
+    fn forced_output_stays_offscreen() {
+        let mut path = RenderPath::new(true);
+        assert!(run(&mut path, true, 3).into_iter().all(|direct| !direct));
+
+        path.forced = false;
+        assert!(path.select(true));
+    }
+
+    #[test]
+    fn failed_direct_render_falls_back_then_retries() {
+        let mut path = RenderPath::new(false);
+        assert!(path.select(true));
+        path.direct_failed(true);
+
+        let frames = run(&mut path, true, OFFSCREEN_FALLBACK_FRAMES as usize);
+        assert!(frames.into_iter().all(|direct| !direct));
+        assert!(path.fallback_ended());
+
+        assert!(path.select(true));
+        assert!(!path.fallback_ended());
+    }
+
+    #[test]
+    fn both_paths_agree_on_the_fallback_count() {
+        // a forced output counts a fallback down too, unforcing it midway resumes the rest
+        let mut forced = RenderPath::new(true);
+        let mut unforced = RenderPath::new(false);
+        for path in [&mut forced, &mut unforced] {
+            path.direct_failed(true);
+            run(path, true, 20);
+        }
+        assert_eq!(forced.fallback_frames, unforced.fallback_frames);
+        assert!(!forced.fallback_ended());
+
+        forced.forced = false;
+        let remaining = forced.fallback_frames as usize;
+        assert_eq!(
+            run(&mut forced, true, remaining),
+            run(&mut unforced, true, remaining)
+        );
+        assert!(forced.select(true) && unforced.select(true));
+    }
+
+    #[test]
+    fn without_offscreen_state_rendering_stays_direct() {
+        let mut path = RenderPath::new(true);
+        path.direct_failed(false);
+        assert_eq!(path.fallback_frames, 0);
+        assert!(path.select(false));
+    }
+
+    #[test]
+    fn config_selects_outputs() {
+        assert!(force_offscreen_matches("1", "DP-1"));
+        assert!(force_offscreen_matches("all", "DP-1"));
+        assert!(force_offscreen_matches("DP-1, HDMI-A-1", "HDMI-A-1"));
+        assert!(!force_offscreen_matches("DP-1", "DP-2"));
+        assert!(!force_offscreen_matches("", "DP-1"));
+    }
+
+    /// Overlapping opaque and translucent rectangles, in physical pixels
+    fn scene() -> Vec<SolidColorRenderElement> {
+        [
+            ((0, 0, 120, 80), [0.8, 0.1, 0.1, 1.0]),
+            ((60, 40, 100, 60), [0.1, 0.1, 0.8, 0.5]),
+            ((150, 10, 50, 90), [0.1, 0.7, 0.2, 1.0]),
+        ]
+        .into_iter()
+        .map(|((x, y, w, h), color)| {
+            SolidColorRenderElement::new(
+                Id::new(),
+                Rectangle::<i32, Physical>::new((x, y).into(), (w, h).into()),
+                CommitCounter::default(),
+                Color32F::from(color),
+                Kind::Unspecified,
+            )
+        })
+        .collect()
+    }
+
+    /// Render `elements` to a buffer the size of the output's mode and read it back
+    ///
+    /// The direct path draws them straight into that buffer, the offscreen path draws
+    /// them into a `PostprocessState` first and composites its texture, as `redraw` does.
+    fn render(
+        renderer: &mut GlowRenderer,
+        output: &Output,
+        elements: &[SolidColorRenderElement],
+        direct: bool,
+    ) -> Vec<u8> {
+        let size = output.current_mode().unwrap().size;
+        let buffer_size = size.to_logical(1).to_buffer(1, Transform::Normal);
+        let mut target =
+            Offscreen::<GlesTexture>::create_buffer(renderer, Fourcc::Abgr8888, buffer_size)
+                .unwrap();
+
+        let postprocess = (!direct).then(|| {
+            let mut postprocess =
+                PostprocessState::new_with_renderer(renderer, Fourcc::Abgr8888, output).unwrap();
+            postprocess
+                .draw(renderer, elements, output.current_transform())
+                .unwrap();
+            postprocess
+        });
+
+        let mut damage_tracker = OutputDamageTracker::from_output(output);
+        let mut fb = renderer.bind(&mut target).unwrap();
+        let res = match &postprocess {
+            None => damage_tracker.render_output(renderer, &mut fb, 0, elements, CLEAR_COLOR),
+            Some(postprocess) => damage_tracker.render_output(
+                renderer,
+                &mut fb,
+                0,
+                &[postprocess.element()],
+                [0.0, 0.0, 0.0, 0.0],
+            ),
+        }
+        .unwrap();
+        renderer.wait(&res.sync).unwrap();
+
+        let mapping = renderer
+            .copy_framebuffer(&fb, Rectangle::from_size(buffer_size), Fourcc::Abgr8888)
+            .unwrap();
+        drop(fb);
+        renderer.map_texture(&mapping).unwrap().to_vec()
+    }
+
+    #[test]
+    fn both_paths_render_the_same_pixels() {
+        let Some(mut renderer) = testing::headless_renderer() else {
+            eprintln!("No EGL device to render on, skipping");
+            return;
+        };
+
+        for scale in [1.0, 2.0] {
+            let output = testing::output("DP-1", (200, 100), Transform::Normal, scale, (0, 0));
+            let direct = render(&mut renderer, &output, &scene(), true);
+            let offscreen = render(&mut renderer, &output, &scene(), false);
+            assert_eq!(direct.len(), 200 * 100 * 4);
+            assert!(direct == offscreen, "paths differ at scale {}", scale);
+        }
+    }
+}
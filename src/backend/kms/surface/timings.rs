@@ -22,6 +22,7 @@ pub struct Timings {
 #[derive(Debug)]
 pub struct PendingFrame {
     render_start: Time<Monotonic>,
+    target_presentation: Option<Time<Monotonic>>,
     render_duration_elements: Option<Duration>,
     render_duration_draw: Option<Duration>,
     presentation_submitted: Option<Time<Monotonic>>,
@@ -31,6 +32,8 @@ pub struct PendingFrame {
 #[allow(dead_code)] // fields will be used for timing analysis
 pub struct Frame {
     pub render_start: Time<Monotonic>,
+    /// vblank the frame was scheduled for, if it could be estimated
+    pub target_presentation: Option<Time<Monotonic>>,
     pub render_duration_elements: Duration,
     pub render_duration_draw: Duration,
     pub presentation_submitted: Time<Monotonic>,
@@ -127,9 +130,14 @@ impl Timings {
         self.vrr
     }
 
-    pub fn start_render(&mut self, clock: &Clock<Monotonic>) {
+    pub fn start_render(
+        &mut self,
+        clock: &Clock<Monotonic>,
+        target_presentation: Option<Time<Monotonic>>,
+    ) {
         self.pending_frame = Some(PendingFrame {
             render_start: clock.now(),
+            target_presentation,
             render_duration_elements: None,
             render_duration_draw: None,
             presentation_submitted: None,
@@ -164,6 +172,7 @@ impl Timings {
         if let Some(frame) = self.pending_frame.take() {
             let new_frame = Frame {
                 render_start: frame.render_start,
+                target_presentation: frame.target_presentation,
                 render_duration_elements: frame.render_duration_elements.unwrap_or_default(),
                 render_duration_draw: frame.render_duration_draw.unwrap_or_default(),
                 presentation_submitted: frame.presentation_submitted.unwrap(),
@@ -335,9 +344,6 @@ impl Timings {
 
     pub fn past_min_render_time(&self, clock: &Clock<Monotonic>) -> bool {
         let now: Duration = clock.now().into();
-        let Some(min_refresh_interval_ns) = self.min_refresh_interval_ns else {
-            return true;
-        };
         let Some(last_presentation_time): Option<Duration> = self
             .previous_frames
             .back()
@@ -345,12 +351,38 @@ impl Timings {
         else {
             return true;
         };
-
-        let min_refresh_interval_ns = min_refresh_interval_ns.get();
         if now <= last_presentation_time {
             return false;
         }
 
+        match self.min_refresh_deadline() {
+            Some(deadline) => now >= deadline,
+            None => true,
+        }
+    }
+
+    /// Time left until a frame has to be submitted to stay above the minimum refresh rate
+    ///
+    /// Only meaningful with VRR, where the panel would otherwise drop to its physical minimum.
+    pub fn time_to_min_refresh_deadline(&self, clock: &Clock<Monotonic>) -> Option<Duration> {
+        self.time_to_min_refresh_deadline_at(clock.now().into())
+    }
+
+    fn time_to_min_refresh_deadline_at(&self, now: Duration) -> Option<Duration> {
+        if !self.vrr {
+            return None;
+        }
+        Some(self.min_refresh_deadline()?.saturating_sub(now))
+    }
+
+    /// Latest submission time that still presents within the minimum refresh interval
+    fn min_refresh_deadline(&self) -> Option<Duration> {
+        let min_refresh_interval_ns = self.min_refresh_interval_ns?.get();
+        let last_presentation_time: Duration = self
+            .previous_frames
+            .back()
+            .map(|frame| frame.presentation_presented.into())?;
+
         const MIN_MARGIN: Duration = Duration::from_millis(3);
         let baseline = if let Some(refresh_interval_ns) = self.refresh_interval_ns {
             MIN_MARGIN.max(Duration::from_nanos(refresh_interval_ns.get() / 2))
@@ -368,7 +400,7 @@ impl Timings {
             } + BASE_SAFETY_MARGIN,
         );
 
-        now >= deadline
+        Some(deadline)
     }
 
     pub fn next_render_time(&self, clock: &Clock<Monotonic>) -> Duration {
@@ -397,3 +429,184 @@ impl Timings {
         estimated_presentation_time.saturating_sub(margin)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval(hz: u64) -> Option<NonZeroU64> {
+        NonZeroU64::new(1_000_000_000 / hz)
+    }
+
+    /// 144Hz panel with a 48Hz VRR floor
+    fn timings(vrr: bool) -> Timings {
+        Timings {
+            refresh_interval_ns: interval(144),
+            min_refresh_interval_ns: interval(48),
+            vrr,
+            vendor: None,
+            pending_frame: None,
+            previous_frames: VecDeque::new(),
+        }
+    }
+
+    fn present(
+        timings: &mut Timings,
+        render_start: Duration,
+        submit: Duration,
+        presented: Duration,
+    ) {
+        timings.previous_frames.push_back(Frame {
+            render_start: Time::from(render_start),
+            target_presentation: None,
+            render_duration_elements: Duration::ZERO,
+            render_duration_draw: Duration::ZERO,
+            presentation_submitted: Time::from(render_start + submit),
+            presentation_presented: Time::from(presented),
+        });
+    }
+
+    #[test]
+    fn no_deadline_without_vrr() {
+        let clock = Clock::<Monotonic>::new();
+        let mut timings = timings(false);
+        present(&mut timings, Duration::ZERO, Duration::ZERO, Duration::ZERO);
+        assert_eq!(timings.time_to_min_refresh_deadline(&clock), None);
+    }
+
+    #[test]
+    fn no_deadline_before_the_first_presentation() {
+        let clock = Clock::<Monotonic>::new();
+        assert_eq!(timings(true).time_to_min_refresh_deadline(&clock), None);
+    }
+
+    #[test]
+    fn deadline_leaves_half_a_refresh_interval_without_samples() {
+        let mut timings = timings(true);
+        let presented = Duration::from_secs(10);
+        present(&mut timings, presented, Duration::ZERO, presented);
+
+        let floor = Duration::from_nanos(1_000_000_000 / 48);
+        let baseline = Duration::from_nanos(1_000_000_000 / 144 / 2);
+        assert_eq!(
+            timings.min_refresh_deadline(),
+            Some(presented + floor - baseline - BASE_SAFETY_MARGIN)
+        );
+    }
+
+    #[test]
+    fn deadline_leaves_the_average_submit_time() {
+        let mut timings = timings(true);
+        let submit = Duration::from_millis(2);
+        let mut presented = Duration::from_secs(10);
+        for _ in 0..SAMPLE_TIME_WINDOW {
+            presented += Duration::from_millis(10);
+            present(&mut timings, presented - submit, submit, presented);
+        }
+
+        let floor = Duration::from_nanos(1_000_000_000 / 48);
+        assert_eq!(
+            timings.min_refresh_deadline(),
+            Some(presented + floor - submit - BASE_SAFETY_MARGIN)
+        );
+    }
+
+    #[test]
+    fn passed_deadline_repeats_right_away() {
+        let clock = Clock::<Monotonic>::new();
+        let mut timings = timings(true);
+        let now: Duration = clock.now().into();
+        let presented = now.saturating_sub(Duration::from_secs(1));
+        present(&mut timings, presented, Duration::ZERO, presented);
+        assert_eq!(
+            timings.time_to_min_refresh_deadline(&clock),
+            Some(Duration::ZERO)
+        );
+        assert!(timings.past_min_render_time(&clock));
+    }
+
+    #[test]
+    fn upcoming_deadline_waits_for_the_floor() {
+        let clock = Clock::<Monotonic>::new();
+        let mut timings = timings(true);
+        let presented: Duration = clock.now().into();
+        present(&mut timings, presented, Duration::ZERO, presented);
+
+        let floor = Duration::from_nanos(1_000_000_000 / 48);
+        let delay = timings.time_to_min_refresh_deadline(&clock).unwrap();
+        assert!(delay > Duration::ZERO);
+        assert!(delay < floor);
+        assert!(!timings.past_min_render_time(&clock));
+    }
+
+    /// Time from the last presentation to the flip of a frame, once submitted
+    const FLIP_LATENCY: Duration = Duration::from_millis(1);
+
+    /// Presentation times of a stalled output kept up by repeat frames, starting with its
+    /// last presentation
+    ///
+    /// Each repeat gets scheduled on the vblank of the previous frame, as
+    /// `schedule_repeat_frame` does, starts rendering when its timer fires and flips
+    /// shortly after being submitted, VRR presenting it right away.
+    fn simulate_stall(timings: &mut Timings, submit_times: &[Duration]) -> Vec<Duration> {
+        let last: Duration = timings
+            .previous_frames
+            .back()
+            .unwrap()
+            .presentation_presented
+            .into();
+        let mut presented = vec![last];
+        for &submit in submit_times {
+            let vblank = *presented.last().unwrap();
+            let delay = timings.time_to_min_refresh_deadline_at(vblank).unwrap();
+            let render_start = vblank + delay;
+            let flip = render_start + submit + FLIP_LATENCY;
+            present(timings, render_start, submit, flip);
+            presented.push(flip);
+        }
+        presented
+    }
+
+    fn assert_intervals_within_the_floor(presented: &[Duration]) {
+        let floor = Duration::from_nanos(1_000_000_000 / 48);
+        let refresh = Duration::from_nanos(1_000_000_000 / 144);
+        for (i, pair) in presented.windows(2).enumerate() {
+            let interval = pair[1] - pair[0];
+            let within = (refresh..=floor).contains(&interval);
+            assert!(within, "flip {} came after {:?}", i + 1, interval);
+        }
+    }
+
+    #[test]
+    fn repeat_frames_keep_a_stalled_output_above_the_floor() {
+        let mut timings = timings(true);
+        // content at 144Hz, then nothing new for a second
+        let submit = Duration::from_millis(2);
+        let mut presented = Duration::from_secs(10);
+        for _ in 0..SAMPLE_TIME_WINDOW {
+            presented += Duration::from_nanos(1_000_000_000 / 144);
+            present(&mut timings, presented - submit, submit, presented);
+        }
+
+        let submit_times: Vec<_> = [1, 3, 2, 2, 3]
+            .into_iter()
+            .cycle()
+            .take(48)
+            .map(Duration::from_millis)
+            .collect();
+        let flips = simulate_stall(&mut timings, &submit_times);
+        assert_intervals_within_the_floor(&flips);
+        assert!(flips.last().unwrap() - flips[0] <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn first_repeat_frames_stay_above_the_floor_without_samples() {
+        let mut timings = timings(true);
+        let presented = Duration::from_secs(10);
+        present(&mut timings, presented, Duration::ZERO, presented);
+
+        let submit_times = vec![Duration::from_millis(3); SAMPLE_TIME_WINDOW * 2];
+        let flips = simulate_stall(&mut timings, &submit_times);
+        assert_intervals_within_the_floor(&flips);
+    }
+}
@@ -15,7 +15,7 @@ use smithay::{
     input::pointer::{CursorIcon, CursorImageAttributes, CursorImageStatus},
     reexports::wayland_server::protocol::wl_surface,
     render_elements,
-    utils::{Buffer as BufferCoords, Logical, Physical, Point, Scale, Size, Transform},
+    utils::{Buffer as BufferCoords, Logical, Physical, Point, Rectangle, Scale, Size, Transform},
     wayland::compositor::with_states,
 };
 use std::{collections::HashMap, io::Read, sync::Mutex};
@@ -129,10 +129,44 @@ render_elements! {
     Surface=WaylandSurfaceRenderElement<R>,
 }
 
+/// Where the cursor is drawn on an output, in physical pixels relative to the output
+///
+/// Both the cursor position and the output geometry are global logical. None when the
+/// hotspot isn't on the output, that avoids duplicate cursors where outputs overlap.
+pub fn location_on_output(
+    cursor_position: Point<f64, Logical>,
+    output_geometry: Rectangle<i32, Logical>,
+    scale: Scale<f64>,
+) -> Option<Point<f64, Physical>> {
+    let output_geometry = output_geometry.to_f64();
+    output_geometry
+        .contains(cursor_position)
+        .then(|| (cursor_position - output_geometry.loc).to_physical(scale))
+}
+
+/// Hotspot of a theme image of `image_size` drawn at `image_scale`, in physical pixels
+/// at `scale`
+///
+/// The hotspot follows the image scale without rounding to whole logical pixels first.
+fn theme_hotspot(
+    hotspot: (u32, u32),
+    image_size: (u32, u32),
+    image_scale: u32,
+    scale: Scale<f64>,
+) -> Point<i32, Physical> {
+    Point::<f64, BufferCoords>::from((hotspot.0 as f64, hotspot.1 as f64))
+        .to_logical(
+            image_scale as f64,
+            Transform::Normal,
+            &Size::from((image_size.0 as f64, image_size.1 as f64)),
+        )
+        .to_physical_precise_round(scale)
+}
+
 pub fn draw_surface_cursor<R>(
     renderer: &mut R,
     surface: &wl_surface::WlSurface,
-    location: Point<f64, Logical>,
+    location: Point<f64, Physical>,
     scale: impl Into<Scale<f64>>,
 ) -> Vec<(CursorRenderElement<R>, Point<i32, Physical>)>
 where
@@ -154,7 +188,7 @@ where
     render_elements_from_surface_tree(
         renderer,
         surface,
-        location.to_physical(scale).to_i32_round(),
+        location.to_i32_round(),
         scale,
         1.0,
         Kind::Cursor,
@@ -221,12 +255,13 @@ impl Default for CursorStateInner {
     }
 }
 
-// simplified draw_cursor that returns the cursor element for software rendering
+// simplified draw_cursor that returns the cursor element for software rendering,
+// `location` comes from location_on_output
 pub fn draw_cursor<R>(
     renderer: &mut R,
     cursor_state: &mut CursorStateInner,
     cursor_status: &CursorImageStatus,
-    location: Point<f64, Logical>,
+    location: Point<f64, Physical>,
     scale: Scale<f64>,
     time_millis: u32,
 ) -> Vec<(CursorRenderElement<R>, Point<i32, Physical>)>
@@ -267,19 +302,19 @@ where
             }
         };
 
-        let hotspot = Point::<i32, BufferCoords>::from((frame.xhot as i32, frame.yhot as i32))
-            .to_logical(
-                actual_scale as i32,
-                Transform::Normal,
-                &Size::from((frame.width as i32, frame.height as i32)),
-            );
+        let hotspot = theme_hotspot(
+            (frame.xhot, frame.yhot),
+            (frame.width, frame.height),
+            actual_scale,
+            scale,
+        );
         cursor_state.current_image = Some(frame);
 
         vec![(
             CursorRenderElement::Static(
                 MemoryRenderBufferRenderElement::from_buffer(
                     renderer,
-                    location.to_physical(scale),
+                    location,
                     &pointer_image,
                     None,
                     None,
@@ -288,7 +323,7 @@ where
                 )
                 .expect("Failed to import cursor bitmap"),
             ),
-            hotspot.to_physical_precise_round(scale),
+            hotspot,
         )]
     } else if let CursorImageStatus::Surface(wl_surface) = cursor_status {
         draw_surface_cursor(renderer, wl_surface, location, scale)
@@ -296,3 +331,49 @@ where
         Vec::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3840x2160 output at scale 2, right of a 1920x1080 one
+    fn output_geometry() -> Rectangle<i32, Logical> {
+        Rectangle::new((1920, 0).into(), (1920, 1080).into())
+    }
+
+    #[test]
+    fn cursor_is_placed_in_physical_pixels_of_its_output() {
+        let location = location_on_output((2000.5, 100.25).into(), output_geometry(), 2.0.into());
+        assert_eq!(location, Some(Point::from((161.0, 200.5))));
+
+        let location = location_on_output((1920.0, 0.0).into(), output_geometry(), 2.0.into());
+        assert_eq!(location, Some(Point::from((0.0, 0.0))));
+    }
+
+    #[test]
+    fn cursor_off_the_output_is_not_drawn_there() {
+        for position in [(1919.5, 100.0), (3840.0, 100.0), (2000.0, 1080.0)] {
+            let location = location_on_output(position.into(), output_geometry(), 2.0.into());
+            assert_eq!(location, None, "{:?}", position);
+        }
+    }
+
+    #[test]
+    fn hotspot_follows_the_image_scale() {
+        // a 24px theme cursor, hotspot at (4, 5) logical
+        assert_eq!(
+            theme_hotspot((4, 5), (24, 24), 1, 2.0.into()),
+            Point::from((8, 10))
+        );
+        // the same cursor from the 48px image drawn at scale 2 lands on the same pixel
+        assert_eq!(
+            theme_hotspot((8, 10), (48, 48), 2, 2.0.into()),
+            Point::from((8, 10))
+        );
+        // fractional scales round once, in physical pixels
+        assert_eq!(
+            theme_hotspot((9, 9), (48, 48), 2, 1.5.into()),
+            Point::from((7, 7))
+        );
+    }
+}
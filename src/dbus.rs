@@ -0,0 +1,780 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Optional session bus service (feature "dbus").
+//!
+//! Exposes the portal Settings backend interface, so portal-aware applications
+//! pick up the configured color scheme and accent color, and a small
+//! org.swl.Compositor1 interface for tooling. Everything runs on the main
+//! event loop through calloop's futures executor; requests that change
+//! compositor state are forwarded to the loop, mostly as keybinding actions.
+//! The connection is re-established with backoff if the bus goes away.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use futures_lite::StreamExt;
+use smithay::reexports::calloop::{
+    channel::{self, Sender},
+    futures::executor,
+    timer::TimeoutFuture,
+    LoopHandle,
+};
+use tracing::{debug, info, warn};
+use zbus::{
+    connection, fdo, interface,
+    zvariant::{OwnedValue, Value},
+};
+
+use crate::{
+    backend::kms::{
+        quirks,
+        surface::{self, fingerprint, jank, render_path},
+    },
+    input::{keybindings::Action, layout::SharedActiveLayout},
+    shell::{app_id_and_title, Shell},
+    wayland::{diagnostics::SharedClientDiagnostics, globals::Globals},
+    State,
+};
+
+/// Bus names owned by the service
+const PORTAL_BUS_NAME: &str = "org.freedesktop.impl.portal.desktop.swl";
+const COMPOSITOR_BUS_NAME: &str = "org.swl.Compositor";
+
+/// Object paths the interfaces are served at
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const COMPOSITOR_PATH: &str = "/org/swl/Compositor";
+
+/// Reconnection backoff after losing the bus
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Settings namespace holding the appearance keys
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+
+/// Appearance settings, from SWL_COLOR_SCHEME and SWL_ACCENT_COLOR
+#[derive(Debug, Clone, Copy)]
+struct Appearance {
+    /// 0: no preference, 1: prefer dark, 2: prefer light
+    color_scheme: u32,
+    /// sRGB accent color, components in [0, 1]
+    accent_color: Option<(f64, f64, f64)>,
+}
+
+impl Appearance {
+    fn from_env() -> Self {
+        let color_scheme = match std::env::var("SWL_COLOR_SCHEME").as_deref() {
+            Ok("dark") | Ok("prefer-dark") => 1,
+            Ok("light") | Ok("prefer-light") => 2,
+            _ => 0,
+        };
+
+        let accent_color = std::env::var("SWL_ACCENT_COLOR")
+            .ok()
+            .and_then(|color| parse_hex_color(&color));
+
+        Self {
+            color_scheme,
+            accent_color,
+        }
+    }
+
+    /// All keys of the appearance namespace
+    fn values(&self) -> fdo::Result<HashMap<String, OwnedValue>> {
+        let mut values = HashMap::new();
+        values.insert("color-scheme".to_string(), owned(self.color_scheme)?);
+        if let Some(accent_color) = self.accent_color {
+            values.insert("accent-color".to_string(), owned(accent_color)?);
+        }
+        Ok(values)
+    }
+}
+
+/// Parse "#rrggbb" into normalized components
+fn parse_hex_color(color: &str) -> Option<(f64, f64, f64)> {
+    let hex = color.trim().strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let component = |range: std::ops::Range<usize>| {
+        u8::from_str_radix(hex.get(range)?, 16)
+            .ok()
+            .map(|value| value as f64 / 255.0)
+    };
+    Some((component(0..2)?, component(2..4)?, component(4..6)?))
+}
+
+fn owned<'a>(value: impl Into<Value<'a>>) -> fdo::Result<OwnedValue> {
+    value
+        .into()
+        .try_into()
+        .map_err(|err: zbus::zvariant::Error| fdo::Error::Failed(err.to_string()))
+}
+
+/// Whether a ReadAll namespace pattern matches, a trailing '*' acts as a glob
+fn namespace_matches(pattern: &str, namespace: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => namespace.starts_with(prefix),
+        None => pattern.is_empty() || pattern == namespace,
+    }
+}
+
+/// Compositor state change requested over the bus, applied on the main loop
+#[derive(Debug)]
+enum Request {
+    Action(Action),
+    ForceOffscreen { output: String, force: bool },
+}
+
+/// org.freedesktop.impl.portal.Settings backend
+struct PortalSettings {
+    appearance: Appearance,
+}
+
+#[interface(name = "org.freedesktop.impl.portal.Settings")]
+impl PortalSettings {
+    async fn read_all(
+        &self,
+        namespaces: Vec<String>,
+    ) -> fdo::Result<HashMap<String, HashMap<String, OwnedValue>>> {
+        let mut settings = HashMap::new();
+        if namespaces.is_empty()
+            || namespaces
+                .iter()
+                .any(|pattern| namespace_matches(pattern, APPEARANCE_NAMESPACE))
+        {
+            settings.insert(APPEARANCE_NAMESPACE.to_string(), self.appearance.values()?);
+        }
+        Ok(settings)
+    }
+
+    async fn read(&self, namespace: &str, key: &str) -> fdo::Result<OwnedValue> {
+        if namespace == APPEARANCE_NAMESPACE {
+            if let Some(value) = self.appearance.values()?.remove(key) {
+                return Ok(value);
+            }
+        }
+        Err(fdo::Error::UnknownProperty(format!(
+            "Unknown setting {}.{}",
+            namespace, key
+        )))
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        2
+    }
+}
+
+/// org.swl.Compositor1, a safe subset of compositor controls
+struct CompositorControl {
+    shell: Arc<RwLock<Shell>>,
+    globals: Globals,
+    client_diagnostics: SharedClientDiagnostics,
+    keyboard_layout: SharedActiveLayout,
+    requests: Sender<Request>,
+}
+
+#[interface(name = "org.swl.Compositor1")]
+impl CompositorControl {
+    /// Switch to a workspace by name, like the workspace keybindings
+    async fn switch_workspace(&self, name: String) -> fdo::Result<()> {
+        if name.is_empty() || name.contains(':') {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "Invalid workspace name '{}'",
+                name
+            )));
+        }
+        self.send(Request::Action(Action::SwitchToWorkspace(name)))
+    }
+
+    /// Render an output through the offscreen path instead of directly, for debugging
+    async fn set_force_offscreen(&self, output: String, force: bool) -> fdo::Result<()> {
+        let known = self
+            .shell
+            .read()
+            .unwrap()
+            .space
+            .outputs()
+            .any(|known| known.name() == output);
+        if !known {
+            return Err(fdo::Error::InvalidArgs(format!(
+                "Unknown output '{}'",
+                output
+            )));
+        }
+        self.send(Request::ForceOffscreen { output, force })
+    }
+
+    /// List outputs with quirk overrides as (output, settings)
+    async fn list_output_quirks(&self) -> Vec<(String, String)> {
+        self.shell
+            .read()
+            .unwrap()
+            .space
+            .outputs()
+            .filter_map(|output| {
+                let quirks = quirks::output_quirks(output);
+                (!quirks.is_empty()).then(|| (output.name(), quirks.to_string()))
+            })
+            .collect()
+    }
+
+    /// List windows as (app_id, title, workspace)
+    async fn list_windows(&self) -> Vec<(String, String, String)> {
+        let shell = self.shell.read().unwrap();
+        let mut windows = Vec::new();
+        for workspace in shell.workspaces.values() {
+            for window in &workspace.windows {
+                let Some((app_id, title)) = app_id_and_title(window) else {
+                    continue;
+                };
+                windows.push((app_id, title, workspace.name.clone()));
+            }
+        }
+        windows
+    }
+
+    /// Frame pacing of the outputs as (output, frames, missed deadlines, missed vblanks)
+    async fn output_frame_stats(&self) -> Vec<(String, u32, u32, u32)> {
+        self.shell
+            .read()
+            .unwrap()
+            .space
+            .outputs()
+            .map(|output| {
+                let stats = jank::output_jank(output);
+                (
+                    output.name(),
+                    stats.frames as u32,
+                    stats.missed_deadline as u32,
+                    stats.missed_vblank as u32,
+                )
+            })
+            .collect()
+    }
+
+    /// VRR state of the outputs as (output, active, refresh floor in Hz)
+    async fn output_vrr(&self) -> Vec<(String, bool, f64)> {
+        self.shell
+            .read()
+            .unwrap()
+            .space
+            .outputs()
+            .map(|output| {
+                let vrr = surface::vrr_status(output);
+                (output.name(), vrr.active, vrr.min_refresh_hz)
+            })
+            .collect()
+    }
+
+    /// Render path per output as (name, direct, forced offscreen, fallback frames left)
+    ///
+    /// Outputs fall back to offscreen rendering for a while after a direct render failed.
+    async fn output_render_path(&self) -> Vec<(String, bool, bool, u32)> {
+        self.shell
+            .read()
+            .unwrap()
+            .space
+            .outputs()
+            .map(|output| {
+                let path = render_path::render_path(output);
+                (
+                    output.name(),
+                    path.direct,
+                    path.forced,
+                    path.fallback_frames,
+                )
+            })
+            .collect()
+    }
+
+    /// Frames skipped as identical to the last queued one, per output as (name, frames)
+    async fn output_skipped_frames(&self) -> Vec<(String, u64)> {
+        self.shell
+            .read()
+            .unwrap()
+            .space
+            .outputs()
+            .map(|output| (output.name(), fingerprint::skipped_frames(output)))
+            .collect()
+    }
+
+    /// Late buffers of the windows as (app_id, title, late buffers, new buffers)
+    async fn window_frame_stats(&self) -> Vec<(String, String, u32, u32)> {
+        let shell = self.shell.read().unwrap();
+        let mut windows = Vec::new();
+        for workspace in shell.workspaces.values() {
+            for window in &workspace.windows {
+                let Some((app_id, title)) = app_id_and_title(window) else {
+                    continue;
+                };
+                let (late, frames) = jank::window_late_buffers(window);
+                windows.push((app_id, title, late as u32, frames as u32));
+            }
+        }
+        windows
+    }
+
+    /// Clients with recorded protocol issues, most issues first
+    ///
+    /// One line per client: `<name> total=<n> <kind>=<n> ...`
+    async fn list_client_issues(&self) -> Vec<String> {
+        self.client_diagnostics.lock().unwrap().list()
+    }
+
+    /// Active keyboard layout as (group index, xkb name)
+    async fn keyboard_layout(&self) -> (u32, String) {
+        let active = self.keyboard_layout.lock().unwrap().clone();
+        (active.index, active.name)
+    }
+
+    /// Globals advertised to clients as (interface, version, privileged)
+    async fn list_globals(&self) -> Vec<(String, u32, bool)> {
+        self.globals
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.interface.to_string(), entry.version, entry.privileged))
+            .collect()
+    }
+}
+
+impl CompositorControl {
+    fn send(&self, request: Request) -> fdo::Result<()> {
+        self.requests
+            .send(request)
+            .map_err(|_| fdo::Error::Failed("Compositor is shutting down".to_string()))
+    }
+}
+
+/// Start the D-Bus service on the main event loop
+pub fn start(
+    handle: &LoopHandle<'static, State>,
+    shell: Arc<RwLock<Shell>>,
+    globals: Globals,
+    client_diagnostics: SharedClientDiagnostics,
+    keyboard_layout: SharedActiveLayout,
+) -> Result<()> {
+    let (executor, scheduler) = executor::<()>().context("Failed to create D-Bus executor")?;
+    handle
+        .insert_source(executor, |(), _, _| {})
+        .map_err(|err| anyhow::anyhow!("Failed to insert D-Bus executor: {}", err.error))?;
+
+    // state changes are applied from the loop, actions exactly like keybindings
+    let (requests, request_receiver) = channel::channel::<Request>();
+    handle
+        .insert_source(request_receiver, |event, _, state| {
+            if let channel::Event::Msg(request) = event {
+                debug!("D-Bus request: {:?}", request);
+                handle_request(state, request);
+            }
+        })
+        .map_err(|err| anyhow::anyhow!("Failed to insert D-Bus request channel: {}", err.error))?;
+
+    scheduler
+        .schedule(supervise(
+            handle.clone(),
+            shell,
+            globals,
+            client_diagnostics,
+            keyboard_layout,
+            requests,
+        ))
+        .map_err(|err| anyhow::anyhow!("Failed to schedule D-Bus service: {}", err))?;
+
+    Ok(())
+}
+
+fn handle_request(state: &mut State, request: Request) {
+    match request {
+        Request::Action(action) => state.handle_action(action),
+        Request::ForceOffscreen { output, force } => {
+            let Some(output) = state
+                .outputs
+                .iter()
+                .find(|known| known.name() == output)
+                .cloned()
+            else {
+                warn!("Output {} is gone, ignoring offscreen request", output);
+                return;
+            };
+            state.backend.force_offscreen(&output, force);
+        }
+    }
+}
+
+/// Keep the service connected, reconnecting with backoff when the bus goes away
+async fn supervise(
+    handle: LoopHandle<'static, State>,
+    shell: Arc<RwLock<Shell>>,
+    globals: Globals,
+    client_diagnostics: SharedClientDiagnostics,
+    keyboard_layout: SharedActiveLayout,
+    requests: Sender<Request>,
+) {
+    let appearance = Appearance::from_env();
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        match serve(
+            appearance,
+            shell.clone(),
+            globals.clone(),
+            client_diagnostics.clone(),
+            keyboard_layout.clone(),
+            requests.clone(),
+        )
+        .await
+        {
+            Ok(()) => {
+                info!("D-Bus connection closed, reconnecting");
+                backoff = INITIAL_BACKOFF;
+            }
+            Err(err) => {
+                warn!(
+                    "D-Bus service unavailable: {}, retrying in {:?}",
+                    err, backoff
+                );
+            }
+        }
+
+        TimeoutFuture::from_duration(&handle, backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Serve both interfaces until the connection drops
+async fn serve(
+    appearance: Appearance,
+    shell: Arc<RwLock<Shell>>,
+    globals: Globals,
+    client_diagnostics: SharedClientDiagnostics,
+    keyboard_layout: SharedActiveLayout,
+    requests: Sender<Request>,
+) -> zbus::Result<()> {
+    let connection = connection::Builder::session()?
+        .name(PORTAL_BUS_NAME)?
+        .name(COMPOSITOR_BUS_NAME)?
+        .serve_at(PORTAL_PATH, PortalSettings { appearance })?
+        .serve_at(
+            COMPOSITOR_PATH,
+            CompositorControl {
+                shell,
+                globals,
+                client_diagnostics,
+                keyboard_layout,
+                requests,
+            },
+        )?
+        // tasks are driven below, on the main loop instead of a dedicated thread
+        .internal_executor(false)
+        .build()
+        .await?;
+
+    info!("D-Bus service running as {}", COMPOSITOR_BUS_NAME);
+
+    // the stream ends once the bus connection is gone
+    let mut messages = zbus::MessageStream::from(&connection);
+    connection
+        .executor()
+        .run(async { while messages.next().await.is_some() {} })
+        .await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixStream, time::Instant};
+
+    use futures_lite::future;
+    use smithay::{
+        reexports::{
+            calloop::EventLoop,
+            wayland_server::{
+                protocol::{wl_compositor::WlCompositor, wl_seat::WlSeat},
+                Resource,
+            },
+        },
+        utils::Transform,
+    };
+    use zbus::Guid;
+
+    use super::*;
+    use crate::{
+        input::layout::ActiveLayout,
+        utils::testing,
+        wayland::{diagnostics::ClientDiagnostics, globals::GlobalEntry},
+    };
+
+    const PORTAL_INTERFACE: &str = "org.freedesktop.impl.portal.Settings";
+    const COMPOSITOR_INTERFACE: &str = "org.swl.Compositor1";
+
+    /// Service over a private peer-to-peer connection, no session bus involved
+    struct Service {
+        _server: zbus::Connection,
+        client: zbus::Connection,
+        requests: EventLoop<'static, Vec<Request>>,
+    }
+
+    impl Service {
+        fn new(shell: Shell, globals: Globals) -> Self {
+            let appearance = Appearance {
+                color_scheme: 1,
+                accent_color: parse_hex_color("#3584e4"),
+            };
+            let requests = EventLoop::<Vec<Request>>::try_new().unwrap();
+            let (sender, receiver) = channel::channel::<Request>();
+            requests
+                .handle()
+                .insert_source(receiver, |event, _, received| {
+                    if let channel::Event::Msg(request) = event {
+                        received.push(request);
+                    }
+                })
+                .unwrap();
+            let control = CompositorControl {
+                shell: Arc::new(RwLock::new(shell)),
+                globals,
+                client_diagnostics: Arc::new(std::sync::Mutex::new(ClientDiagnostics::new())),
+                keyboard_layout: Arc::new(std::sync::Mutex::new(ActiveLayout {
+                    index: 1,
+                    name: "German".to_string(),
+                })),
+                requests: sender,
+            };
+
+            let (server, client) = UnixStream::pair().unwrap();
+            let server = connection::Builder::unix_stream(server)
+                .server(Guid::generate())
+                .unwrap()
+                .p2p()
+                .serve_at(PORTAL_PATH, PortalSettings { appearance })
+                .unwrap()
+                .serve_at(COMPOSITOR_PATH, control)
+                .unwrap()
+                .build();
+            let client = connection::Builder::unix_stream(client).p2p().build();
+            let (server, client) = future::block_on(future::zip(server, client));
+
+            Self {
+                _server: server.unwrap(),
+                client: client.unwrap(),
+                requests,
+            }
+        }
+
+        /// Requests forwarded to the main loop so far
+        fn received(&mut self) -> Vec<Request> {
+            let mut received = Vec::new();
+            self.requests
+                .dispatch(Some(Duration::ZERO), &mut received)
+                .unwrap();
+            received
+        }
+    }
+
+    /// Call a method of the service, a macro as bodies of any signature are accepted
+    macro_rules! call {
+        ($service:expr, $path:expr, $interface:expr, $method:expr, $body:expr) => {
+            future::block_on($service.client.call_method(
+                None::<&str>,
+                $path,
+                Some($interface),
+                $method,
+                $body,
+            ))
+        };
+    }
+
+    macro_rules! compositor {
+        ($service:expr, $method:expr, $body:expr) => {
+            call!(
+                $service,
+                COMPOSITOR_PATH,
+                COMPOSITOR_INTERFACE,
+                $method,
+                $body
+            )
+        };
+    }
+
+    fn is_invalid_args(err: &zbus::Error) -> bool {
+        matches!(err, zbus::Error::MethodError(name, _, _)
+            if name.as_str() == "org.freedesktop.DBus.Error.InvalidArgs")
+    }
+
+    #[test]
+    fn portal_reads_the_appearance() {
+        let service = Service::new(Shell::new(), Globals::new());
+
+        let reply = call!(
+            service,
+            PORTAL_PATH,
+            PORTAL_INTERFACE,
+            "Read",
+            &(APPEARANCE_NAMESPACE, "color-scheme")
+        )
+        .unwrap();
+        let value: OwnedValue = reply.body().deserialize().unwrap();
+        assert_eq!(u32::try_from(value).unwrap(), 1);
+
+        let reply = call!(
+            service,
+            PORTAL_PATH,
+            PORTAL_INTERFACE,
+            "ReadAll",
+            &(vec!["org.freedesktop.*"],)
+        )
+        .unwrap();
+        let settings: HashMap<String, HashMap<String, OwnedValue>> =
+            reply.body().deserialize().unwrap();
+        let appearance = &settings[APPEARANCE_NAMESPACE];
+        assert!(appearance.contains_key("color-scheme"));
+        assert!(appearance.contains_key("accent-color"));
+
+        let err = call!(
+            service,
+            PORTAL_PATH,
+            PORTAL_INTERFACE,
+            "Read",
+            &(APPEARANCE_NAMESPACE, "contrast")
+        )
+        .unwrap_err();
+        assert!(matches!(err, zbus::Error::MethodError(..)));
+    }
+
+    #[test]
+    fn switch_workspace_is_forwarded_to_the_loop() {
+        let mut service = Service::new(Shell::new(), Globals::new());
+
+        compositor!(service, "SwitchWorkspace", &("3",)).unwrap();
+        let received = service.received();
+        assert!(matches!(
+            received.as_slice(),
+            [Request::Action(Action::SwitchToWorkspace(name))] if name == "3"
+        ));
+    }
+
+    #[test]
+    fn invalid_requests_are_rejected_before_the_loop() {
+        let mut service = Service::new(Shell::new(), Globals::new());
+
+        let err = compositor!(service, "SwitchWorkspace", &("tags:0",)).unwrap_err();
+        assert!(is_invalid_args(&err));
+        let err = compositor!(service, "SetForceOffscreen", &("DP-9", true)).unwrap_err();
+        assert!(is_invalid_args(&err));
+        assert!(service.received().is_empty());
+    }
+
+    #[test]
+    fn list_globals_round_trip() {
+        let globals = Globals::new();
+        for (interface, version) in [(WlSeat::interface(), 9), (WlCompositor::interface(), 6)] {
+            globals.insert(GlobalEntry {
+                interface: interface.name,
+                version,
+                privileged: false,
+            });
+        }
+        let service = Service::new(Shell::new(), globals);
+
+        let reply = compositor!(service, "ListGlobals", &()).unwrap();
+        let globals: Vec<(String, u32, bool)> = reply.body().deserialize().unwrap();
+        assert_eq!(
+            globals,
+            vec![
+                ("wl_compositor".to_string(), 6, false),
+                ("wl_seat".to_string(), 9, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_path_is_reported_per_output() {
+        let mut shell = Shell::new();
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        shell.space.map_output(&output, (0, 0));
+        let mut path = render_path::RenderPath::new(true);
+        path.direct_failed(true);
+        path.select(true);
+        render_path::publish_render_path(&output, path);
+        let service = Service::new(shell, Globals::new());
+
+        let reply = compositor!(service, "OutputRenderPath", &()).unwrap();
+        let paths: Vec<(String, bool, bool, u32)> = reply.body().deserialize().unwrap();
+        assert_eq!(
+            paths,
+            vec![(
+                "DP-1".to_string(),
+                false,
+                true,
+                render_path::OFFSCREEN_FALLBACK_FRAMES - 1
+            )]
+        );
+    }
+
+    #[test]
+    fn skipped_frames_are_reported_per_output() {
+        let mut shell = Shell::new();
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        shell.space.map_output(&output, (0, 0));
+        fingerprint::publish_skipped_frames(&output, 42);
+        let service = Service::new(shell, Globals::new());
+
+        let reply = compositor!(service, "OutputSkippedFrames", &()).unwrap();
+        let skipped: Vec<(String, u64)> = reply.body().deserialize().unwrap();
+        assert_eq!(skipped, vec![("DP-1".to_string(), 42)]);
+    }
+
+    #[test]
+    fn keyboard_layout_is_reported() {
+        let service = Service::new(Shell::new(), Globals::new());
+
+        let reply = compositor!(service, "KeyboardLayout", &()).unwrap();
+        let layout: (u32, String) = reply.body().deserialize().unwrap();
+        assert_eq!(layout, (1, "German".to_string()));
+    }
+
+    #[test]
+    fn frame_stats_queries_fit_in_a_frame() {
+        let mut shell = Shell::new();
+        for (name, location) in [("DP-1", (0, 0)), ("HDMI-A-1", (1920, 0))] {
+            let output = testing::output(name, (1920, 1080), Transform::Normal, 1.0, location);
+            shell.space.map_output(&output, location);
+        }
+        let service = Service::new(shell, Globals::new());
+
+        // the compositor handles queries on its main loop, the round trip of a query
+        // bounds how long it holds up rendering
+        let mut latencies = Vec::new();
+        for _ in 0..1000 {
+            let start = Instant::now();
+            let reply = compositor!(service, "OutputFrameStats", &()).unwrap();
+            latencies.push(start.elapsed());
+            let mut stats: Vec<(String, u32, u32, u32)> = reply.body().deserialize().unwrap();
+            stats.sort();
+            assert_eq!(
+                stats,
+                vec![
+                    ("DP-1".to_string(), 0, 0, 0),
+                    ("HDMI-A-1".to_string(), 0, 0, 0),
+                ]
+            );
+
+            let start = Instant::now();
+            let reply = compositor!(service, "WindowFrameStats", &()).unwrap();
+            latencies.push(start.elapsed());
+            let windows: Vec<(String, String, u32, u32)> = reply.body().deserialize().unwrap();
+            assert!(windows.is_empty());
+        }
+
+        // a frame lasts 16.7ms at 60Hz, a query takes a small part of it
+        latencies.sort();
+        let p99 = latencies[latencies.len() * 99 / 100];
+        assert!(p99 < Duration::from_millis(2), "99th percentile: {:?}", p99);
+    }
+}
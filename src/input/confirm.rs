@@ -0,0 +1,323 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Confirmation of destructive keybinding actions.
+//!
+//! Bindings of quit and close-workspace, and of the actions SWL_CONFIRM adds,
+//! only run when pressed twice within a few seconds. The first press arms a
+//! pending slot for that binding with a timer and puts up an OSD prompt,
+//! pressing any other key cancels it. SWL_CONFIRM can also opt bindings out.
+
+use std::time::{Duration, Instant};
+
+use smithay::reexports::calloop::{
+    timer::{TimeoutAction, Timer},
+    RegistrationToken,
+};
+use tracing::{info, warn};
+
+use super::keybindings::{Action, BindingId, Keybinding};
+use crate::State;
+
+/// Time to press a binding again to confirm it
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Actions requiring confirmation unless SWL_CONFIRM opts them out
+const CONFIRMED_BY_DEFAULT: [Action; 2] = [Action::Quit, Action::CloseWorkspaceWindows];
+
+/// A binding waiting for its second press
+#[derive(Debug)]
+pub struct PendingConfirmation {
+    binding: BindingId,
+    action: Action,
+    armed_at: Instant,
+    timer: RegistrationToken,
+}
+
+/// Whether a press of a binding requiring confirmation confirms the pending one
+///
+/// Only the same binding pressed again before the timeout does, the timer dropping
+/// expired confirmations may not have run yet.
+fn confirms(pending: Option<(BindingId, Instant)>, binding: BindingId, now: Instant) -> bool {
+    pending.is_some_and(|(pending, armed_at)| {
+        pending == binding && now.saturating_duration_since(armed_at) < CONFIRM_TIMEOUT
+    })
+}
+
+/// Actions requiring confirmation, quit and close-workspace unless SWL_CONFIRM changes it
+pub fn confirmed_actions() -> Vec<Action> {
+    parse_confirmed_actions(std::env::var("SWL_CONFIRM").ok().as_deref())
+}
+
+/// A comma separated list of "quit", "close" and "close-workspace" to confirm, each
+/// followed by "=false" to skip confirming it instead, "none" turns confirmation off
+fn parse_confirmed_actions(config: Option<&str>) -> Vec<Action> {
+    let mut actions = CONFIRMED_BY_DEFAULT.to_vec();
+    let entries = config
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty());
+
+    for entry in entries {
+        if entry == "none" {
+            actions.clear();
+            continue;
+        }
+        let (name, confirm) = match entry.split_once('=') {
+            Some((name, value)) => (name.trim(), !matches!(value.trim(), "false" | "0" | "off")),
+            None => (entry, true),
+        };
+        let action = match name {
+            "quit" => Action::Quit,
+            "close" => Action::CloseWindow,
+            "close-workspace" => Action::CloseWorkspaceWindows,
+            _ => {
+                warn!("Ignoring unknown action in SWL_CONFIRM: {}", name);
+                continue;
+            }
+        };
+        actions.retain(|confirmed| *confirmed != action);
+        if confirm {
+            actions.push(action);
+        }
+    }
+    actions
+}
+
+/// Prompt asking for the second press of a binding
+fn prompt(binding: &Keybinding) -> String {
+    let modifiers = &binding.modifiers;
+    let mut keys: Vec<String> = [
+        (modifiers.logo, "super"),
+        (modifiers.ctrl, "ctrl"),
+        (modifiers.alt, "alt"),
+        (modifiers.shift, "shift"),
+    ]
+    .into_iter()
+    .filter(|(held, _)| *held)
+    .map(|(_, name)| name.to_string())
+    .collect();
+    keys.push(match char::from_u32(binding.key) {
+        Some(key) if key.is_ascii_graphic() => key.to_string(),
+        _ => format!("{:#x}", binding.key),
+    });
+
+    let action = match &binding.action {
+        Action::Quit => "quit".to_string(),
+        Action::CloseWindow => "close the window".to_string(),
+        Action::CloseWorkspaceWindows => "close every window of the workspace".to_string(),
+        action => format!("{:?}", action),
+    };
+
+    format!(
+        "Press {} again within {}s to {}",
+        keys.join("+"),
+        CONFIRM_TIMEOUT.as_secs(),
+        action
+    )
+}
+
+impl State {
+    /// Run the action of a pressed binding, once confirmed if it requires confirmation
+    pub(super) fn run_binding(&mut self, binding: BindingId, action: Action, now: Instant) {
+        if self.confirm_binding(binding, &action, now) {
+            self.handle_action(action);
+        }
+    }
+
+    /// Whether the action of a pressed binding may run now
+    ///
+    /// The first press of a binding requiring confirmation only arms it, the second
+    /// one within the timeout lets it through.
+    fn confirm_binding(&mut self, binding: BindingId, action: &Action, now: Instant) -> bool {
+        if !self.keybindings.requires_confirmation(binding) {
+            self.cancel_confirmation();
+            return true;
+        }
+
+        let pending = self
+            .pending_confirmation
+            .as_ref()
+            .map(|pending| (pending.binding, pending.armed_at));
+        if confirms(pending, binding, now) {
+            if let Some(pending) = self.pending_confirmation.take() {
+                self.loop_handle.remove(pending.timer);
+            }
+            self.hide_confirmation_prompt();
+            return true;
+        }
+        self.cancel_confirmation();
+
+        let timer = Timer::from_duration(CONFIRM_TIMEOUT);
+        let timer = self.loop_handle.insert_source(timer, |_, _, state| {
+            if state.pending_confirmation.take().is_some() {
+                info!("Confirmation timed out");
+                state.hide_confirmation_prompt();
+            }
+            TimeoutAction::Drop
+        });
+        match timer {
+            Ok(timer) => {
+                if let Some(keybinding) = self.keybindings.get(binding) {
+                    let prompt = prompt(keybinding);
+                    info!("{}", prompt);
+                    self.shell
+                        .write()
+                        .unwrap()
+                        .osd
+                        .show(prompt, CONFIRM_TIMEOUT.as_millis() as u64);
+                    for output in &self.outputs {
+                        self.backend.schedule_render(output);
+                    }
+                }
+                self.pending_confirmation = Some(PendingConfirmation {
+                    binding,
+                    action: action.clone(),
+                    armed_at: now,
+                    timer,
+                });
+                false
+            }
+            // without a timer the confirmation could never expire, don't block the action
+            Err(err) => {
+                warn!("Failed to arm confirmation timer: {}", err);
+                true
+            }
+        }
+    }
+
+    /// Drop a pending confirmation, e.g. because another key got pressed
+    pub(super) fn cancel_confirmation(&mut self) {
+        if let Some(pending) = self.pending_confirmation.take() {
+            self.loop_handle.remove(pending.timer);
+            info!("Cancelled confirmation of {:?}", pending.action);
+            self.hide_confirmation_prompt();
+        }
+    }
+
+    fn hide_confirmation_prompt(&mut self) {
+        if self.shell.write().unwrap().osd.hide() {
+            for output in &self.outputs {
+                self.backend.schedule_render(output);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::{
+        backend::input::KeyState,
+        input::keyboard::{keysyms as xkb, Keysym, ModifiersState},
+    };
+
+    use super::*;
+    use crate::utils::testing;
+
+    const QUIT: BindingId = BindingId(0);
+    const CLOSE_WORKSPACE: BindingId = BindingId(1);
+
+    #[test]
+    fn first_press_only_arms() {
+        assert!(!confirms(None, QUIT, Instant::now()));
+    }
+
+    #[test]
+    fn second_press_within_the_timeout_confirms() {
+        let armed_at = Instant::now();
+        let now = armed_at + Duration::from_secs(1);
+        assert!(confirms(Some((QUIT, armed_at)), QUIT, now));
+    }
+
+    #[test]
+    fn second_press_after_the_timeout_arms_again() {
+        let armed_at = Instant::now();
+        let now = armed_at + CONFIRM_TIMEOUT;
+        assert!(!confirms(Some((QUIT, armed_at)), QUIT, now));
+    }
+
+    #[test]
+    fn other_binding_does_not_confirm() {
+        let armed_at = Instant::now();
+        assert!(!confirms(Some((QUIT, armed_at)), CLOSE_WORKSPACE, armed_at));
+    }
+
+    #[test]
+    fn quit_and_close_workspace_are_confirmed_by_default() {
+        assert_eq!(parse_confirmed_actions(None), CONFIRMED_BY_DEFAULT);
+        assert_eq!(parse_confirmed_actions(Some("")), CONFIRMED_BY_DEFAULT);
+    }
+
+    #[test]
+    fn bindings_opt_in_and_out() {
+        assert!(parse_confirmed_actions(Some("none")).is_empty());
+        assert_eq!(
+            parse_confirmed_actions(Some("quit=false, close,bogus")),
+            [Action::CloseWorkspaceWindows, Action::CloseWindow]
+        );
+        assert_eq!(
+            parse_confirmed_actions(Some("none,close-workspace=true")),
+            [Action::CloseWorkspaceWindows]
+        );
+    }
+
+    #[test]
+    fn prompt_names_the_binding_and_action() {
+        let binding = Keybinding::new(
+            ModifiersState {
+                logo: true,
+                shift: true,
+                ..Default::default()
+            },
+            xkb::KEY_e,
+            Action::Quit,
+        );
+        assert_eq!(
+            prompt(&binding),
+            "Press super+shift+e again within 3s to quit"
+        );
+    }
+
+    /// The quit binding of a fresh state, confirmed by default
+    fn quit_binding(state: &State) -> BindingId {
+        let modifiers = ModifiersState {
+            logo: true,
+            shift: true,
+            ..Default::default()
+        };
+        let (binding, action) = state
+            .keybindings
+            .check(&modifiers, Keysym::from(xkb::KEY_e), KeyState::Pressed)
+            .unwrap();
+        assert_eq!(action, Action::Quit);
+        binding
+    }
+
+    #[test]
+    fn quit_runs_on_a_second_press_within_the_timeout() {
+        let (_event_loop, _display, mut state) = testing::state();
+        let binding = quit_binding(&state);
+        let now = Instant::now();
+
+        state.run_binding(binding, Action::Quit, now);
+        assert!(!state.should_stop);
+        assert!(state.shell.read().unwrap().osd.prompt().is_some());
+
+        state.run_binding(binding, Action::Quit, now + Duration::from_secs(1));
+        assert!(state.should_stop);
+        assert!(state.shell.read().unwrap().osd.prompt().is_none());
+    }
+
+    #[test]
+    fn quit_does_not_run_on_a_second_press_after_the_timeout() {
+        let (_event_loop, _display, mut state) = testing::state();
+        let binding = quit_binding(&state);
+        let now = Instant::now();
+
+        state.run_binding(binding, Action::Quit, now);
+        state.run_binding(binding, Action::Quit, now + CONFIRM_TIMEOUT);
+        assert!(!state.should_stop);
+        // the late press armed a new confirmation
+        assert!(state.pending_confirmation.is_some());
+    }
+}
@@ -0,0 +1,536 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Interactive move and resize of floating windows.
+//!
+//! Started by xdg_toplevel move/resize requests while a button is held on the
+//! window. The pointer focus is cleared for the duration of the grab and a
+//! compositor cursor shown on top of the client one, when the last button is
+//! released the surface under the pointer gets its own cursor back. Tiled
+//! windows get their geometry from the layout and ignore the requests.
+
+use smithay::{
+    desktop::Window,
+    input::{
+        pointer::{
+            AxisFrame, ButtonEvent, CursorIcon, CursorImageStatus, Focus, GestureHoldBeginEvent,
+            GestureHoldEndEvent, GesturePinchBeginEvent, GesturePinchEndEvent,
+            GesturePinchUpdateEvent, GestureSwipeBeginEvent, GestureSwipeEndEvent,
+            GestureSwipeUpdateEvent, GrabStartData, MotionEvent, PointerGrab, PointerInnerHandle,
+            RelativeMotionEvent,
+        },
+        Seat,
+    },
+    reexports::{
+        wayland_protocols::xdg::shell::server::xdg_toplevel::{self, ResizeEdge},
+        wayland_server::{protocol::wl_surface::WlSurface, Resource},
+    },
+    utils::{Logical, Point, Rectangle, Serial, Size},
+    wayland::shell::xdg::ToplevelSurface,
+};
+use tracing::debug;
+
+use crate::State;
+
+/// Cursor shown while resizing from an edge
+fn resize_cursor(edges: ResizeEdge) -> CursorIcon {
+    match edges {
+        ResizeEdge::Top => CursorIcon::NResize,
+        ResizeEdge::Bottom => CursorIcon::SResize,
+        ResizeEdge::Left => CursorIcon::WResize,
+        ResizeEdge::Right => CursorIcon::EResize,
+        ResizeEdge::TopLeft => CursorIcon::NwResize,
+        ResizeEdge::TopRight => CursorIcon::NeResize,
+        ResizeEdge::BottomLeft => CursorIcon::SwResize,
+        ResizeEdge::BottomRight => CursorIcon::SeResize,
+        _ => CursorIcon::Move,
+    }
+}
+
+/// Geometry of a window resized from `edges` by the pointer moving `delta`
+///
+/// The opposite edges stay in place, the size doesn't drop below 1x1.
+fn resize_geometry(
+    initial: Rectangle<i32, Logical>,
+    edges: ResizeEdge,
+    delta: Point<i32, Logical>,
+) -> Rectangle<i32, Logical> {
+    let (left, right, top, bottom) = match edges {
+        ResizeEdge::Top => (false, false, true, false),
+        ResizeEdge::Bottom => (false, false, false, true),
+        ResizeEdge::Left => (true, false, false, false),
+        ResizeEdge::Right => (false, true, false, false),
+        ResizeEdge::TopLeft => (true, false, true, false),
+        ResizeEdge::TopRight => (false, true, true, false),
+        ResizeEdge::BottomLeft => (true, false, false, true),
+        ResizeEdge::BottomRight => (false, true, false, true),
+        _ => (false, false, false, false),
+    };
+
+    let mut size = initial.size;
+    if left {
+        size.w -= delta.x;
+    } else if right {
+        size.w += delta.x;
+    }
+    if top {
+        size.h -= delta.y;
+    } else if bottom {
+        size.h += delta.y;
+    }
+    let size = Size::from((size.w.max(1), size.h.max(1)));
+
+    // moving the left or top edge moves the window, the opposite edge stays put
+    let mut location = initial.loc;
+    if left {
+        location.x += initial.size.w - size.w;
+    }
+    if top {
+        location.y += initial.size.h - size.h;
+    }
+    Rectangle::new(location, size)
+}
+
+/// Moves a floating window with the pointer
+pub struct MoveGrab {
+    start_data: GrabStartData<State>,
+    window: Window,
+    initial_location: Point<i32, Logical>,
+}
+
+/// Resizes a floating window with the pointer
+pub struct ResizeGrab {
+    start_data: GrabStartData<State>,
+    window: Window,
+    edges: ResizeEdge,
+    /// window location and geometry size when the grab started
+    initial: Rectangle<i32, Logical>,
+}
+
+impl State {
+    /// Start moving a floating window on an xdg_toplevel.move request
+    pub fn start_move_grab(
+        &mut self,
+        surface: &ToplevelSurface,
+        seat: &Seat<Self>,
+        serial: Serial,
+    ) {
+        let Some((start_data, window, location)) = self.grab_target(surface, seat, serial) else {
+            return;
+        };
+
+        debug!("Starting interactive move");
+        let grab = MoveGrab {
+            start_data,
+            window,
+            initial_location: location,
+        };
+        self.set_cursor_override(CursorImageStatus::Named(CursorIcon::Grabbing));
+        seat.get_pointer()
+            .unwrap()
+            .set_grab(self, grab, serial, Focus::Clear);
+    }
+
+    /// Start resizing a floating window on an xdg_toplevel.resize request
+    pub fn start_resize_grab(
+        &mut self,
+        surface: &ToplevelSurface,
+        seat: &Seat<Self>,
+        serial: Serial,
+        edges: ResizeEdge,
+    ) {
+        let Some((start_data, window, location)) = self.grab_target(surface, seat, serial) else {
+            return;
+        };
+
+        debug!("Starting interactive resize from {:?}", edges);
+        surface.with_pending_state(|state| {
+            state.states.set(xdg_toplevel::State::Resizing);
+        });
+        surface.send_pending_configure();
+
+        let grab = ResizeGrab {
+            start_data,
+            edges,
+            initial: Rectangle::new(location, window.geometry().size),
+            window,
+        };
+        self.set_cursor_override(CursorImageStatus::Named(resize_cursor(edges)));
+        seat.get_pointer()
+            .unwrap()
+            .set_grab(self, grab, serial, Focus::Clear);
+    }
+
+    /// Grab start data, window and location for a move or resize request, if it may start
+    ///
+    /// The request has to come with the serial of a button still held on the window,
+    /// and only floating windows follow the pointer.
+    fn grab_target(
+        &self,
+        surface: &ToplevelSurface,
+        seat: &Seat<Self>,
+        serial: Serial,
+    ) -> Option<(GrabStartData<Self>, Window, Point<i32, Logical>)> {
+        let pointer = seat.get_pointer()?;
+        if !pointer.has_grab(serial) {
+            return None;
+        }
+        let start_data = pointer.grab_start_data()?;
+        let same_client = start_data
+            .focus
+            .as_ref()
+            .is_some_and(|(focus, _)| focus.id().same_client_as(&surface.wl_surface().id()));
+        if !same_client {
+            return None;
+        }
+
+        let shell = self.shell.read().unwrap();
+        let window = shell
+            .space
+            .elements()
+            .find(|window| window.toplevel() == Some(surface))
+            .cloned()?;
+        if !shell.is_floating(&window) {
+            debug!("Ignoring interactive move/resize of a tiled window");
+            return None;
+        }
+        let location = shell.space.element_location(&window)?;
+        Some((start_data, window, location))
+    }
+
+    fn set_cursor_override(&mut self, image: CursorImageStatus) {
+        self.shell
+            .write()
+            .unwrap()
+            .cursor_images
+            .set_override(image);
+        self.show_cursor_image();
+    }
+
+    /// End the compositor cursor, restoring the one of the surface getting pointer focus back
+    fn clear_cursor_override(&mut self) {
+        let focus: Option<WlSurface> = self
+            .pointer_focus
+            .as_ref()
+            .map(|(surface, _)| surface.clone());
+        self.shell
+            .write()
+            .unwrap()
+            .cursor_images
+            .clear_override(focus.as_ref());
+        self.show_cursor_image();
+    }
+
+    /// Schedule a render of every output a grabbed window may be on
+    fn schedule_render_all(&mut self) {
+        for output in self.outputs.clone() {
+            self.backend.schedule_render(&output);
+        }
+    }
+}
+
+/// Pointer events not handled by the grabs themselves, forwarded without focus
+macro_rules! forward_pointer_events {
+    () => {
+        fn relative_motion(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            _focus: Option<(WlSurface, Point<f64, Logical>)>,
+            event: &RelativeMotionEvent,
+        ) {
+            handle.relative_motion(data, None, event);
+        }
+
+        fn button(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            event: &ButtonEvent,
+        ) {
+            handle.button(data, event);
+            if handle.current_pressed().is_empty() {
+                handle.unset_grab(self, data, event.serial, event.time, true);
+            }
+        }
+
+        fn axis(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            details: AxisFrame,
+        ) {
+            handle.axis(data, details);
+        }
+
+        fn frame(&mut self, data: &mut State, handle: &mut PointerInnerHandle<'_, State>) {
+            handle.frame(data);
+        }
+
+        fn gesture_swipe_begin(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            event: &GestureSwipeBeginEvent,
+        ) {
+            handle.gesture_swipe_begin(data, event);
+        }
+
+        fn gesture_swipe_update(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            event: &GestureSwipeUpdateEvent,
+        ) {
+            handle.gesture_swipe_update(data, event);
+        }
+
+        fn gesture_swipe_end(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            event: &GestureSwipeEndEvent,
+        ) {
+            handle.gesture_swipe_end(data, event);
+        }
+
+        fn gesture_pinch_begin(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            event: &GesturePinchBeginEvent,
+        ) {
+            handle.gesture_pinch_begin(data, event);
+        }
+
+        fn gesture_pinch_update(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            event: &GesturePinchUpdateEvent,
+        ) {
+            handle.gesture_pinch_update(data, event);
+        }
+
+        fn gesture_pinch_end(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            event: &GesturePinchEndEvent,
+        ) {
+            handle.gesture_pinch_end(data, event);
+        }
+
+        fn gesture_hold_begin(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            event: &GestureHoldBeginEvent,
+        ) {
+            handle.gesture_hold_begin(data, event);
+        }
+
+        fn gesture_hold_end(
+            &mut self,
+            data: &mut State,
+            handle: &mut PointerInnerHandle<'_, State>,
+            event: &GestureHoldEndEvent,
+        ) {
+            handle.gesture_hold_end(data, event);
+        }
+
+        fn start_data(&self) -> &GrabStartData<State> {
+            &self.start_data
+        }
+    };
+}
+
+impl PointerGrab<State> for MoveGrab {
+    fn motion(
+        &mut self,
+        data: &mut State,
+        handle: &mut PointerInnerHandle<'_, State>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // no client gets pointer events while the window moves
+        handle.motion(data, None, event);
+
+        let delta = event.location - self.start_data.location;
+        let location = self.initial_location.to_f64() + delta;
+        data.shell.write().unwrap().space.map_element(
+            self.window.clone(),
+            location.to_i32_round(),
+            true,
+        );
+        data.schedule_render_all();
+    }
+
+    forward_pointer_events!();
+
+    fn unset(&mut self, data: &mut State) {
+        debug!("Interactive move ended");
+        data.clear_cursor_override();
+    }
+}
+
+impl PointerGrab<State> for ResizeGrab {
+    fn motion(
+        &mut self,
+        data: &mut State,
+        handle: &mut PointerInnerHandle<'_, State>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // no client gets pointer events while the window resizes
+        handle.motion(data, None, event);
+
+        let delta = (event.location - self.start_data.location).to_i32_round();
+        let geometry = resize_geometry(self.initial, self.edges, delta);
+        if let Some(toplevel) = self.window.toplevel() {
+            toplevel.with_pending_state(|state| {
+                state.size = Some(geometry.size);
+            });
+            toplevel.send_pending_configure();
+        }
+        // keeps the opposite edge in place once the client resized to the requested size
+        if geometry.loc != self.initial.loc {
+            data.shell
+                .write()
+                .unwrap()
+                .space
+                .map_element(self.window.clone(), geometry.loc, true);
+        }
+        data.schedule_render_all();
+    }
+
+    forward_pointer_events!();
+
+    fn unset(&mut self, data: &mut State) {
+        debug!("Interactive resize ended");
+        if let Some(toplevel) = self.window.toplevel() {
+            toplevel.with_pending_state(|state| {
+                state.states.unset(xdg_toplevel::State::Resizing);
+            });
+            toplevel.send_pending_configure();
+        }
+        data.clear_cursor_override();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::{Transform, SERIAL_COUNTER};
+
+    use super::*;
+    use crate::utils::{test_client::Harness, testing};
+
+    fn initial() -> Rectangle<i32, Logical> {
+        Rectangle::new((100, 100).into(), (400, 300).into())
+    }
+
+    #[test]
+    fn bottom_right_resize_keeps_the_location() {
+        let geometry = resize_geometry(initial(), ResizeEdge::BottomRight, (50, -20).into());
+        assert_eq!(
+            geometry,
+            Rectangle::new((100, 100).into(), (450, 280).into())
+        );
+    }
+
+    #[test]
+    fn top_left_resize_keeps_the_opposite_corner() {
+        let geometry = resize_geometry(initial(), ResizeEdge::TopLeft, (50, -20).into());
+        assert_eq!(
+            geometry,
+            Rectangle::new((150, 80).into(), (350, 320).into())
+        );
+        assert_eq!(
+            geometry.loc + geometry.size.to_point(),
+            initial().loc + initial().size.to_point()
+        );
+    }
+
+    #[test]
+    fn single_edge_resize_keeps_the_other_axis() {
+        let geometry = resize_geometry(initial(), ResizeEdge::Left, (30, 200).into());
+        assert_eq!(
+            geometry,
+            Rectangle::new((130, 100).into(), (370, 300).into())
+        );
+        let geometry = resize_geometry(initial(), ResizeEdge::Bottom, (30, 200).into());
+        assert_eq!(
+            geometry,
+            Rectangle::new((100, 100).into(), (400, 500).into())
+        );
+    }
+
+    #[test]
+    fn resize_does_not_collapse_the_window() {
+        let geometry = resize_geometry(initial(), ResizeEdge::TopLeft, (1000, 1000).into());
+        assert_eq!(geometry.size, Size::from((1, 1)));
+        assert_eq!(geometry.loc, (499, 399).into());
+    }
+
+    #[test]
+    fn resize_cursor_follows_the_edges() {
+        assert_eq!(resize_cursor(ResizeEdge::Top), CursorIcon::NResize);
+        assert_eq!(resize_cursor(ResizeEdge::BottomRight), CursorIcon::SeResize);
+        assert_eq!(resize_cursor(ResizeEdge::TopLeft), CursorIcon::NwResize);
+    }
+
+    #[test]
+    fn pointer_focus_is_not_refreshed_during_a_grab() {
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let mut harness = Harness::new(&[output], None);
+        let revealed = harness.map_window();
+        let closed = harness.map_window();
+        harness.arrange();
+
+        let window = harness.window(closed);
+        let geometry = harness
+            .state
+            .shell
+            .read()
+            .unwrap()
+            .space
+            .element_geometry(&window)
+            .unwrap();
+        let location = geometry.loc.to_f64() + geometry.size.to_f64().downscale(2.0).to_point();
+        harness.move_pointer(location);
+        harness.take_pointer_enters();
+
+        let pointer = harness.state.seat.get_pointer().unwrap();
+        let grab = MoveGrab {
+            start_data: GrabStartData {
+                focus: None,
+                button: 0x110,
+                location,
+            },
+            window,
+            initial_location: geometry.loc,
+        };
+        pointer.set_grab(
+            &mut harness.state,
+            grab,
+            SERIAL_COUNTER.next_serial(),
+            Focus::Clear,
+        );
+
+        // the grab owns the focus, the window revealed under it has to wait
+        harness.close_window(closed);
+        harness.arrange();
+        harness.state.refresh_pointer_focus();
+        harness.roundtrip();
+        assert!(harness.take_pointer_enters().is_empty());
+
+        pointer.unset_grab(
+            &mut harness.state,
+            SERIAL_COUNTER.next_serial(),
+            State::synthetic_event_time(),
+            false,
+        );
+        harness.state.refresh_pointer_focus();
+        harness.roundtrip();
+        assert_eq!(harness.take_pointer_enters(), [revealed]);
+    }
+}
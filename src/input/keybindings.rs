@@ -6,6 +6,8 @@ use smithay::{
 };
 use tracing::debug;
 
+use crate::shell::virtual_output::Direction;
+
 /// Actions that can be triggered by keybindings
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Action {
@@ -14,6 +16,7 @@ pub enum Action {
     FocusPrev,
     Zoom,
     CloseWindow,
+    CloseWorkspaceWindows,
     ToggleFloating,
     Fullscreen,
 
@@ -22,6 +25,7 @@ pub enum Action {
     DecreaseMasterWidth,
     IncreaseMasterCount,
     DecreaseMasterCount,
+    ToggleOrientation,
 
     // tabbed mode
     ToggleLayoutMode,
@@ -36,17 +40,37 @@ pub enum Action {
     SwitchToWorkspace(String),
     MoveToWorkspace(String),
 
+    // outputs
+    MoveWindowToOutputAndFollow(Direction),
+
+    // tags
+    ToggleTagView,
+    ViewTag(u32),
+    ToggleViewTag(u32),
+    TagWindow(u32),
+    ToggleWindowTag(u32),
+
+    // keyboard
+    SwitchKeyboardLayout,
+
     // system
+    LogDiagnostics,
     Quit,
     VtSwitch(i32),
 }
 
+/// Index of a keybinding, tells apart bindings sharing an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindingId(pub usize);
+
 /// A keybinding definition
 #[derive(Debug, Clone)]
 pub struct Keybinding {
     pub modifiers: ModifiersState,
     pub key: u32,
     pub action: Action,
+    /// only runs when pressed twice, quit and close-workspace by default, see SWL_CONFIRM
+    pub confirm: bool,
 }
 
 impl Keybinding {
@@ -56,6 +80,7 @@ impl Keybinding {
             modifiers,
             key,
             action,
+            confirm: false,
         }
     }
 
@@ -89,6 +114,15 @@ impl Keybindings {
         bindings.push(Keybinding::new(modkey, xkb::KEY_m, Action::Zoom));
         // close window
         bindings.push(Keybinding::new(modkey, xkb::KEY_q, Action::CloseWindow));
+        // close every window of the workspace - Super+Shift+q
+        bindings.push(Keybinding::new(
+            ModifiersState {
+                shift: true,
+                ..modkey
+            },
+            xkb::KEY_q,
+            Action::CloseWorkspaceWindows,
+        ));
         bindings.push(Keybinding::new(
             ModifiersState {
                 shift: true,
@@ -120,6 +154,11 @@ impl Keybindings {
             xkb::KEY_comma,
             Action::DecreaseMasterCount,
         ));
+        bindings.push(Keybinding::new(
+            modkey,
+            xkb::KEY_o,
+            Action::ToggleOrientation,
+        ));
 
         // tabbed mode
         bindings.push(Keybinding::new(
@@ -145,6 +184,16 @@ impl Keybindings {
         ));
         bindings.push(Keybinding::new(modkey, xkb::KEY_d, Action::LaunchMenu));
 
+        // keyboard layout - Super+Ctrl+space cycles the configured xkb layouts
+        bindings.push(Keybinding::new(
+            ModifiersState {
+                ctrl: true,
+                ..modkey
+            },
+            xkb::KEY_space,
+            Action::SwitchKeyboardLayout,
+        ));
+
         // system
         // quit - Super+Shift+e
         bindings.push(Keybinding::new(
@@ -155,6 +204,15 @@ impl Keybindings {
             xkb::KEY_e, // lowercase e, since we now use raw_latin_sym_or_raw_current_sym
             Action::Quit,
         ));
+        // log frame pacing, render paths and client issues - Super+Shift+d
+        bindings.push(Keybinding::new(
+            ModifiersState {
+                shift: true,
+                ..modkey
+            },
+            xkb::KEY_d,
+            Action::LogDiagnostics,
+        ));
 
         // workspace switching - Super+1-9 and Super+0 for workspace 10
         for i in 1..=9 {
@@ -192,6 +250,69 @@ impl Keybindings {
             Action::MoveToWorkspace("10".to_string()),
         ));
 
+        // move window to the adjacent output and follow it - Super+Shift+arrows
+        for (key, direction) in [
+            (xkb::KEY_Left, Direction::Left),
+            (xkb::KEY_Right, Direction::Right),
+            (xkb::KEY_Up, Direction::Up),
+            (xkb::KEY_Down, Direction::Down),
+        ] {
+            bindings.push(Keybinding::new(
+                ModifiersState {
+                    shift: true,
+                    ..modkey
+                },
+                key,
+                Action::MoveWindowToOutputAndFollow(direction),
+            ));
+        }
+
+        // tag view - Super+g toggles it, Super+Ctrl+1-9 views a tag, Super+Ctrl+Shift+1-9 toggles it
+        bindings.push(Keybinding::new(modkey, xkb::KEY_g, Action::ToggleTagView));
+        for i in 1..=9 {
+            bindings.push(Keybinding::new(
+                ModifiersState {
+                    ctrl: true,
+                    ..modkey
+                },
+                xkb::KEY_1 + (i - 1),
+                Action::ViewTag(i),
+            ));
+            bindings.push(Keybinding::new(
+                ModifiersState {
+                    ctrl: true,
+                    shift: true,
+                    ..modkey
+                },
+                xkb::KEY_1 + (i - 1),
+                Action::ToggleViewTag(i),
+            ));
+        }
+
+        // window tags - Super+Alt+1-9 sets the tag, Super+Alt+Shift+1-9 toggles it
+        // (not bound when alt is the modkey, these would shadow workspace bindings)
+        if !modkey.alt {
+            for i in 1..=9 {
+                bindings.push(Keybinding::new(
+                    ModifiersState {
+                        alt: true,
+                        ..modkey
+                    },
+                    xkb::KEY_1 + (i - 1),
+                    Action::TagWindow(i),
+                ));
+                bindings.push(Keybinding::new(
+                    ModifiersState {
+                        alt: true,
+                        shift: true,
+                        ..modkey
+                    },
+                    xkb::KEY_1 + (i - 1),
+                    Action::ToggleWindowTag(i),
+                ));
+            }
+        }
+
         // VT switching - Ctrl+Alt+F1-F12
         for vt in 1..=12 {
             bindings.push(Keybinding::new(
@@ -205,11 +326,26 @@ impl Keybindings {
             ));
         }
 
+        // bindings of destructive actions can require a second press
+        let confirmed = super::confirm::confirmed_actions();
+        for binding in &mut bindings {
+            binding.confirm = confirmed.contains(&binding.action);
+        }
+
         debug!("Initialized {} keybindings", bindings.len());
 
         Self { bindings }
     }
 
+    pub fn get(&self, binding: BindingId) -> Option<&Keybinding> {
+        self.bindings.get(binding.0)
+    }
+
+    /// Whether a binding has to be pressed again to run its action
+    pub fn requires_confirmation(&self, binding: BindingId) -> bool {
+        self.get(binding).is_some_and(|binding| binding.confirm)
+    }
+
     /// Get the modifier key from environment or default to Super
     fn get_modkey() -> ModifiersState {
         let modkey_str = std::env::var("SWL_MODKEY").unwrap_or_else(|_| "super".to_string());
@@ -226,22 +362,22 @@ impl Keybindings {
         }
     }
 
-    /// Check if any keybinding matches and return its action
+    /// Check if any keybinding matches and return it with its action
     pub fn check(
         &self,
         modifiers: &ModifiersState,
         key: Keysym,
         key_state: KeyState,
-    ) -> Option<Action> {
+    ) -> Option<(BindingId, Action)> {
         // only trigger on key press, not release
         if key_state != KeyState::Pressed {
             return None;
         }
 
-        for binding in &self.bindings {
+        for (index, binding) in self.bindings.iter().enumerate() {
             if binding.matches(modifiers, key) {
                 tracing::debug!("Keybinding matched: {:?}", binding.action);
-                return Some(binding.action.clone());
+                return Some((BindingId(index), binding.action.clone()));
             }
         }
 
@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Per-window keyboard layout memory.
+//!
+//! With several xkb layouts configured and SWL_PER_WINDOW_LAYOUT=1, the active
+//! layout group is stored on a window when it loses keyboard focus and
+//! restored when it gets focus back, new windows start on the first layout.
+//! The group is switched before the focus moves, so the enter event already
+//! carries it. Without it the layout group stays global. Either way the
+//! active layout is published for the D-Bus service.
+
+use std::sync::{Arc, Mutex};
+
+use smithay::{
+    desktop::Window,
+    input::keyboard::{Layout, XkbContext},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{IsAlive, Serial},
+};
+use tracing::debug;
+
+use crate::State;
+
+/// Layout group a window had when it last lost focus, kept in the window's user data
+struct RememberedLayout(Mutex<Layout>);
+
+/// Active layout group and its xkb name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActiveLayout {
+    pub index: u32,
+    pub name: String,
+}
+
+/// Active layout, shared with the D-Bus service
+pub type SharedActiveLayout = Arc<Mutex<ActiveLayout>>;
+
+/// Per-window layout tracking state
+#[derive(Debug)]
+pub struct WindowLayouts {
+    enabled: bool,
+    /// window the current layout group belongs to
+    window: Option<Window>,
+    pub active: SharedActiveLayout,
+}
+
+impl WindowLayouts {
+    pub fn new() -> Self {
+        let enabled = matches!(
+            std::env::var("SWL_PER_WINDOW_LAYOUT").as_deref(),
+            Ok("1") | Ok("true") | Ok("on")
+        );
+
+        Self {
+            enabled,
+            window: None,
+            active: SharedActiveLayout::default(),
+        }
+    }
+}
+
+/// Group to switch to for a window getting focus, None when the active one fits
+///
+/// Windows without a remembered group start on the first layout.
+fn layout_for_focus(remembered: Option<Layout>, active: Layout) -> Option<Layout> {
+    let layout = remembered.unwrap_or(Layout(0));
+    (layout != active).then_some(layout)
+}
+
+fn active_layout(context: &XkbContext<'_>) -> ActiveLayout {
+    let xkb = context.xkb().lock().unwrap();
+    let layout = xkb.active_layout();
+    ActiveLayout {
+        index: layout.0,
+        name: xkb.layout_name(layout).to_string(),
+    }
+}
+
+impl State {
+    /// Move keyboard focus, switching to the layout group of the newly focused window first
+    pub fn set_keyboard_focus(&mut self, focus: Option<WlSurface>, serial: Serial) {
+        if self.window_layouts.enabled {
+            self.sync_window_layout(focus.as_ref());
+        }
+        let keyboard = self.seat.get_keyboard().unwrap();
+        keyboard.set_focus(self, focus, serial);
+    }
+
+    /// Switch the keyboard to its next layout group
+    pub(super) fn switch_keyboard_layout(&mut self) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+        keyboard.with_xkb_state(self, |mut context| context.cycle_next_layout());
+        self.publish_active_layout();
+    }
+
+    /// Share the active layout group with the D-Bus service
+    pub fn publish_active_layout(&mut self) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let active = keyboard.with_xkb_state(self, |context| active_layout(&context));
+        debug!(
+            "Keyboard layout is group {} ({})",
+            active.index, active.name
+        );
+        *self.window_layouts.active.lock().unwrap() = active;
+    }
+
+    /// Store the active group on the previously focused window and restore the new one's
+    fn sync_window_layout(&mut self, focused: Option<&WlSurface>) {
+        let keyboard = self.seat.get_keyboard().unwrap();
+        let active = keyboard.with_xkb_state(self, |context| context.active_layout());
+
+        if let Some(previous) = self.window_layouts.window.take() {
+            if previous.alive() {
+                previous
+                    .user_data()
+                    .insert_if_missing_threadsafe(|| RememberedLayout(Mutex::new(active)));
+                if let Some(remembered) = previous.user_data().get::<RememberedLayout>() {
+                    *remembered.0.lock().unwrap() = active;
+                }
+            }
+        }
+
+        // layer surfaces and popups keep whatever layout is active
+        let Some(window) = focused.and_then(|surface| {
+            self.shell
+                .read()
+                .unwrap()
+                .space
+                .elements()
+                .find(|window| window.toplevel().is_some_and(|t| t.wl_surface() == surface))
+                .cloned()
+        }) else {
+            return;
+        };
+
+        let remembered = window
+            .user_data()
+            .get::<RememberedLayout>()
+            .map(|remembered| *remembered.0.lock().unwrap());
+        if let Some(layout) = layout_for_focus(remembered, active) {
+            keyboard.with_xkb_state(self, |mut context| context.set_layout(layout));
+            debug!(
+                "Restored keyboard layout group {} for focused window",
+                layout.0
+            );
+            self.publish_active_layout();
+        }
+
+        self.window_layouts.window = Some(window);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_window_starts_on_the_first_layout() {
+        assert_eq!(layout_for_focus(None, Layout(1)), Some(Layout(0)));
+        assert_eq!(layout_for_focus(None, Layout(0)), None);
+    }
+
+    #[test]
+    fn remembered_layout_is_restored() {
+        assert_eq!(
+            layout_for_focus(Some(Layout(2)), Layout(0)),
+            Some(Layout(2))
+        );
+    }
+
+    #[test]
+    fn matching_layout_is_kept() {
+        assert_eq!(layout_for_focus(Some(Layout(1)), Layout(1)), None);
+    }
+}
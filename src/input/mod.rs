@@ -1,6 +1,9 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
+pub mod confirm;
+pub mod grabs;
 pub mod keybindings;
+pub mod layout;
 
 use smithay::{
     backend::input::{
@@ -8,7 +11,7 @@ use smithay::{
         GestureBeginEvent, GestureEndEvent,
         GesturePinchUpdateEvent as GesturePinchUpdateEventTrait,
         GestureSwipeUpdateEvent as GestureSwipeUpdateEventTrait, InputBackend, InputEvent,
-        KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent, PointerMotionEvent,
+        KeyState, KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent, PointerMotionEvent,
     },
     backend::session::Session,
     input::{
@@ -31,7 +34,7 @@ use smithay::{
         data_device::set_data_device_focus, primary_selection::set_primary_focus,
     },
 };
-use std::process::Command;
+use std::{process::Command, time::Instant};
 use tracing::{debug, error, info, trace, warn};
 
 use self::keybindings::Action;
@@ -39,6 +42,30 @@ use crate::utils::coordinates::GlobalPointF64;
 use crate::State;
 
 impl State {
+    /// Show the cursor image the shell arbitrated, client or compositor override
+    pub(crate) fn show_cursor_image(&mut self) {
+        let (current, cursor_position) = {
+            let shell = self.shell.read().unwrap();
+            (shell.cursor_images.current().clone(), shell.cursor_position)
+        };
+
+        // the theme cursor takes precedence when rendering, only keep it for named images
+        let cursor_state = self
+            .seat
+            .user_data()
+            .get::<crate::backend::render::cursor::CursorState>()
+            .unwrap();
+        cursor_state.lock().unwrap().current_cursor = match current {
+            smithay::input::pointer::CursorImageStatus::Named(icon) => Some(icon),
+            _ => None,
+        };
+
+        // schedule render for the output containing the cursor
+        if let Some(output) = self.shell.read().unwrap().output_at(cursor_position) {
+            self.backend.schedule_render(&output);
+        }
+    }
+
     /// Process input events from the backend
     pub fn process_input_event_impl<B: InputBackend>(&mut self, event: InputEvent<B>)
     where
@@ -90,13 +117,19 @@ impl State {
                             let key = keysym
                                 .raw_latin_sym_or_raw_current_sym()
                                 .unwrap_or(keysym.modified_sym());
-                            if let Some(action) =
+                            if let Some((binding, action)) =
                                 state.keybindings.check(modifiers, key, event.state())
                             {
                                 trace!("Key intercepted for action: {:?}", action);
-                                state.handle_action(action);
+                                state.run_binding(binding, action, Instant::now());
                                 FilterResult::Intercept(())
                             } else {
+                                // any other key press cancels a pending confirmation
+                                if event.state() == KeyState::Pressed
+                                    && !keysym.modified_sym().is_modifier_key()
+                                {
+                                    state.cancel_confirmation();
+                                }
                                 // forward to client
                                 FilterResult::Forward
                             }
@@ -130,7 +163,7 @@ impl State {
                     let time = Event::time_msec(&event);
 
                     // find surface under cursor (including decorations)
-                    let surface_under = self.shell.read().unwrap().surface_under(location);
+                    let surface_under = self.pointer_focus_at(location);
 
                     pointer.motion(
                         self,
@@ -202,7 +235,7 @@ impl State {
                     let time = Event::time_msec(&event);
 
                     // find surface under cursor (including decorations)
-                    let surface_under = self.shell.read().unwrap().surface_under(location);
+                    let surface_under = self.pointer_focus_at(location);
 
                     pointer.motion(
                         self,
@@ -255,11 +288,7 @@ impl State {
                                         if let Some(workspace) =
                                             shell.workspaces.get(workspace_name)
                                         {
-                                            if let Some(window) = workspace
-                                                .tiled_windows()
-                                                .nth(workspace.active_tab_index)
-                                                .cloned()
-                                            {
+                                            if let Some(window) = workspace.active_tab().cloned() {
                                                 tab_surface = window
                                                     .toplevel()
                                                     .map(|t| t.wl_surface().clone());
@@ -273,9 +302,8 @@ impl State {
 
                     // set keyboard focus if tab was clicked
                     if let Some(surface) = tab_surface {
-                        let keyboard = self.seat.get_keyboard().unwrap();
                         let serial = SERIAL_COUNTER.next_serial();
-                        keyboard.set_focus(self, Some(surface), serial);
+                        self.set_keyboard_focus(Some(surface), serial);
                     }
 
                     // if not a tab click, handle normal window focus
@@ -296,9 +324,8 @@ impl State {
                             if let Some(surface) =
                                 window.toplevel().and_then(|t| Some(t.wl_surface().clone()))
                             {
-                                let keyboard = self.seat.get_keyboard().unwrap();
                                 let serial = SERIAL_COUNTER.next_serial();
-                                keyboard.set_focus(self, Some(surface), serial);
+                                self.set_keyboard_focus(Some(surface), serial);
                                 //debug!("Set keyboard focus to clicked window");
                             }
                         } else {
@@ -471,7 +498,7 @@ impl State {
     }
 
     /// Handle a keybinding action
-    fn handle_action(&mut self, action: Action) {
+    pub(crate) fn handle_action(&mut self, action: Action) {
         use Action::*;
 
         match action {
@@ -492,9 +519,8 @@ impl State {
                 };
                 // update keyboard focus
                 if let Some(surface) = surface {
-                    let keyboard = self.seat.get_keyboard().unwrap();
                     let serial = SERIAL_COUNTER.next_serial();
-                    keyboard.set_focus(self, Some(surface), serial);
+                    self.set_keyboard_focus(Some(surface), serial);
                 }
             }
             FocusPrev => {
@@ -513,9 +539,8 @@ impl State {
                 };
                 // update keyboard focus
                 if let Some(surface) = surface {
-                    let keyboard = self.seat.get_keyboard().unwrap();
                     let serial = SERIAL_COUNTER.next_serial();
-                    keyboard.set_focus(self, Some(surface), serial);
+                    self.set_keyboard_focus(Some(surface), serial);
                 }
             }
             Zoom => {
@@ -531,6 +556,9 @@ impl State {
                 let mut shell = self.shell.write().unwrap();
                 shell.close_focused();
             }
+            CloseWorkspaceWindows => {
+                self.shell.read().unwrap().close_workspace_windows();
+            }
             ToggleFloating => {
                 let mut shell = self.shell.write().unwrap();
                 if let Some(window) = shell.focused_window.clone() {
@@ -604,6 +632,21 @@ impl State {
                     self.backend.schedule_render(&output);
                 }
             }
+            ToggleOrientation => {
+                let outputs = {
+                    let mut shell = self.shell.write().unwrap();
+                    // Apply to focused workspace
+                    if let Some(workspace) = shell.focused_workspace_mut() {
+                        workspace.tiling.toggle_orientation();
+                        workspace.needs_arrange = true;
+                    }
+                    shell.focused_physical_outputs()
+                };
+                // Schedule render on affected outputs
+                for output in outputs {
+                    self.backend.schedule_render(&output);
+                }
+            }
 
             // tabbed mode
             ToggleLayoutMode => {
@@ -639,9 +682,8 @@ impl State {
 
                     // Update keyboard focus
                     if let Some(surface) = surface {
-                        let keyboard = self.seat.get_keyboard().unwrap();
                         let serial = SERIAL_COUNTER.next_serial();
-                        keyboard.set_focus(self, Some(surface), serial);
+                        self.set_keyboard_focus(Some(surface), serial);
                     }
 
                     self.backend.schedule_render(&output);
@@ -666,9 +708,8 @@ impl State {
 
                     // Update keyboard focus
                     if let Some(surface) = surface {
-                        let keyboard = self.seat.get_keyboard().unwrap();
                         let serial = SERIAL_COUNTER.next_serial();
-                        keyboard.set_focus(self, Some(surface), serial);
+                        self.set_keyboard_focus(Some(surface), serial);
                     }
 
                     self.backend.schedule_render(&output);
@@ -827,7 +868,7 @@ impl State {
 
                 // synthesize pointer motion to move cursor to target virtual output
                 let pointer = self.seat.get_pointer().unwrap();
-                let surface_under = self.shell.read().unwrap().surface_under(target_center);
+                let surface_under = self.pointer_focus_at(target_center);
                 let serial = smithay::utils::SERIAL_COUNTER.next_serial();
 
                 pointer.motion(
@@ -836,7 +877,7 @@ impl State {
                     &smithay::input::pointer::MotionEvent {
                         location: target_center,
                         serial,
-                        time: 0, // synthetic event
+                        time: Self::synthetic_event_time(),
                     },
                 );
                 pointer.frame(self);
@@ -847,15 +888,13 @@ impl State {
                 // update keyboard focus
                 if let Some(window) = focused_window {
                     if let Some(surface) = window.toplevel().map(|t| t.wl_surface().clone()) {
-                        let keyboard = self.seat.get_keyboard().unwrap();
                         let serial = smithay::utils::SERIAL_COUNTER.next_serial();
-                        keyboard.set_focus(self, Some(surface), serial);
+                        self.set_keyboard_focus(Some(surface), serial);
                     }
                 } else {
                     // clear keyboard focus when no window is focused
-                    let keyboard = self.seat.get_keyboard().unwrap();
                     let serial = smithay::utils::SERIAL_COUNTER.next_serial();
-                    keyboard.set_focus(self, None, serial);
+                    self.set_keyboard_focus(None, serial);
                 }
 
                 // schedule render for all affected outputs
@@ -886,9 +925,8 @@ impl State {
                     // Update keyboard focus to ensure it follows the moved window
                     if let Some(window) = focused_window {
                         if let Some(surface) = window.toplevel().map(|t| t.wl_surface().clone()) {
-                            let keyboard = self.seat.get_keyboard().unwrap();
                             let serial = smithay::utils::SERIAL_COUNTER.next_serial();
-                            keyboard.set_focus(self, Some(surface), serial);
+                            self.set_keyboard_focus(Some(surface), serial);
                             //tracing::debug!("Updated keyboard focus after moving window to workspace");
                         }
                     }
@@ -897,7 +935,81 @@ impl State {
                 }
             }
 
+            // outputs
+            MoveWindowToOutputAndFollow(direction) => {
+                let Some((window, outputs)) = self
+                    .shell
+                    .write()
+                    .unwrap()
+                    .move_window_to_output_and_follow(direction)
+                else {
+                    return;
+                };
+
+                // warp the cursor to the window's new center
+                let target_center = self
+                    .shell
+                    .read()
+                    .unwrap()
+                    .space
+                    .element_geometry(&window)
+                    .map(|geometry| GlobalPointF64::from_center(geometry).as_point());
+                if let Some(target_center) = target_center {
+                    let pointer = self.seat.get_pointer().unwrap();
+                    let surface_under = self.pointer_focus_at(target_center);
+                    let serial = smithay::utils::SERIAL_COUNTER.next_serial();
+
+                    pointer.motion(
+                        self,
+                        surface_under,
+                        &smithay::input::pointer::MotionEvent {
+                            location: target_center,
+                            serial,
+                            time: Self::synthetic_event_time(),
+                        },
+                    );
+                    pointer.frame(self);
+
+                    self.shell.write().unwrap().cursor_position = target_center;
+                }
+
+                if let Some(surface) = window.toplevel().map(|t| t.wl_surface().clone()) {
+                    let serial = smithay::utils::SERIAL_COUNTER.next_serial();
+                    self.set_keyboard_focus(Some(surface), serial);
+                }
+
+                for output in &outputs {
+                    self.backend.schedule_render(output);
+                }
+            }
+
+            // tags
+            ToggleTagView => {
+                let outputs = self.shell.write().unwrap().toggle_tag_view();
+                self.finish_tag_action(outputs);
+            }
+            ViewTag(tag) => {
+                let outputs = self.shell.write().unwrap().view_tag(tag);
+                self.finish_tag_action(outputs);
+            }
+            ToggleViewTag(tag) => {
+                let outputs = self.shell.write().unwrap().toggle_view_tag(tag);
+                self.finish_tag_action(outputs);
+            }
+            TagWindow(tag) => {
+                let outputs = self.shell.write().unwrap().tag_window(tag);
+                self.finish_tag_action(outputs);
+            }
+            ToggleWindowTag(tag) => {
+                let outputs = self.shell.write().unwrap().toggle_window_tag(tag);
+                self.finish_tag_action(outputs);
+            }
+
+            // keyboard
+            SwitchKeyboardLayout => self.switch_keyboard_layout(),
+
             // system
+            LogDiagnostics => self.log_diagnostics(),
             Quit => {
                 info!("Quit requested via keybinding");
                 self.loop_signal.stop();
@@ -919,6 +1031,29 @@ impl State {
             }
         }
     }
+
+    /// Sync keyboard focus with the shell and redraw after a tag change
+    fn finish_tag_action(&mut self, outputs: Vec<smithay::output::Output>) {
+        if outputs.is_empty() {
+            return;
+        }
+
+        // the focused window may have been hidden by the change
+        let surface = self
+            .shell
+            .read()
+            .unwrap()
+            .focused_window
+            .as_ref()
+            .and_then(|w| w.toplevel())
+            .map(|t| t.wl_surface().clone());
+        let serial = SERIAL_COUNTER.next_serial();
+        self.set_keyboard_focus(surface, serial);
+
+        for output in outputs {
+            self.backend.schedule_render(&output);
+        }
+    }
 }
 
 // implement SeatHandler for State
@@ -943,23 +1078,17 @@ impl SeatHandler for State {
             .unwrap();
         *cursor_status.lock().unwrap() = image.clone();
 
-        // also update cursor theme state if it's a named cursor
-        if let smithay::input::pointer::CursorImageStatus::Named(icon) = &image {
-            let cursor_state = seat
-                .user_data()
-                .get::<crate::backend::render::cursor::CursorState>()
-                .unwrap();
-            cursor_state.lock().unwrap().current_cursor = Some(*icon);
-        }
-
-        // also store in shell for rendering
-        self.shell.write().unwrap().cursor_status = image;
-
-        // schedule render for the output containing the cursor
-        let cursor_position = self.shell.read().unwrap().cursor_position;
-        if let Some(output) = self.shell.read().unwrap().output_at(cursor_position) {
-            self.backend.schedule_render(&output);
-        }
+        // remember the image for the surface with pointer focus, the one set_cursor is
+        // checked against, a compositor override stays on top until it ends
+        let focus = seat
+            .get_pointer()
+            .and_then(|pointer| pointer.current_focus());
+        self.shell
+            .write()
+            .unwrap()
+            .cursor_images
+            .set_client(focus.as_ref(), image);
+        self.show_cursor_image();
     }
 
     fn focus_changed(&mut self, seat: &Seat<Self>, focused: Option<&Self::KeyboardFocus>) {
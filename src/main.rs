@@ -11,6 +11,8 @@ use smithay::{
 use tracing::{error, info};
 
 mod backend;
+#[cfg(feature = "dbus")]
+mod dbus;
 mod environment;
 mod input;
 mod shell;
@@ -21,6 +23,14 @@ mod wayland;
 use state::State;
 
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some("--list-protocols") {
+        if let Err(err) = list_protocols() {
+            eprintln!("Failed to list protocols: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     if let Err(err) = main_inner() {
         error!("Error occurred in main(): {}", err);
         std::process::exit(1);
@@ -46,16 +56,37 @@ fn main_inner() -> Result<()> {
         event_loop.handle(),
         event_loop.get_signal(),
     );
+    debug_assert!(
+        state.globals.match_expected(),
+        "advertised globals differ from src/wayland/globals.expected:\n{}",
+        state.globals
+    );
+    state.publish_active_layout();
 
     // init backend
     backend::init_backend(&display_handle, &mut event_loop, &mut state)?;
 
+    // watch for clients that stop answering pings
+    wayland::responsiveness::start(&event_loop.handle());
+
     // update environment variables for systemd and D-Bus
     environment::update_environment(&state.socket_name);
 
     // run startup program if configured
     startup::run_startup_program();
 
+    // start the optional D-Bus service
+    #[cfg(feature = "dbus")]
+    if let Err(err) = dbus::start(
+        &event_loop.handle(),
+        state.shell.clone(),
+        state.globals.clone(),
+        state.client_diagnostics.clone(),
+        state.window_layouts.active.clone(),
+    ) {
+        error!("Failed to start D-Bus service: {}", err);
+    }
+
     info!("Starting event loop");
 
     // run the event loop
@@ -76,12 +107,35 @@ fn main_inner() -> Result<()> {
             state.needs_focus_refresh = false;
             state.refresh_focus();
         }
+
+        // refresh pointer focus if windows moved or vanished under the cursor
+        let needs_pointer_refresh =
+            std::mem::take(&mut state.shell.write().unwrap().needs_pointer_refresh);
+        if needs_pointer_refresh {
+            state.refresh_pointer_focus();
+        }
     })?;
 
     info!("Event loop exited");
     Ok(())
 }
 
+/// Print the globals created at startup, without a socket or taking the DRM devices
+fn list_protocols() -> Result<()> {
+    let event_loop: EventLoop<'static, State> =
+        EventLoop::try_new().context("Failed to initialize event loop")?;
+    let display = Display::<State>::new().context("Failed to create wayland display")?;
+    let state = State::new(
+        display.handle(),
+        String::new(),
+        event_loop.handle(),
+        event_loop.get_signal(),
+    );
+
+    print!("{}", state.globals);
+    Ok(())
+}
+
 fn init_logger() -> Result<()> {
     use tracing_subscriber::{fmt, EnvFilter};
 
@@ -116,7 +170,9 @@ fn init_wayland_display(
             // accept new wayland clients
             match state.display_handle.insert_client(
                 client_stream,
-                std::sync::Arc::new(crate::wayland::handlers::ClientState::new()),
+                std::sync::Arc::new(crate::wayland::handlers::ClientState::new(
+                    state.client_disconnects.clone(),
+                )),
             ) {
                 Ok(client) => {
                     tracing::trace!("New Wayland client connected: {:?}", client.id());
@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Cursor image arbitration between clients and the compositor.
+//!
+//! Clients set the base image for the surface that has pointer focus, the
+//! compositor may put an override on top for the duration of a grab or while
+//! the pointer hovers its own UI. The image each surface set last is
+//! remembered, so when an override ends the surface under the pointer gets its
+//! own cursor back right away instead of the default arrow until its next
+//! enter.
+
+use std::{collections::HashMap, hash::Hash};
+
+use smithay::{
+    input::pointer::CursorImageStatus, reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::IsAlive,
+};
+
+/// Client cursor with an optional compositor override
+#[derive(Debug)]
+pub struct CursorImages<S = WlSurface> {
+    base: CursorImageStatus,
+    override_image: Option<CursorImageStatus>,
+    /// last image set while each surface had pointer focus
+    by_surface: HashMap<S, CursorImageStatus>,
+}
+
+impl<S: Clone + Eq + Hash + IsAlive> CursorImages<S> {
+    pub fn new() -> Self {
+        Self {
+            base: CursorImageStatus::default_named(),
+            override_image: None,
+            by_surface: HashMap::new(),
+        }
+    }
+
+    /// Image to render
+    pub fn current(&self) -> &CursorImageStatus {
+        self.override_image.as_ref().unwrap_or(&self.base)
+    }
+
+    /// Cursor set by a client for the surface that has pointer focus
+    pub fn set_client(&mut self, focus: Option<&S>, image: CursorImageStatus) {
+        self.by_surface.retain(|surface, _| surface.alive());
+        if let Some(surface) = focus {
+            self.by_surface.insert(surface.clone(), image.clone());
+        }
+        self.base = image;
+    }
+
+    /// Show a compositor cursor on top of the client one, e.g. during a grab
+    pub fn set_override(&mut self, image: CursorImageStatus) {
+        self.override_image = Some(image);
+    }
+
+    /// End the compositor override, going back to the cursor of the surface under the pointer
+    pub fn clear_override(&mut self, focus: Option<&S>) {
+        self.override_image = None;
+        if let Some(image) = focus.and_then(|surface| self.by_surface.get(surface)) {
+            self.base = image.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use smithay::input::pointer::CursorIcon;
+
+    use super::*;
+
+    /// Stand-in for a client surface
+    #[derive(Debug, Clone)]
+    struct Surface(u32, Arc<AtomicBool>);
+
+    impl Surface {
+        fn new(id: u32) -> Self {
+            Self(id, Arc::new(AtomicBool::new(true)))
+        }
+
+        fn destroy(&self) {
+            self.1.store(false, Ordering::SeqCst);
+        }
+    }
+
+    impl PartialEq for Surface {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+
+    impl Eq for Surface {}
+
+    impl Hash for Surface {
+        fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+            self.0.hash(state);
+        }
+    }
+
+    impl IsAlive for Surface {
+        fn alive(&self) -> bool {
+            self.1.load(Ordering::SeqCst)
+        }
+    }
+
+    fn named(icon: CursorIcon) -> CursorImageStatus {
+        CursorImageStatus::Named(icon)
+    }
+
+    #[test]
+    fn override_stays_on_top_of_client_cursors() {
+        let surface = Surface::new(1);
+        let mut images = CursorImages::new();
+        images.set_override(named(CursorIcon::Grabbing));
+        images.set_client(Some(&surface), named(CursorIcon::Text));
+        assert_eq!(images.current(), &named(CursorIcon::Grabbing));
+    }
+
+    #[test]
+    fn ending_the_override_restores_the_focused_surfaces_cursor() {
+        let editor = Surface::new(1);
+        let terminal = Surface::new(2);
+        let mut images = CursorImages::new();
+        images.set_client(Some(&editor), named(CursorIcon::Text));
+        images.set_client(Some(&terminal), CursorImageStatus::Hidden);
+
+        // a grab started over the terminal ends with the pointer over the editor
+        images.set_override(named(CursorIcon::Grabbing));
+        images.clear_override(Some(&editor));
+        assert_eq!(images.current(), &named(CursorIcon::Text));
+
+        images.set_override(named(CursorIcon::Grabbing));
+        images.clear_override(Some(&terminal));
+        assert_eq!(images.current(), &CursorImageStatus::Hidden);
+    }
+
+    #[test]
+    fn ending_the_override_without_a_known_cursor_keeps_the_base() {
+        let surface = Surface::new(1);
+        let mut images = CursorImages::new();
+        images.set_client(None, named(CursorIcon::Pointer));
+
+        images.set_override(named(CursorIcon::Grabbing));
+        images.clear_override(Some(&surface));
+        assert_eq!(images.current(), &named(CursorIcon::Pointer));
+
+        images.set_override(named(CursorIcon::Grabbing));
+        images.clear_override(None);
+        assert_eq!(images.current(), &named(CursorIcon::Pointer));
+    }
+
+    #[test]
+    fn destroyed_surfaces_are_forgotten() {
+        let gone = Surface::new(1);
+        let other = Surface::new(2);
+        let mut images = CursorImages::new();
+        images.set_client(Some(&gone), named(CursorIcon::Text));
+        gone.destroy();
+        images.set_client(Some(&other), named(CursorIcon::Default));
+
+        images.set_override(named(CursorIcon::Grabbing));
+        images.clear_override(Some(&gone));
+        assert_eq!(images.current(), &named(CursorIcon::Default));
+    }
+}
@@ -1,5 +1,9 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
+pub mod cursor;
+pub mod osd;
+pub mod suspend;
+pub mod tags;
 pub mod tiling;
 pub mod virtual_output;
 pub mod workspace;
@@ -18,15 +22,18 @@ use smithay::{
         utils::{surface_presentation_feedback_flags_from_states, OutputPresentationFeedback},
         Space, Window,
     },
-    input::pointer::CursorImageStatus,
     output::Output,
-    reexports::wayland_server::protocol::wl_surface::WlSurface,
-    utils::{IsAlive, Logical, Point, Rectangle, Scale},
+    reexports::wayland_server::{backend::ClientId, protocol::wl_surface::WlSurface, Resource},
+    utils::{IsAlive, Logical, Point, Rectangle, Scale, Size},
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceData},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
 };
-use std::collections::HashMap;
 
-use self::virtual_output::{VirtualOutputId, VirtualOutputManager};
-use self::workspace::{Workspace, WorkspaceId};
+use self::virtual_output::{Direction, VirtualOutputId, VirtualOutputManager};
+use self::workspace::{UnmappedWindow, Workspace, WorkspaceId};
 use crate::backend::render::element::{AsGlowRenderer, SwlElement};
 use crate::utils::coordinates::{
     GlobalPoint, GlobalRect, OutputExt, OutputRelativePoint, SpaceExt, VirtualOutputRelativePoint,
@@ -38,6 +45,82 @@ pub const BORDER_WIDTH: i32 = 1;
 const FOCUSED_BORDER_COLOR: [f32; 4] = [0.0, 0.5, 1.0, 1.0]; // bright blue
 const UNFOCUSED_BORDER_COLOR: [f32; 4] = [0.0, 0.2, 0.5, 1.0]; // darker blue
 
+// offset between successive floating windows
+const DEFAULT_CASCADE_STEP: i32 = 30;
+
+/// Alpha of the windows of clients not answering their pings
+const UNRESPONSIVE_ALPHA: f32 = 0.5;
+
+/// Fullscreen bookkeeping of a window leaving its workspace
+#[derive(Debug, Clone, Copy)]
+pub(super) struct FullscreenTransfer {
+    geometry_offset: Option<Point<i32, Logical>>,
+}
+
+impl FullscreenTransfer {
+    /// Capture the state before the window is removed, None if it isn't fullscreen
+    pub(super) fn capture(workspace: &Workspace, window: &Window) -> Option<Self> {
+        (workspace.fullscreen.as_ref() == Some(window)).then(|| Self {
+            geometry_offset: workspace.cached_geometry_offsets.get(window).copied(),
+        })
+    }
+}
+
+/// Configure a window as fullscreen with the given size
+///
+/// Only sends a configure if the state changed, so repeating it from arrange is free.
+fn configure_fullscreen(window: &Window, size: smithay::utils::Size<i32, Logical>) {
+    use smithay::reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::State;
+
+    if let Some(toplevel) = window.toplevel() {
+        toplevel.with_pending_state(|state| {
+            state.size = Some(size);
+            state.bounds = Some(size);
+            state.states.set(State::Fullscreen);
+
+            // remove tiled states
+            state.states.unset(State::TiledLeft);
+            state.states.unset(State::TiledRight);
+            state.states.unset(State::TiledTop);
+            state.states.unset(State::TiledBottom);
+        });
+
+        if toplevel.is_initial_configure_sent() {
+            toplevel.send_pending_configure();
+        }
+    }
+}
+
+/// Drop the fullscreen state of a window leaving its workspace
+pub(super) fn unset_fullscreen(window: &Window) {
+    use smithay::reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::State;
+
+    if let Some(toplevel) = window.toplevel() {
+        toplevel.with_pending_state(|state| {
+            state.states.unset(State::Fullscreen);
+        });
+        if toplevel.is_initial_configure_sent() {
+            toplevel.send_pending_configure();
+        }
+    }
+}
+
+/// App id and title of a toplevel window
+pub fn app_id_and_title(window: &Window) -> Option<(String, String)> {
+    let toplevel = window.toplevel()?;
+    let (app_id, title) = with_states(toplevel.wl_surface(), |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .map(|data| {
+                let data = data.lock().unwrap();
+                (data.app_id.clone(), data.title.clone())
+            })
+            .unwrap_or_default()
+    });
+    Some((app_id.unwrap_or_default(), title.unwrap_or_default()))
+}
+
 /// Determine if a window should float by default
 fn should_float_impl(window: &Window) -> bool {
     // check if window is a dialog
@@ -61,6 +144,45 @@ fn should_float_impl(window: &Window) -> bool {
     false
 }
 
+/// Offset between successive floating windows (SWL_CASCADE_STEP)
+fn cascade_step() -> i32 {
+    static STEP: OnceLock<i32> = OnceLock::new();
+    *STEP.get_or_init(|| {
+        std::env::var("SWL_CASCADE_STEP")
+            .ok()
+            .and_then(|s| s.parse::<i32>().ok())
+            .map(|step| step.max(0))
+            .unwrap_or(DEFAULT_CASCADE_STEP)
+    })
+}
+
+/// Position of the next window of a cascade in an area, advancing the cascade
+///
+/// The first window is centered, the following ones are offset one step right and
+/// down each. The cascade starts over at the center when a window would leave the area.
+fn cascade_position(
+    area: Rectangle<i32, Logical>,
+    size: Size<i32, Logical>,
+    cascade: &mut u32,
+    step: i32,
+) -> Point<i32, Logical> {
+    let centered = Point::<i32, Logical>::from((
+        area.loc.x + ((area.size.w - size.w) / 2).max(0),
+        area.loc.y + ((area.size.h - size.h) / 2).max(0),
+    ));
+
+    let mut offset = step * *cascade as i32;
+    if centered.x + offset + size.w > area.loc.x + area.size.w
+        || centered.y + offset + size.h > area.loc.y + area.size.h
+    {
+        *cascade = 0;
+        offset = 0;
+    }
+    *cascade += 1;
+
+    centered + Point::from((offset, offset))
+}
+
 /// A simple shell for managing windows
 pub struct Shell {
     /// The space containing all windows
@@ -81,14 +203,35 @@ pub struct Shell {
     /// Cursor position (relative to space origin)
     pub cursor_position: Point<f64, Logical>,
 
-    /// Cursor image status
-    pub cursor_status: CursorImageStatus,
+    /// Cursor images set by clients and the compositor
+    pub cursor_images: cursor::CursorImages,
 
     /// Virtual output manager
     pub virtual_output_manager: VirtualOutputManager,
 
     /// Currently focused virtual output (for fallback operations)
     pub focused_virtual_output_id: Option<virtual_output::VirtualOutputId>,
+
+    /// Tag bitmask per window, untagged windows have tags::DEFAULT_TAG_MASK
+    pub window_tags: HashMap<Window, u32>,
+
+    /// Virtual outputs currently in tag view
+    tag_views: HashMap<VirtualOutputId, tags::TagView>,
+
+    /// Windows moved or vanished under the cursor, pointer focus must be recomputed
+    pub needs_pointer_refresh: bool,
+
+    /// Windows last configured with the Suspended state
+    suspended_windows: HashSet<Window>,
+
+    /// Hidden windows whose Suspended state waits for them to finish a resize
+    suspend_deferred: HashSet<Window>,
+
+    /// Clients not answering their pings, their windows are dimmed
+    unresponsive_clients: HashSet<ClientId>,
+
+    /// Prompt shown on every output
+    pub osd: osd::Osd,
 }
 
 impl Shell {
@@ -103,9 +246,36 @@ impl Shell {
             // using negative coordinates as sentinel for "not on any output"
             // TODO: convert to Option<GlobalPoint<f64>> for better type safety
             cursor_position: Point::from((-1000.0, -1000.0)),
-            cursor_status: CursorImageStatus::default_named(),
+            cursor_images: cursor::CursorImages::new(),
             virtual_output_manager: VirtualOutputManager::new(),
             focused_virtual_output_id: None,
+            window_tags: HashMap::new(),
+            tag_views: HashMap::new(),
+            needs_pointer_refresh: false,
+            suspended_windows: HashSet::new(),
+            suspend_deferred: HashSet::new(),
+            unresponsive_clients: HashSet::new(),
+            osd: osd::Osd::default(),
+        }
+    }
+
+    /// Replace the set of unresponsive clients, returns whether it changed
+    pub fn set_unresponsive_clients(&mut self, clients: HashSet<ClientId>) -> bool {
+        let changed = self.unresponsive_clients != clients;
+        self.unresponsive_clients = clients;
+        changed
+    }
+
+    /// Alpha a window is rendered with, dimmed while its client is unresponsive
+    fn window_alpha(&self, window: &Window) -> f32 {
+        let unresponsive = window
+            .toplevel()
+            .and_then(|toplevel| toplevel.wl_surface().client())
+            .is_some_and(|client| self.unresponsive_clients.contains(&client.id()));
+        if unresponsive {
+            UNRESPONSIVE_ALPHA
+        } else {
+            1.0
         }
     }
 
@@ -144,6 +314,18 @@ impl Shell {
         workspace_name: &str,
     ) {
         let workspace_id = self.find_or_create_workspace_id(workspace_name);
+        if self.is_tag_view_workspace(workspace_id) {
+            tracing::warn!(
+                "Refusing to switch to tag view workspace '{}'",
+                workspace_name
+            );
+            return;
+        }
+
+        // workspace switching always happens in workspace mode
+        if self.is_tag_view(virtual_id) {
+            self.leave_tag_view(virtual_id);
+        }
 
         tracing::debug!(
             "Switching virtual output {:?} to workspace '{}'",
@@ -190,7 +372,7 @@ impl Shell {
                                 fallback_ws.windows.len(),
                                 current_owner
                             );
-                            for window in &fallback_ws.windows {
+                            for window in fallback_ws.mapped_windows() {
                                 self.space.map_element(window.clone(), (0, 0), false);
                             }
                         }
@@ -236,7 +418,7 @@ impl Shell {
                 new_workspace.windows.len(),
                 workspace_name
             );
-            for window in &new_workspace.windows {
+            for window in new_workspace.mapped_windows() {
                 self.space.map_element(window.clone(), (0, 0), false);
             }
 
@@ -291,6 +473,34 @@ impl Shell {
         tracing::info!("Added output {} to shell space", output.name());
     }
 
+    /// Add the outputs of a device and lay out the virtual outputs of all outputs
+    ///
+    /// `vout_config` is a SWL_VIRTUAL_OUTPUTS config replacing the default virtual outputs.
+    pub fn add_outputs(&mut self, outputs: &[Output], vout_config: Option<&str>) {
+        for output in outputs {
+            self.add_output(output);
+        }
+
+        let all_outputs: Vec<Output> = self.space.outputs().cloned().collect();
+        if let Some(config) = vout_config {
+            self.virtual_output_manager
+                .apply_config(config, &all_outputs);
+        }
+        self.virtual_output_manager.update_all(&all_outputs);
+
+        // ensure all virtual outputs have initial active workspaces
+        let vout_ids: Vec<_> = self.virtual_output_manager.all().map(|vo| vo.id).collect();
+        for (i, vout_id) in vout_ids.iter().enumerate() {
+            if let Some(vout) = self.virtual_output_manager.get(*vout_id) {
+                if vout.active_workspace().is_none() {
+                    // assign different workspaces to different virtual outputs
+                    let workspace_name = (i + 1).to_string();
+                    self.switch_workspace_on_virtual(*vout_id, &workspace_name);
+                }
+            }
+        }
+    }
+
     /// Update output position in the space (call this after output configuration changes)
     pub fn update_output_position(&mut self, output: &Output) {
         let position = output.current_location_typed();
@@ -406,29 +616,40 @@ impl Shell {
         );
 
         // Add window to workspace
+        let floating = should_float_impl(&window);
         if let Some(workspace) = self.workspaces.get_mut(&workspace_id) {
             workspace.virtual_output_id = Some(virtual_output_id);
-            workspace.add_window(window.clone(), should_float_impl(&window));
+            workspace.add_window(window.clone(), floating);
             workspace.append_focus(&window);
         }
 
-        // Map window in smithay space at virtual output's global position
+        // Map window in smithay space at virtual output's global position, tiled windows
+        // get their place on arrange, floating ones keep theirs
         let vout_position = self
             .virtual_output_manager
             .get(virtual_output_id)
             .map(|vout| vout.logical_geometry.location().as_point())
             .unwrap_or_default();
-        self.space.map_element(window.clone(), vout_position, false);
-        tracing::debug!(
-            "Mapped window to smithay space at {:?} (virtual output global position)",
+        let location = if floating {
+            self.cascade_floating_window(&window, virtual_output_id, workspace_id)
+                .unwrap_or(vout_position)
+        } else {
             vout_position
-        );
+        };
+        self.space.map_element(window.clone(), location, false);
+        tracing::debug!("Mapped window to smithay space at {:?}", location);
 
         tracing::debug!("Setting focus to window");
         self.focused_window = Some(window.clone());
 
         tracing::debug!("Set focus to new window");
 
+        // tags from rules or the current view, may hide the window right away
+        self.assign_initial_tags(&window, virtual_output_id);
+        if self.is_tag_view(virtual_output_id) {
+            self.refresh_tag_view(virtual_output_id);
+        }
+
         // arrange windows - we need to get the output from virtual output
         let outputs_to_arrange: Vec<_> =
             if let Some(vout) = self.virtual_output_manager.get(virtual_output_id) {
@@ -445,6 +666,41 @@ impl Shell {
         }
     }
 
+    /// Global location of a new floating window, centered on its virtual output
+    ///
+    /// Each floating window mapped while others are visible is offset one step right
+    /// and down from the previous one, so dialogs opened in a row don't cover each
+    /// other. The cascade wraps back to the center when a window would leave the
+    /// available area and starts over once no floating window is left.
+    fn cascade_floating_window(
+        &mut self,
+        window: &Window,
+        virtual_output_id: VirtualOutputId,
+        workspace_id: WorkspaceId,
+    ) -> Option<Point<i32, Logical>> {
+        let workspace = self.workspaces.get(&workspace_id)?;
+        let area = workspace.available_area.as_rectangle();
+        let others_floating = workspace
+            .mapped_windows()
+            .any(|w| w != window && workspace.floating_windows.contains(w));
+
+        let vout = self.virtual_output_manager.get_mut(virtual_output_id)?;
+        if !others_floating {
+            vout.floating_cascade = 0;
+        }
+
+        // place the visible geometry, not the CSD shadow around it
+        let geometry = window.geometry();
+        let position = cascade_position(
+            area,
+            geometry.size,
+            &mut vout.floating_cascade,
+            cascade_step(),
+        );
+        let location =
+            VirtualOutputRelativePoint::from(position).to_global(vout.logical_geometry.location());
+        Some(location.as_point() - geometry.loc)
+    }
 
     /// Move a window to a specific workspace
     pub fn move_window_to_workspace(
@@ -453,6 +709,13 @@ impl Shell {
         workspace_name: String,
         output: &Output,
     ) {
+        // fullscreen state follows the window, capture it before removal clears it
+        let fullscreen = self
+            .workspaces
+            .values()
+            .find(|workspace| workspace.windows.contains(&window))
+            .and_then(|workspace| FullscreenTransfer::capture(workspace, &window));
+
         // First, remove window from all workspaces
         self.remove_window(&window);
 
@@ -466,6 +729,9 @@ impl Shell {
         if let Some(workspace) = self.workspaces.get_mut(&workspace_id) {
             workspace.add_window(window.clone(), floating);
         }
+        if let Some(fullscreen) = fullscreen {
+            self.transfer_fullscreen(&window, fullscreen, workspace_id);
+        }
 
         // If this workspace is currently active on any virtual output on this physical output, map the window
         let should_map = self
@@ -485,6 +751,126 @@ impl Shell {
         self.set_focus(window);
     }
 
+    /// Apply the fullscreen state of a window that moved to another workspace
+    ///
+    /// The destination keeps its own fullscreen window if it has one. Otherwise the
+    /// window stays fullscreen and, if the destination is shown, gets configured for the
+    /// destination virtual output right away, so it never presents at the stale size.
+    pub(super) fn transfer_fullscreen(
+        &mut self,
+        window: &Window,
+        transfer: FullscreenTransfer,
+        destination_id: WorkspaceId,
+    ) {
+        let size = self
+            .find_workspace_owner(destination_id)
+            .and_then(|vout_id| self.virtual_output_manager.get(vout_id))
+            .map(|vout| vout.logical_geometry.size());
+        let Some(destination) = self.workspaces.get_mut(&destination_id) else {
+            return;
+        };
+
+        if destination.fullscreen.is_some() {
+            unset_fullscreen(window);
+            return;
+        }
+        destination.fullscreen = Some(window.clone());
+        if let Some(offset) = transfer.geometry_offset {
+            destination
+                .cached_geometry_offsets
+                .insert(window.clone(), offset);
+        }
+
+        // hidden workspaces get configured when they are arranged
+        if let Some(size) = size {
+            configure_fullscreen(window, size);
+        }
+    }
+
+    /// Move the focused window to the adjacent virtual output and follow it
+    ///
+    /// The window joins the active workspace over there and stays focused. Focus and the
+    /// arrangement of both virtual outputs are settled here, each physical output is
+    /// arranged once, so nothing in between ever gets rendered. Returns the moved window
+    /// and the physical outputs needing a redraw.
+    pub fn move_window_to_output_and_follow(
+        &mut self,
+        direction: Direction,
+    ) -> Option<(Window, Vec<Output>)> {
+        let window = self.focused_window.clone()?;
+        let source_id = self
+            .workspaces
+            .iter()
+            .find(|(_, workspace)| workspace.windows.contains(&window))
+            .map(|(id, _)| *id)?;
+        let source_vout_id = self.find_workspace_owner(source_id)?;
+        let Some(target_vout_id) = self
+            .virtual_output_manager
+            .neighbor(source_vout_id, direction)
+        else {
+            tracing::debug!("No virtual output {:?} of {:?}", direction, source_vout_id);
+            return None;
+        };
+        let target_vout = self.virtual_output_manager.get(target_vout_id)?;
+        let target_id = target_vout.active_workspace()?;
+        let view_mask = target_vout.view_mask;
+        let offset = target_vout.logical_geometry.location().as_point()
+            - self
+                .virtual_output_manager
+                .get(source_vout_id)?
+                .logical_geometry
+                .location()
+                .as_point();
+
+        let floating = self
+            .workspaces
+            .get(&source_id)
+            .is_some_and(|workspace| workspace.floating_windows.contains(&window));
+        let location = self.space.element_location(&window);
+
+        self.migrate_window(source_id, target_id, &window);
+
+        // the window has to match the view it lands in, or it would get parked right away
+        if self.is_tag_view(target_vout_id) {
+            self.window_tags.insert(window.clone(), view_mask);
+        }
+
+        // floating windows keep their place relative to the virtual output, tiled ones
+        // get positioned by the arrange below
+        if floating {
+            if let Some(location) = location {
+                self.space
+                    .map_element(window.clone(), location + offset, false);
+            }
+        }
+
+        self.set_focus(window.clone());
+
+        let outputs = self.physical_outputs_of_both(source_vout_id, target_vout_id);
+        for output in &outputs {
+            self.arrange_windows_on_output(output);
+        }
+
+        tracing::info!(
+            "Moved window from {:?} to {:?}",
+            source_vout_id,
+            target_vout_id
+        );
+
+        Some((window, outputs))
+    }
+
+    /// Physical outputs showing either virtual output, each listed once
+    fn physical_outputs_of_both(&self, a: VirtualOutputId, b: VirtualOutputId) -> Vec<Output> {
+        let mut outputs = self.physical_outputs_of(a);
+        for output in self.physical_outputs_of(b) {
+            if !outputs.contains(&output) {
+                outputs.push(output);
+            }
+        }
+        outputs
+    }
+
     /// Get the window under the given point
     pub fn window_under(&self, point: Point<f64, Logical>) -> Option<Window> {
         use tracing::debug;
@@ -793,6 +1179,7 @@ impl Shell {
     /// Refresh the space (needed for damage tracking)
     pub fn refresh(&mut self) {
         self.space.refresh();
+        self.refresh_tags();
     }
 
     /// Find which output a surface is visible on
@@ -921,6 +1308,23 @@ impl Shell {
         // elements should be in front-to-back order for smithay's damage tracker
         // (first element is topmost, last element is bottommost)
 
+        // a pending prompt covers everything, even overlay layers
+        if self.osd.prompt().is_some() {
+            if let Some(geometry) = self.space.output_geometry(output) {
+                let banner = osd::banner(geometry.size);
+                let banner_buffer =
+                    SolidColorBuffer::new((banner.size.w, banner.size.h), osd::BANNER_COLOR);
+                let banner_element = SolidColorRenderElement::from_buffer(
+                    &banner_buffer,
+                    banner.loc.to_physical_precise_round(output_scale),
+                    output_scale,
+                    1.0,
+                    smithay::backend::renderer::element::Kind::Unspecified,
+                );
+                elements.push(SwlElement::SolidColor(banner_element));
+            }
+        }
+
         // 1. Overlay layers always render (topmost)
         for layer_surface in &layers {
             let layer = layer_surface.layer();
@@ -988,7 +1392,7 @@ impl Shell {
                                                 .as_point()
                                                 .to_physical_precise_round(output_scale),
                                             output_scale,
-                                            1.0,
+                                            self.window_alpha(fullscreen_window),
                                         );
                                         window_elements.extend(
                                             surface_elements
@@ -1026,7 +1430,7 @@ impl Shell {
                                                 .as_point()
                                                 .to_physical_precise_round(output_scale),
                                             output_scale,
-                                            1.0,
+                                            self.window_alpha(window),
                                         );
                                         //tracing::debug!("Window render_elements: global {:?} -> output-relative {:?} (physical {:?})",
                                         //    location, output_relative_location, output_relative_location.as_point().to_physical_precise_round::<_, i32>(output_scale));
@@ -1074,8 +1478,9 @@ impl Shell {
 
                                 // render individual tab sections with separators
                                 let tab_width = area.size().w / tiled.len() as i32;
+                                let active_tab_index = workspace.active_tab_index();
                                 for (i, _window) in tiled.iter().enumerate() {
-                                    let is_active = i == workspace.active_tab_index;
+                                    let is_active = i == active_tab_index;
                                     let color = if is_active {
                                         FOCUSED_BORDER_COLOR // bright blue for active
                                     } else {
@@ -1402,6 +1807,13 @@ impl Shell {
     //         // no need to send frame callbacks here - the render loop will handle that
     //     }
 
+    /// Whether a window floats on its workspace
+    pub fn is_floating(&self, window: &Window) -> bool {
+        self.workspaces
+            .values()
+            .any(|workspace| workspace.floating_windows.contains(window))
+    }
+
     /// Toggle floating state for a window
     pub fn toggle_floating(&mut self, window: &Window, output: &Output) {
         if let Some(workspace) = self.workspace_containing_window_mut(window) {
@@ -1659,6 +2071,30 @@ impl Shell {
         }
     }
 
+    /// Ask every window of the workspace shown under the cursor to close
+    pub fn close_workspace_windows(&self) {
+        let Some(workspace) = self
+            .virtual_output_at_point(self.cursor_position)
+            .and_then(|vout_id| self.virtual_output_manager.get(vout_id))
+            .and_then(|vout| vout.active_workspace())
+            .and_then(|workspace_id| self.workspaces.get(&workspace_id))
+        else {
+            tracing::warn!("No workspace under the cursor to close");
+            return;
+        };
+
+        for window in &workspace.windows {
+            if let Some(toplevel) = window.toplevel() {
+                toplevel.send_close();
+            }
+        }
+        tracing::info!(
+            "Sent close request to {} windows of workspace {}",
+            workspace.windows.len(),
+            workspace.name
+        );
+    }
+
     /// Refresh focus to the topmost window in the focus stack
     /// Called when layer surfaces are destroyed or focus needs updating
     pub fn refresh_focus(&mut self) -> Option<Window> {
@@ -1698,23 +2134,13 @@ impl Shell {
         // Update the focus stack in the window's workspace
         for workspace in self.workspaces.values_mut() {
             if workspace.windows.contains(&window) {
+                // in tabbed mode the active tab follows focus, show the new one
+                let old_tab = workspace.active_tab().cloned();
                 workspace.append_focus(&window);
-
-                // In tabbed mode, also update active_tab_index to match focused window
-                if matches!(workspace.layout_mode, workspace::LayoutMode::Tabbed) {
-                    let idx = workspace
-                        .tiled_windows()
-                        .enumerate()
-                        .find(|(_, w)| *w == &window)
-                        .map(|(idx, _)| idx);
-                    if let Some(idx) = idx {
-                        let old_index = workspace.active_tab_index;
-                        workspace.active_tab_index = idx;
-                        // trigger rearrangement if tab index changed
-                        if old_index != idx {
-                            workspace.needs_arrange = true;
-                        }
-                    }
+                if matches!(workspace.layout_mode, workspace::LayoutMode::Tabbed)
+                    && workspace.active_tab() != old_tab.as_ref()
+                {
+                    workspace.needs_arrange = true;
                 }
 
                 break;
@@ -1910,6 +2336,9 @@ impl Shell {
 
     /// Arrange windows on the given output according to the tiling layout
     pub fn arrange_windows_on_output(&mut self, output: &Output) {
+        // windows about to be revealed lose Suspended in the configures sent below
+        self.update_suspended_states();
+
         // collect virtual output info to avoid borrowing conflicts
         let virtual_output_info: Vec<_> = self
             .virtual_output_manager
@@ -1925,11 +2354,23 @@ impl Shell {
             .collect();
 
         // Calculate non-exclusive zone from physical output (in output-relative coordinates)
-        let non_exclusive_zone = {
+        let mut non_exclusive_zone = {
             let layer_map = smithay::desktop::layer_map_for_output(output);
             layer_map.non_exclusive_zone()
         };
 
+        // never tile into a degenerate area, fall back to the whole output instead
+        if non_exclusive_zone.size.w <= 0 || non_exclusive_zone.size.h <= 0 {
+            if let Some(output_geometry) = self.space.output_geometry(output) {
+                tracing::warn!(
+                    "Non-exclusive zone of output {} is degenerate ({:?}), using full output",
+                    output.name(),
+                    non_exclusive_zone
+                );
+                non_exclusive_zone = Rectangle::from_size(output_geometry.size);
+            }
+        }
+
         // Convert non-exclusive zone to global coordinates
         let output_position = output.current_location_typed();
         let non_exclusive_zone_origin =
@@ -1978,6 +2419,7 @@ impl Shell {
                 );
 
                 workspace.update_output_geometry(available_geometry_relative);
+                workspace.update_output_size(logical_geometry.size());
 
                 // clean up dead windows first
                 workspace.refresh();
@@ -1985,39 +2427,12 @@ impl Shell {
                 // validate workspace consistency for debugging
                 workspace.validate_consistency();
 
-                // handle fullscreen window first
-                if let Some(fullscreen_window) = &workspace.fullscreen {
-                    // for fullscreen, we need the actual output's logical size after transform
-                    // the virtual output's logical_geometry might be pre-transform
-                    let fullscreen_size = if let Some(mode) = output.current_mode() {
-                        let transform = output.current_transform();
-                        let scale = output.current_scale().fractional_scale();
-
-                        // apply transform to get the logical size
-                        let (width, height) = match transform {
-                            smithay::utils::Transform::_90
-                            | smithay::utils::Transform::_270
-                            | smithay::utils::Transform::Flipped90
-                            | smithay::utils::Transform::Flipped270 => {
-                                // rotated: swap width and height
-                                (
-                                    (mode.size.h as f64 / scale) as i32,
-                                    (mode.size.w as f64 / scale) as i32,
-                                )
-                            }
-                            _ => {
-                                // not rotated
-                                (
-                                    (mode.size.w as f64 / scale) as i32,
-                                    (mode.size.h as f64 / scale) as i32,
-                                )
-                            }
-                        };
-                        smithay::utils::Size::from((width, height))
-                    } else {
-                        logical_geometry.size()
-                    };
-
+                // handle fullscreen window first, unless its client unmapped it
+                if let Some(fullscreen_window) = workspace
+                    .fullscreen
+                    .as_ref()
+                    .filter(|window| !workspace.unmapped_windows.contains_key(window))
+                {
                     // position fullscreen window at virtual output origin
                     self.space.map_element(
                         fullscreen_window.clone(),
@@ -2025,25 +2440,8 @@ impl Shell {
                         false,
                     );
 
-                    if let Some(toplevel) = fullscreen_window.toplevel() {
-                        use smithay::reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::State;
-
-                        toplevel.with_pending_state(|state| {
-                            state.size = Some(fullscreen_size);
-                            state.bounds = Some(fullscreen_size);
-                            state.states.set(State::Fullscreen);
-
-                            // remove tiled states
-                            state.states.unset(State::TiledLeft);
-                            state.states.unset(State::TiledRight);
-                            state.states.unset(State::TiledTop);
-                            state.states.unset(State::TiledBottom);
-                        });
-
-                        if toplevel.is_initial_configure_sent() {
-                            toplevel.send_configure();
-                        }
-                    }
+                    // a move between workspaces may already have sent this exact state
+                    configure_fullscreen(fullscreen_window, logical_geometry.size());
 
                     workspace.needs_arrange = false;
                     continue; // don't arrange other windows when one is fullscreen
@@ -2134,9 +2532,10 @@ impl Shell {
                                     state.states.unset(State::Fullscreen);
                                 });
 
-                                // send the configure event
+                                // configure only what changed, a revealed window may
+                                // already have been told it is no longer suspended
                                 if toplevel.is_initial_configure_sent() {
-                                    toplevel.send_configure();
+                                    toplevel.send_pending_configure();
                                 }
                             }
                         }
@@ -2148,7 +2547,8 @@ impl Shell {
                         }
 
                         // show only the active tab
-                        if let Some(active_window) = windows_to_tile.get(workspace.active_tab_index)
+                        if let Some(active_window) =
+                            windows_to_tile.get(workspace.active_tab_index())
                         {
                             let available_area = workspace.available_area;
                             let window_rect = VirtualOutputRelativeRect::with_y_offset(
@@ -2226,9 +2626,10 @@ impl Shell {
                                     state.states.unset(State::Fullscreen);
                                 });
 
-                                // send the configure event
+                                // configure only what changed, a revealed window may
+                                // already have been told it is no longer suspended
                                 if toplevel.is_initial_configure_sent() {
-                                    toplevel.send_configure();
+                                    toplevel.send_pending_configure();
                                 }
                             }
                         }
@@ -2238,6 +2639,126 @@ impl Shell {
                 workspace.needs_arrange = false;
             }
         }
+
+        // windows may have moved under a stationary cursor
+        self.mark_pointer_refresh(output);
+    }
+
+    /// Request a pointer focus refresh if the output contains the cursor
+    fn mark_pointer_refresh(&mut self, output: &Output) {
+        let contains_cursor = self
+            .space
+            .output_geometry(output)
+            .is_some_and(|geometry| geometry.to_f64().contains(self.cursor_position));
+        if contains_cursor {
+            self.needs_pointer_refresh = true;
+        }
+    }
+
+    /// Window of a toplevel surface, in any workspace or parked by a tag view
+    pub fn toplevel_window(&self, surface: &WlSurface) -> Option<Window> {
+        self.workspaces
+            .values()
+            .flat_map(|workspace| workspace.windows.iter())
+            .chain(self.tag_hidden_windows())
+            .find(|window| {
+                window
+                    .toplevel()
+                    .is_some_and(|toplevel| toplevel.wl_surface() == surface)
+            })
+            .cloned()
+    }
+
+    /// Handle a client unmapping its toplevel (null buffer commit)
+    ///
+    /// Unlike removal, the window keeps its workspace, position in the stack,
+    /// floating and fullscreen state. It is only taken out of the space, tiling
+    /// and focus until it gets a buffer again. Windows of hidden workspaces and
+    /// windows parked by a tag view are tracked the same way.
+    pub fn unmap_window(&mut self, window: &Window) -> Vec<Output> {
+        let location = self.space.element_location(window).unwrap_or_default();
+        let outputs = self.space.outputs_for_element(window);
+        let unmapped = UnmappedWindow::new(location);
+
+        if let Some(workspace) = self.workspace_containing_window_mut(window) {
+            workspace.unmapped_windows.insert(window.clone(), unmapped);
+            workspace.focus_stack.retain(|w| w != window);
+            workspace.needs_arrange = true;
+        } else if let Some(slot) = self.tag_hidden_unmapped_mut(window) {
+            *slot = Some(unmapped);
+        } else {
+            return Vec::new();
+        }
+
+        if self.focused_window.as_ref() == Some(window) {
+            self.focused_window = None;
+            self.update_focused_virtual_output();
+        }
+
+        for output in &outputs {
+            self.mark_pointer_refresh(output);
+        }
+        self.space.unmap_elem(window);
+
+        outputs
+    }
+
+    /// Find a window unmapped by its client from its toplevel surface
+    pub fn unmapped_window(&mut self, surface: &WlSurface) -> Option<Window> {
+        let window = self.toplevel_window(surface)?;
+        self.unmapped_state_mut(&window).is_some().then_some(window)
+    }
+
+    /// Whether a commit without buffer of an unmapped window needs a configure, see
+    /// [`UnmappedWindow::take_initial_configure`]
+    pub fn take_initial_configure(&mut self, window: &Window) -> bool {
+        self.unmapped_state_mut(window)
+            .is_some_and(UnmappedWindow::take_initial_configure)
+    }
+
+    fn unmapped_state_mut(&mut self, window: &Window) -> Option<&mut UnmappedWindow> {
+        let in_workspace = self
+            .workspaces
+            .values()
+            .any(|workspace| workspace.windows.contains(window));
+        if in_workspace {
+            self.workspace_containing_window_mut(window)?
+                .unmapped_windows
+                .get_mut(window)
+        } else {
+            self.tag_hidden_unmapped_mut(window)?.as_mut()
+        }
+    }
+
+    /// Restore a window the client mapped again into its previous slot
+    pub fn remap_window(&mut self, window: &Window) -> Vec<Output> {
+        let Some((workspace_id, unmapped)) =
+            self.workspaces.iter_mut().find_map(|(id, workspace)| {
+                let unmapped = workspace.unmapped_windows.remove(window)?;
+                workspace.append_focus(window);
+                workspace.needs_arrange = true;
+                Some((*id, unmapped))
+            })
+        else {
+            // a window parked by a tag view is mapped once its tags are viewed
+            if let Some(slot) = self.tag_hidden_unmapped_mut(window) {
+                *slot = None;
+            }
+            return Vec::new();
+        };
+
+        // windows of hidden workspaces are mapped when the workspace is shown
+        let Some(vout_id) = self.find_workspace_owner(workspace_id) else {
+            return Vec::new();
+        };
+        self.space
+            .map_element(window.clone(), unmapped.location, false);
+
+        let outputs = self.physical_outputs_of(vout_id);
+        for output in &outputs {
+            self.mark_pointer_refresh(output);
+        }
+        outputs
     }
 
     /// Remove a window from all workspaces
@@ -2252,13 +2773,19 @@ impl Shell {
             }
         }
 
+        // hidden windows of a tag view aren't part of any workspace
+        self.remove_from_tag_views(window);
+
         // Clear focused window if it was removed
         if self.focused_window.as_ref() == Some(window) {
             self.focused_window = None;
             self.update_focused_virtual_output();
         }
 
-        // Unmap from space
+        // Unmap from space, whatever was below it may now be under the cursor
+        for output in self.space.outputs_for_element(window) {
+            self.mark_pointer_refresh(&output);
+        }
         self.space.unmap_elem(window);
 
         // Find all affected outputs via virtual output manager
@@ -2303,13 +2830,8 @@ impl Shell {
                         };
 
                         if relative_x >= tab_start && relative_x < tab_end {
-                            workspace.active_tab_index = i;
-                            workspace.needs_arrange = true;
-
-                            // Update focus to the clicked tab
-                            let window = workspace.tiled_windows().nth(i).cloned();
-                            if let Some(window) = window {
-                                workspace.append_focus(&window);
+                            // focusing the clicked tab makes it the active one
+                            if let Some(window) = workspace.select_tab(i) {
                                 self.focused_window = Some(window);
                                 self.update_focused_virtual_output();
                             }
@@ -2323,3 +2845,425 @@ impl Shell {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::keybindings::Action;
+    use crate::utils::{test_client::Harness, testing};
+    use smithay::utils::Transform;
+    use wayland_protocols::xdg::shell::client::xdg_toplevel::State as ToplevelState;
+
+    #[test]
+    fn five_floating_windows_cascade_on_screen() {
+        let area = Rectangle::from_size(Size::from((1920, 1080)));
+        let size = Size::from((400, 300));
+        let mut cascade = 0;
+        let positions: Vec<_> = (0..5)
+            .map(|_| cascade_position(area, size, &mut cascade, 30))
+            .collect();
+
+        assert_eq!(positions[0], Point::from((760, 390)));
+        for pair in positions.windows(2) {
+            assert_eq!(pair[1] - pair[0], Point::from((30, 30)));
+        }
+        for position in &positions {
+            assert!(area.contains_rect(Rectangle::new(*position, size)));
+        }
+    }
+
+    #[test]
+    fn cascade_starts_over_before_leaving_the_area() {
+        let area = Rectangle::new(Point::from((0, 30)), Size::from((1000, 600)));
+        let size = Size::from((400, 500));
+        let mut cascade = 0;
+        let positions: Vec<_> = (0..4)
+            .map(|_| cascade_position(area, size, &mut cascade, 30))
+            .collect();
+
+        // 50px of room below the centered window, one step fits
+        assert_eq!(positions[0], Point::from((300, 80)));
+        assert_eq!(positions[1], Point::from((330, 110)));
+        assert_eq!(positions[2], positions[0]);
+        assert_eq!(positions[3], positions[1]);
+    }
+
+    /// Shell with a single 1920x1080 output at the origin, showing workspace "1"
+    fn shell_with_output() -> (Shell, Output) {
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let mut shell = Shell::new();
+        shell.add_output(&output);
+        (shell, output)
+    }
+
+    #[test]
+    fn arrange_under_the_cursor_refreshes_pointer_focus() {
+        let (mut shell, output) = shell_with_output();
+        shell.cursor_position = Point::from((100.0, 100.0));
+        shell.needs_pointer_refresh = false;
+
+        shell.arrange_windows_on_output(&output);
+        assert!(shell.needs_pointer_refresh);
+    }
+
+    /// Center of a mapped window of the harness client
+    fn window_center(harness: &Harness, index: usize) -> Point<f64, Logical> {
+        let window = harness.window(index);
+        let shell = harness.state.shell.read().unwrap();
+        let geometry = shell.space.element_geometry(&window).unwrap().to_f64();
+        geometry.loc + geometry.size.downscale(2.0).to_point()
+    }
+
+    #[test]
+    fn closing_the_window_under_the_cursor_enters_the_revealed_one() {
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let mut harness = Harness::new(&[output], None);
+        let revealed = harness.map_window();
+        let closed = harness.map_window();
+        harness.arrange();
+
+        harness.move_pointer(window_center(&harness, closed));
+        assert_eq!(harness.take_pointer_enters(), [closed]);
+
+        // the remaining window takes the whole output, under the stationary cursor
+        harness.close_window(closed);
+        harness.arrange();
+        assert!(std::mem::take(
+            &mut harness.state.shell.write().unwrap().needs_pointer_refresh
+        ));
+        harness.state.refresh_pointer_focus();
+        harness.roundtrip();
+        assert_eq!(harness.take_pointer_enters(), [revealed]);
+
+        // nothing changed since, nothing to enter again
+        harness.state.refresh_pointer_focus();
+        harness.roundtrip();
+        assert!(harness.take_pointer_enters().is_empty());
+    }
+
+    #[test]
+    fn arrange_away_from_the_cursor_keeps_pointer_focus() {
+        let (mut shell, output) = shell_with_output();
+        shell.cursor_position = Point::from((2000.0, 100.0));
+        shell.needs_pointer_refresh = false;
+
+        shell.arrange_windows_on_output(&output);
+        assert!(!shell.needs_pointer_refresh);
+    }
+
+    #[test]
+    fn move_to_output_arranges_each_physical_output_once() {
+        let (mut shell, left) = shell_with_output();
+        let right = testing::output("DP-2", (1920, 1080), Transform::Normal, 1.0, (1920, 0));
+        shell.add_output(&right);
+        let vouts: Vec<_> = shell
+            .virtual_output_manager
+            .all()
+            .map(|vout| vout.id)
+            .collect();
+        let (left_vout, right_vout) = (vouts[0], vouts[1]);
+
+        assert_eq!(
+            shell
+                .virtual_output_manager
+                .neighbor(left_vout, Direction::Right),
+            Some(right_vout)
+        );
+        assert_eq!(
+            shell.physical_outputs_of_both(left_vout, right_vout),
+            [left.clone(), right]
+        );
+        assert_eq!(shell.physical_outputs_of_both(left_vout, left_vout), [left]);
+    }
+
+    #[test]
+    fn move_to_output_without_focus_does_nothing() {
+        let (mut shell, _output) = shell_with_output();
+        let moved = shell.move_window_to_output_and_follow(Direction::Right);
+        assert!(moved.is_none());
+    }
+
+    #[test]
+    fn fullscreen_window_moved_to_another_output_is_configured_once() {
+        let left = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let right = testing::output("DP-2", (2560, 1440), Transform::Normal, 1.0, (1920, 0));
+        let mut harness = Harness::new(&[left, right], None);
+        let window = harness.map_window();
+
+        harness.state.handle_action(Action::Fullscreen);
+        harness.roundtrip();
+        let configures = harness.take_configures(window);
+        assert_eq!(configures.last().unwrap().size, (1920, 1080));
+
+        harness
+            .state
+            .handle_action(Action::MoveWindowToOutputAndFollow(Direction::Right));
+        harness.roundtrip();
+        let configures = harness.take_configures(window);
+        assert_eq!(configures.len(), 1, "{:?}", configures);
+        assert_eq!(configures[0].size, (2560, 1440));
+        assert!(configures[0].has(ToplevelState::Fullscreen));
+
+        let moved = harness.window(window);
+        let shell = harness.state.shell.read().unwrap();
+        assert_eq!(
+            shell.space.element_location(&moved),
+            Some(Point::from((1920, 0)))
+        );
+    }
+
+    #[test]
+    fn window_moved_to_another_output_takes_cursor_and_focus_along() {
+        let left = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let right = testing::output("DP-2", (2560, 1440), Transform::Normal, 1.0, (1920, 0));
+        let mut harness = Harness::new(&[left, right], None);
+        let stays = harness.map_window();
+        let moved = harness.map_window();
+        harness.arrange();
+        harness.move_pointer(window_center(&harness, stays));
+        harness.take_pointer_enters();
+        harness.take_keyboard_focus();
+        let window = harness.window(moved);
+        assert_eq!(
+            harness.state.shell.read().unwrap().focused_window.as_ref(),
+            Some(&window)
+        );
+
+        harness
+            .state
+            .handle_action(Action::MoveWindowToOutputAndFollow(Direction::Right));
+        harness.roundtrip();
+
+        let shell = harness.state.shell.read().unwrap();
+        let rect = shell.space.element_geometry(&window).unwrap();
+        assert!(rect.loc.x >= 1920, "{:?}", rect);
+        let cursor = harness.state.seat.get_pointer().unwrap().current_location();
+        assert!(rect.to_f64().contains(cursor), "{:?}", cursor);
+        assert_eq!(shell.cursor_position, cursor);
+        assert_eq!(shell.focused_window.as_ref(), Some(&window));
+        drop(shell);
+
+        // the pointer follows the window once, the keyboard focus never left it
+        assert_eq!(harness.take_pointer_enters(), [moved]);
+        assert!(harness.take_keyboard_focus().is_empty());
+    }
+
+    #[test]
+    fn fullscreen_window_fills_a_configured_virtual_output_of_a_rotated_output() {
+        // a portrait output split in a top and a bottom half
+        let output = testing::output("DP-1", (1920, 1080), Transform::_90, 1.0, (0, 0));
+        let config = "DP-1:0,0,1080x960;DP-1:0,960,1080x960";
+        let mut harness = Harness::new(&[output], Some(config));
+        let window = harness.map_window();
+
+        harness.state.handle_action(Action::Fullscreen);
+        harness.roundtrip();
+        let configures = harness.take_configures(window);
+        let last = configures.last().unwrap();
+        assert_eq!(last.size, (1080, 960));
+        assert!(last.has(ToplevelState::Fullscreen));
+    }
+
+    /// Workspace and index of a window, and its rectangle while mapped
+    fn slot(
+        shell: &Shell,
+        window: &Window,
+    ) -> (WorkspaceId, usize, Option<Rectangle<i32, Logical>>) {
+        let (id, index) = shell
+            .workspaces
+            .iter()
+            .find_map(|(id, workspace)| {
+                let index = workspace.windows.iter().position(|w| w == window)?;
+                Some((*id, index))
+            })
+            .unwrap();
+        (id, index, shell.space.element_geometry(window))
+    }
+
+    #[test]
+    fn unmapped_windows_come_back_to_their_slot() {
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let mut harness = Harness::new(&[output], None);
+        let tiled = harness.map_window();
+        let floating = harness.map_window();
+
+        // the second window floats where it got dragged to
+        harness.state.handle_action(Action::ToggleFloating);
+        let floating_window = harness.window(floating);
+        let mut shell = harness.state.shell.write().unwrap();
+        shell
+            .space
+            .map_element(floating_window.clone(), (300, 200), false);
+        drop(shell);
+        harness.map_window();
+        harness.arrange();
+
+        let windows = [harness.window(tiled), floating_window.clone()];
+        let slots = |harness: &Harness| {
+            let shell = harness.state.shell.read().unwrap();
+            windows
+                .iter()
+                .map(|window| slot(&shell, window))
+                .collect::<Vec<_>>()
+        };
+        let before = slots(&harness);
+
+        for index in [tiled, floating] {
+            harness.unmap_window(index);
+            let window = harness.window(index);
+            let shell = harness.state.shell.read().unwrap();
+            assert_eq!(shell.space.element_location(&window), None);
+            drop(shell);
+
+            harness.remap_window(index);
+        }
+        harness.arrange();
+
+        assert_eq!(slots(&harness), before);
+        let shell = harness.state.shell.read().unwrap();
+        assert_eq!(
+            before[1].2.map(|rect| rect.loc),
+            Some(Point::from((300, 200)))
+        );
+        assert!(shell
+            .workspaces
+            .values()
+            .any(|workspace| workspace.floating_windows.contains(&floating_window)));
+    }
+
+    #[test]
+    fn unmapped_fullscreen_window_keeps_the_fullscreen_slot() {
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let mut harness = Harness::new(&[output], None);
+        harness.map_window();
+        let fullscreen = harness.map_window();
+        harness.state.handle_action(Action::Fullscreen);
+        harness.roundtrip();
+        let window = harness.window(fullscreen);
+
+        harness.unmap_window(fullscreen);
+        harness.remap_window(fullscreen);
+        harness.arrange();
+
+        let shell = harness.state.shell.read().unwrap();
+        let (id, index, rect) = slot(&shell, &window);
+        assert_eq!(index, 1);
+        assert_eq!(rect.map(|rect| rect.loc), Some(Point::from((0, 0))));
+        assert_eq!(shell.workspaces[&id].fullscreen.as_ref(), Some(&window));
+        drop(shell);
+
+        let configures = harness.take_configures(fullscreen);
+        let last = configures.last().unwrap();
+        assert!(last.has(ToplevelState::Fullscreen));
+        assert_eq!(last.size, (1920, 1080));
+    }
+
+    /// Map `count` tiled windows on a single 1920x1080 output, returns their workspace
+    fn harness_with_windows(count: usize) -> (Harness, Output, WorkspaceId, Vec<Window>) {
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let mut harness = Harness::new(&[output.clone()], None);
+        let indices: Vec<_> = (0..count).map(|_| harness.map_window()).collect();
+        harness.arrange();
+        let windows: Vec<_> = indices.iter().map(|&index| harness.window(index)).collect();
+        let (id, _, _) = slot(&harness.state.shell.read().unwrap(), &windows[0]);
+        (harness, output, id, windows)
+    }
+
+    /// Windows of a workspace mapped in the space, in workspace order
+    fn visible_windows(shell: &Shell, id: WorkspaceId) -> Vec<Window> {
+        shell.workspaces[&id]
+            .windows
+            .iter()
+            .filter(|window| shell.space.element_location(window).is_some())
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn toggling_tabbed_mode_keeps_the_order_and_shows_the_focused_window() {
+        let (mut harness, _output, id, _windows) = harness_with_windows(3);
+        let order = harness.state.shell.read().unwrap().workspaces[&id]
+            .windows
+            .clone();
+
+        let check = |harness: &Harness| {
+            let shell = harness.state.shell.read().unwrap();
+            let workspace = &shell.workspaces[&id];
+            assert_eq!(workspace.windows, order);
+            let focused = shell.focused_window.clone().unwrap();
+            match workspace.layout_mode {
+                workspace::LayoutMode::Tabbed => {
+                    assert_eq!(workspace.active_tab(), Some(&focused));
+                    assert_eq!(visible_windows(&shell, id), [focused]);
+                }
+                workspace::LayoutMode::Tiling => assert_eq!(visible_windows(&shell, id), order),
+            }
+        };
+
+        // focus moves in both modes, each move followed by a toggle
+        for focus in [
+            Action::FocusNext,
+            Action::FocusNext,
+            Action::FocusPrev,
+            Action::FocusPrev,
+            Action::FocusPrev,
+        ] {
+            harness.state.handle_action(focus);
+            harness.arrange();
+            check(&harness);
+
+            harness.state.handle_action(Action::ToggleLayoutMode);
+            harness.arrange();
+            check(&harness);
+        }
+    }
+
+    #[test]
+    fn clicking_a_tab_shows_and_focuses_its_window() {
+        let (mut harness, output, id, _windows) = harness_with_windows(3);
+        let point = |harness: &Harness, tab: i32| {
+            let shell = harness.state.shell.read().unwrap();
+            let area = shell.workspaces[&id].available_area;
+            let tab_width = area.size().w / 3;
+            let location = area.location().as_point();
+            Point::from((
+                (location.x + tab * tab_width + tab_width / 2) as f64,
+                (location.y + workspace::TAB_HEIGHT / 2) as f64,
+            ))
+        };
+
+        // tiled windows have no tab bar
+        let first_tab = point(&harness, 0);
+        let mut shell = harness.state.shell.write().unwrap();
+        assert!(!shell.handle_tab_click(&output, first_tab));
+        drop(shell);
+
+        harness.state.handle_action(Action::ToggleLayoutMode);
+        harness.arrange();
+        let tiled: Vec<_> = harness.state.shell.read().unwrap().workspaces[&id]
+            .tiled_windows()
+            .cloned()
+            .collect();
+
+        for tab in [0, 2, 1] {
+            let location = point(&harness, tab);
+            let mut shell = harness.state.shell.write().unwrap();
+            assert!(shell.handle_tab_click(&output, location));
+            let window = &tiled[tab as usize];
+            assert_eq!(shell.workspaces[&id].active_tab(), Some(window));
+            assert_eq!(shell.focused_window.as_ref(), Some(window));
+            drop(shell);
+
+            harness.arrange();
+            let shell = harness.state.shell.read().unwrap();
+            assert_eq!(visible_windows(&shell, id), [window.clone()]);
+        }
+
+        // below the tab bar is the window, not a tab
+        let mut below = point(&harness, 0);
+        below.y += workspace::TAB_HEIGHT as f64;
+        let mut shell = harness.state.shell.write().unwrap();
+        assert!(!shell.handle_tab_click(&output, below));
+        assert_eq!(shell.workspaces[&id].active_tab(), Some(&tiled[1]));
+    }
+}
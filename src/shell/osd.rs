@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! On-screen prompts.
+//!
+//! swl draws no text: while a prompt is up, a banner runs across the top of
+//! every output, and its message goes to the notification daemon through
+//! notify-send when one is installed.
+
+use std::process::Command;
+
+use smithay::utils::{Logical, Rectangle, Size};
+use tracing::debug;
+
+/// Height of the prompt banner
+const BANNER_HEIGHT: i32 = 6;
+
+pub const BANNER_COLOR: [f32; 4] = [0.9, 0.3, 0.1, 1.0]; // orange
+
+/// Prompt currently shown, if any
+#[derive(Debug, Default)]
+pub struct Osd {
+    prompt: Option<String>,
+}
+
+impl Osd {
+    /// Show a prompt, replacing the current one, for `timeout_ms` at most
+    pub fn show(&mut self, message: String, timeout_ms: u64) {
+        notify(&message, timeout_ms);
+        self.prompt = Some(message);
+    }
+
+    /// Take the prompt down, returns whether one was shown
+    pub fn hide(&mut self) -> bool {
+        self.prompt.take().is_some()
+    }
+
+    pub fn prompt(&self) -> Option<&str> {
+        self.prompt.as_deref()
+    }
+}
+
+/// Banner of a prompt, relative to an output of the given size
+pub fn banner(output_size: Size<i32, Logical>) -> Rectangle<i32, Logical> {
+    Rectangle::from_size((output_size.w, BANNER_HEIGHT.min(output_size.h)).into())
+}
+
+/// Hand the message to the notification daemon, best effort
+fn notify(message: &str, timeout_ms: u64) {
+    // keep test runs from popping up notifications
+    if cfg!(test) {
+        return;
+    }
+    let spawned = Command::new("notify-send")
+        .args(["--app-name=swl", "--urgency=critical"])
+        .arg(format!("--expire-time={}", timeout_ms))
+        .arg(message)
+        .spawn();
+    if let Err(err) = spawned {
+        debug!("Failed to run notify-send: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banner_spans_the_top_of_the_output() {
+        assert_eq!(
+            banner((1920, 1080).into()),
+            Rectangle::from_size((1920, BANNER_HEIGHT).into())
+        );
+        assert_eq!(banner((800, 4).into()).size.h, 4);
+    }
+
+    #[test]
+    fn hiding_reports_a_shown_prompt() {
+        let mut osd = Osd::default();
+        assert!(!osd.hide());
+        osd.show("Press super+shift+e again within 3s to quit".into(), 3000);
+        assert_eq!(
+            osd.prompt(),
+            Some("Press super+shift+e again within 3s to quit")
+        );
+        assert!(osd.hide());
+        assert_eq!(osd.prompt(), None);
+    }
+}
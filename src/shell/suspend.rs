@@ -0,0 +1,266 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! xdg_toplevel suspended state.
+//!
+//! Windows that can't be seen (hidden workspaces and tags, inactive tabs,
+//! windows behind a fullscreen window) are told so through the Suspended
+//! state, letting clients stop rendering. The visibility is derived from the
+//! workspace model rather than the space, so it can be evaluated before an
+//! arrange and the state is already cleared in the configure that reveals a
+//! window.
+
+use std::collections::HashSet;
+
+use smithay::{
+    desktop::Window,
+    reexports::{
+        wayland_protocols::xdg::shell::server::xdg_toplevel,
+        wayland_server::protocol::wl_surface::WlSurface,
+    },
+    utils::IsAlive,
+};
+
+use super::workspace::LayoutMode;
+use super::Shell;
+
+impl Shell {
+    /// Windows that will be rendered once the visible workspaces are arranged
+    fn visible_windows(&self) -> HashSet<Window> {
+        let mut visible = HashSet::new();
+
+        for vout in self.virtual_output_manager.all() {
+            let Some(workspace) = vout
+                .active_workspace()
+                .and_then(|workspace_id| self.workspaces.get(&workspace_id))
+            else {
+                continue;
+            };
+
+            // only the fullscreen window gets rendered
+            if let Some(fullscreen) = workspace
+                .fullscreen
+                .as_ref()
+                .filter(|window| !workspace.unmapped_windows.contains_key(window))
+            {
+                visible.insert(fullscreen.clone());
+                continue;
+            }
+
+            visible.extend(
+                workspace
+                    .mapped_windows()
+                    .filter(|window| workspace.floating_windows.contains(window))
+                    .cloned(),
+            );
+            match workspace.layout_mode {
+                LayoutMode::Tiling => visible.extend(workspace.tiled_windows().cloned()),
+                LayoutMode::Tabbed => visible.extend(workspace.active_tab().cloned()),
+            }
+        }
+
+        visible
+    }
+
+    /// Whether a window was last told it is suspended
+    pub fn is_suspended(&self, window: &Window) -> bool {
+        self.suspended_windows.contains(window)
+    }
+
+    /// Send Suspended to windows that became invisible and clear it for revealed ones
+    ///
+    /// Runs ahead of the configures of an arrange, so the state already matches the
+    /// frame rendered next. Only changes are configured, the last sent state is
+    /// tracked per window.
+    pub fn update_suspended_states(&mut self) {
+        self.suspended_windows.retain(|window| window.alive());
+        self.suspend_deferred.retain(|window| window.alive());
+
+        let visible = self.visible_windows();
+        let windows: Vec<Window> = self
+            .workspaces
+            .values()
+            .flat_map(|workspace| workspace.windows.iter())
+            .chain(self.tag_hidden_windows())
+            .cloned()
+            .collect();
+
+        for window in windows {
+            self.update_suspended_state(&window, visible.contains(&window));
+        }
+    }
+
+    /// Suspend a hidden window that had to finish a resize first, called on its commits
+    pub fn retry_deferred_suspension(&mut self, surface: &WlSurface) {
+        // nothing waits in the common case, skip working out visibility on every commit
+        let Some(window) = self
+            .suspend_deferred
+            .iter()
+            .find(|window| {
+                window
+                    .toplevel()
+                    .is_some_and(|toplevel| toplevel.wl_surface() == surface)
+            })
+            .cloned()
+        else {
+            return;
+        };
+
+        let visible = self.visible_windows().contains(&window);
+        self.update_suspended_state(&window, visible);
+    }
+
+    fn update_suspended_state(&mut self, window: &Window, visible: bool) {
+        let Some(toplevel) = window.toplevel() else {
+            return;
+        };
+        if !toplevel.is_initial_configure_sent() {
+            return;
+        }
+
+        let resizing =
+            toplevel.current_state().size != toplevel.with_pending_state(|state| state.size);
+        let change = suspend_change(visible, self.suspended_windows.contains(window), resizing);
+        if change == Some(SuspendChange::Defer) {
+            self.suspend_deferred.insert(window.clone());
+            return;
+        }
+        self.suspend_deferred.remove(window);
+
+        let suspend = match change {
+            Some(SuspendChange::Suspend) => true,
+            Some(SuspendChange::Resume) => false,
+            _ => return,
+        };
+        toplevel.with_pending_state(|state| {
+            if suspend {
+                state.states.set(xdg_toplevel::State::Suspended);
+            } else {
+                state.states.unset(xdg_toplevel::State::Suspended);
+            }
+        });
+        toplevel.send_pending_configure();
+
+        tracing::trace!("Window suspended: {}", suspend);
+        if suspend {
+            self.suspended_windows.insert(window.clone());
+        } else {
+            self.suspended_windows.remove(window);
+        }
+    }
+}
+
+/// Change of the Suspended state a window needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SuspendChange {
+    Suspend,
+    Resume,
+    /// hidden while catching up with a resize, it has to repaint first
+    Defer,
+}
+
+/// Suspended state change from a window's visibility in the next frame
+fn suspend_change(visible: bool, suspended: bool, resizing: bool) -> Option<SuspendChange> {
+    match (visible, suspended) {
+        (true, true) => Some(SuspendChange::Resume),
+        (false, false) if resizing => Some(SuspendChange::Defer),
+        (false, false) => Some(SuspendChange::Suspend),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::Transform;
+    use wayland_protocols::xdg::shell::client::xdg_toplevel::State as ToplevelState;
+
+    use super::*;
+    use crate::{
+        input::keybindings::Action,
+        utils::{test_client::Harness, testing},
+    };
+
+    #[test]
+    fn hidden_window_is_suspended() {
+        assert_eq!(
+            suspend_change(false, false, false),
+            Some(SuspendChange::Suspend)
+        );
+    }
+
+    #[test]
+    fn revealed_window_resumes_even_while_resizing() {
+        assert_eq!(
+            suspend_change(true, true, false),
+            Some(SuspendChange::Resume)
+        );
+        assert_eq!(
+            suspend_change(true, true, true),
+            Some(SuspendChange::Resume)
+        );
+    }
+
+    #[test]
+    fn resizing_window_is_suspended_after_repainting() {
+        assert_eq!(
+            suspend_change(false, false, true),
+            Some(SuspendChange::Defer)
+        );
+        // its commit with the new size lets the retry go through
+        assert_eq!(
+            suspend_change(false, false, false),
+            Some(SuspendChange::Suspend)
+        );
+    }
+
+    #[test]
+    fn unchanged_visibility_sends_nothing() {
+        assert_eq!(suspend_change(true, false, false), None);
+        assert_eq!(suspend_change(true, false, true), None);
+        assert_eq!(suspend_change(false, true, false), None);
+        assert_eq!(suspend_change(false, true, true), None);
+    }
+
+    #[test]
+    fn hide_and_reveal_configure_suspended_once_each() {
+        let output = testing::output("DP-1", (1920, 1080), Transform::Normal, 1.0, (0, 0));
+        let mut harness = Harness::new(&[output], None);
+        let index = harness.map_window();
+        harness.arrange();
+        let size = harness.take_configures(index).last().unwrap().size;
+        let window = harness.window(index);
+
+        for _ in 0..2 {
+            harness
+                .state
+                .handle_action(Action::SwitchToWorkspace("2".into()));
+            harness.arrange();
+            let configures = harness.take_configures(index);
+            assert_eq!(configures.len(), 1, "{:?}", configures);
+            assert!(configures[0].has(ToplevelState::Suspended));
+            assert_eq!(configures[0].size, size);
+            assert!(harness.state.shell.read().unwrap().is_suspended(&window));
+
+            harness
+                .state
+                .handle_action(Action::SwitchToWorkspace("1".into()));
+            // the configure revealing the window is sent ahead of the frame showing it
+            harness
+                .state
+                .shell
+                .write()
+                .unwrap()
+                .update_suspended_states();
+            harness.roundtrip();
+            let configures = harness.take_configures(index);
+            assert_eq!(configures.len(), 1, "{:?}", configures);
+            assert!(!configures[0].has(ToplevelState::Suspended));
+            assert_eq!(configures[0].size, size);
+
+            // arranging the revealed workspace has nothing left to tell the window
+            harness.arrange();
+            let configures = harness.take_configures(index);
+            assert!(configures.is_empty(), "{:?}", configures);
+            assert!(!harness.state.shell.read().unwrap().is_suspended(&window));
+        }
+    }
+}
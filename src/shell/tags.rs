@@ -0,0 +1,658 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Optional dwm-style tag layer on top of workspaces.
+//!
+//! Every window carries a tag bitmask and every virtual output a view mask.
+//! A virtual output in tag view shows a synthetic workspace holding the
+//! windows whose mask intersects the view mask, so mapping, focus and
+//! arrangement go through the regular workspace machinery. Entering the view
+//! gathers the windows of every workspace last shown on the virtual output,
+//! leaving it returns them to the workspace they came from. Windows that are
+//! part of the view but don't match the mask are parked outside of any
+//! workspace, which keeps every window in at most one workspace.
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use smithay::{
+    desktop::Window,
+    output::Output,
+    utils::IsAlive,
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceData},
+};
+
+use super::virtual_output::VirtualOutputId;
+use super::workspace::{UnmappedWindow, Workspace, WorkspaceId};
+use super::{unset_fullscreen, FullscreenTransfer, Shell};
+
+/// Number of available tags
+pub const TAG_COUNT: u32 = 9;
+
+/// Mask of windows that never got tagged
+pub const DEFAULT_TAG_MASK: u32 = 1;
+
+/// Bitmask covering all valid tags
+const ALL_TAGS: u32 = (1 << TAG_COUNT) - 1;
+
+/// Prefix of synthetic workspace names, not reachable through workspace switching
+const TAG_VIEW_PREFIX: &str = "tags:";
+
+/// Mask for a single 1-based tag number
+pub fn tag_mask(tag: u32) -> Option<u32> {
+    (1..=TAG_COUNT).contains(&tag).then(|| 1 << (tag - 1))
+}
+
+/// A tagged window that is currently not visible in its view
+#[derive(Debug)]
+struct HiddenWindow {
+    window: Window,
+    floating: bool,
+    /// set while its client has it unmapped
+    unmapped: Option<UnmappedWindow>,
+}
+
+/// Tag view state of a single virtual output
+#[derive(Debug)]
+pub struct TagView {
+    /// Synthetic workspace shown while the view is active
+    workspace_id: WorkspaceId,
+    /// Workspace that was active before entering the view, shown again when leaving
+    previous_workspace: Option<WorkspaceId>,
+    /// Workspace each gathered window came from, windows without one go to the previous workspace
+    origins: HashMap<Window, WorkspaceId>,
+    /// Windows of this view that don't match the view mask
+    hidden: Vec<HiddenWindow>,
+}
+
+/// Tag rules from SWL_TAG_RULES, e.g. "firefox:2,mpv:3+4"
+fn tag_rules() -> &'static [(String, u32)] {
+    static RULES: OnceLock<Vec<(String, u32)>> = OnceLock::new();
+    RULES.get_or_init(|| {
+        std::env::var("SWL_TAG_RULES")
+            .map(|spec| parse_tag_rules(&spec))
+            .unwrap_or_default()
+    })
+}
+
+fn parse_tag_rules(spec: &str) -> Vec<(String, u32)> {
+    spec.split(',')
+        .filter_map(|rule| {
+            let (app_id, tags) = rule.trim().rsplit_once(':')?;
+            let mask = tags
+                .split('+')
+                .map(|tag| tag.trim().parse::<u32>().ok().and_then(tag_mask))
+                .try_fold(0, |mask, tag| tag.map(|tag| mask | tag));
+            match mask {
+                Some(mask) if mask != 0 && !app_id.is_empty() => Some((app_id.to_string(), mask)),
+                _ => {
+                    tracing::warn!("Ignoring invalid tag rule: {}", rule);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Mask configured for the window's app_id, if any rule matches
+fn rule_mask(window: &Window) -> Option<u32> {
+    let rules = tag_rules();
+    if rules.is_empty() {
+        return None;
+    }
+
+    let app_id = with_states(window.toplevel()?.wl_surface(), |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .and_then(|data| data.lock().unwrap().app_id.clone())
+    })?;
+
+    rules
+        .iter()
+        .find(|(rule_app_id, _)| *rule_app_id == app_id)
+        .map(|(_, mask)| *mask)
+}
+
+/// Workspaces a tag view gathers its windows from, the active one first
+///
+/// A hidden workspace belongs to the virtual output it was last shown on,
+/// synthetic workspaces of other tag views are left alone.
+fn tag_view_sources(
+    workspaces: &HashMap<WorkspaceId, Workspace>,
+    vout_id: VirtualOutputId,
+    active: Option<WorkspaceId>,
+) -> Vec<WorkspaceId> {
+    let mut hidden: Vec<(&str, WorkspaceId)> = workspaces
+        .iter()
+        .filter(|(id, workspace)| {
+            Some(**id) != active
+                && workspace.virtual_output_id == Some(vout_id)
+                && !workspace.name.starts_with(TAG_VIEW_PREFIX)
+        })
+        .map(|(id, workspace)| (workspace.name.as_str(), *id))
+        .collect();
+    hidden.sort_by(|a, b| a.0.cmp(b.0));
+
+    active
+        .filter(|id| workspaces.contains_key(id))
+        .into_iter()
+        .chain(hidden.into_iter().map(|(_, id)| id))
+        .collect()
+}
+
+impl Shell {
+    /// Tag mask of a window
+    pub fn window_tags(&self, window: &Window) -> u32 {
+        self.window_tags
+            .get(window)
+            .copied()
+            .unwrap_or(DEFAULT_TAG_MASK)
+    }
+
+    /// Whether the virtual output currently shows its tag view
+    pub fn is_tag_view(&self, vout_id: VirtualOutputId) -> bool {
+        self.tag_views.contains_key(&vout_id)
+    }
+
+    /// Whether the workspace is the synthetic workspace of a tag view
+    pub fn is_tag_view_workspace(&self, workspace_id: WorkspaceId) -> bool {
+        self.tag_views
+            .values()
+            .any(|view| view.workspace_id == workspace_id)
+    }
+
+    /// Virtual output the tag actions apply to: the focused one, else the one under the cursor
+    fn tag_target_virtual_output(&self) -> Option<VirtualOutputId> {
+        self.focused_virtual_output_id
+            .or_else(|| self.virtual_output_at_point(self.cursor_position))
+    }
+
+    /// Tag view containing a window, visible or hidden
+    fn tag_view_of_window(&self, window: &Window) -> Option<VirtualOutputId> {
+        self.tag_views.iter().find_map(|(vout_id, view)| {
+            let visible = self
+                .workspaces
+                .get(&view.workspace_id)
+                .is_some_and(|workspace| workspace.windows.contains(window));
+            let hidden = view.hidden.iter().any(|hidden| &hidden.window == window);
+            (visible || hidden).then_some(*vout_id)
+        })
+    }
+
+    /// Assign the initial tags of a window newly added to a virtual output
+    pub(super) fn assign_initial_tags(&mut self, window: &Window, vout_id: VirtualOutputId) {
+        let mask = rule_mask(window).or_else(|| {
+            self.virtual_output_manager
+                .get(vout_id)
+                .filter(|_| self.is_tag_view(vout_id))
+                .map(|vout| vout.view_mask)
+        });
+
+        if let Some(mask) = mask {
+            self.window_tags.insert(window.clone(), mask);
+        }
+    }
+
+    /// Windows parked by tag views, they are not part of any workspace
+    pub(super) fn tag_hidden_windows(&self) -> impl Iterator<Item = &Window> {
+        self.tag_views
+            .values()
+            .flat_map(|view| view.hidden.iter().map(|hidden| &hidden.window))
+    }
+
+    /// Unmap state of a window parked by a tag view, None if it isn't parked
+    pub(super) fn tag_hidden_unmapped_mut(
+        &mut self,
+        window: &Window,
+    ) -> Option<&mut Option<UnmappedWindow>> {
+        self.tag_views
+            .values_mut()
+            .flat_map(|view| view.hidden.iter_mut())
+            .find(|hidden| &hidden.window == window)
+            .map(|hidden| &mut hidden.unmapped)
+    }
+
+    /// Forget a window that is being removed from all workspaces
+    pub(super) fn remove_from_tag_views(&mut self, window: &Window) {
+        for view in self.tag_views.values_mut() {
+            view.hidden.retain(|hidden| &hidden.window != window);
+        }
+    }
+
+    /// Drop tag state of dead windows
+    pub(super) fn refresh_tags(&mut self) {
+        self.window_tags.retain(|window, _| window.alive());
+        for view in self.tag_views.values_mut() {
+            view.hidden.retain(|hidden| hidden.window.alive());
+        }
+    }
+
+    /// Enter or leave the tag view on the focused virtual output
+    ///
+    /// Returns the physical outputs needing a redraw.
+    pub fn toggle_tag_view(&mut self) -> Vec<Output> {
+        let Some(vout_id) = self.tag_target_virtual_output() else {
+            return Vec::new();
+        };
+
+        if self.is_tag_view(vout_id) {
+            self.leave_tag_view(vout_id);
+        } else {
+            self.enter_tag_view(vout_id);
+        }
+
+        self.physical_outputs_of(vout_id)
+    }
+
+    /// Switch a virtual output to its tag view, taking over the windows of its workspaces
+    fn enter_tag_view(&mut self, vout_id: VirtualOutputId) {
+        let Some(vout) = self.virtual_output_manager.get(vout_id) else {
+            return;
+        };
+        let previous_workspace = vout.active_workspace();
+        let view_mask = vout.view_mask;
+        let geometry = vout.logical_geometry;
+
+        let workspace_id =
+            self.find_or_create_workspace_id(&format!("{}{}", TAG_VIEW_PREFIX, vout_id.0));
+
+        // move the windows over, untagged ones join the current view so nothing disappears
+        let mut origins = HashMap::new();
+        let mut focus_stacks = Vec::new();
+        for source_id in tag_view_sources(&self.workspaces, vout_id, previous_workspace) {
+            let Some(source) = self.workspaces.get(&source_id) else {
+                continue;
+            };
+            let (windows, focus_stack) = (source.windows.clone(), source.focus_stack.clone());
+            let shown = Some(source_id) == previous_workspace;
+
+            for window in &windows {
+                self.window_tags.entry(window.clone()).or_insert(view_mask);
+                self.migrate_window(source_id, workspace_id, window);
+                origins.insert(window.clone(), source_id);
+
+                // windows of hidden workspaces aren't in the space yet
+                let mapped = self
+                    .workspaces
+                    .get(&workspace_id)
+                    .is_some_and(|workspace| !workspace.unmapped_windows.contains_key(window));
+                if !shown && mapped {
+                    self.space.map_element(window.clone(), (0, 0), false);
+                }
+            }
+            focus_stacks.push(focus_stack);
+        }
+
+        // the active workspace comes first, its focus history is the most recent one
+        for focus_stack in focus_stacks.iter().rev() {
+            self.restore_focus_stack(workspace_id, focus_stack);
+        }
+
+        if let Some(vout) = self.virtual_output_manager.get_mut(vout_id) {
+            vout.set_active_workspace(Some(workspace_id));
+        }
+        if let Some(workspace) = self.workspaces.get_mut(&workspace_id) {
+            workspace.update_output_geometry(geometry);
+            workspace.virtual_output_id = Some(vout_id);
+        }
+
+        self.tag_views.insert(
+            vout_id,
+            TagView {
+                workspace_id,
+                previous_workspace,
+                origins,
+                hidden: Vec::new(),
+            },
+        );
+
+        tracing::info!(
+            "Virtual output {:?} entered tag view with mask {:#b}",
+            vout_id,
+            view_mask
+        );
+
+        self.refresh_tag_view(vout_id);
+    }
+
+    /// Switch a virtual output back to workspace mode
+    ///
+    /// All windows of the view, visible or not, return to the workspace they were
+    /// gathered from, and the workspace active before entering the view is shown again.
+    pub(super) fn leave_tag_view(&mut self, vout_id: VirtualOutputId) {
+        let Some(view) = self.tag_views.remove(&vout_id) else {
+            return;
+        };
+
+        let target_name = view
+            .previous_workspace
+            .and_then(|id| self.get_workspace_name(id))
+            .unwrap_or_else(|| "1".to_string());
+        let target_id = self.find_or_create_workspace_id(&target_name);
+
+        // origins may have been closed meanwhile, windows opened in the view have none
+        let destination = |workspaces: &HashMap<WorkspaceId, Workspace>, window: &Window| {
+            view.origins
+                .get(window)
+                .copied()
+                .filter(|origin| workspaces.contains_key(origin))
+                .unwrap_or(target_id)
+        };
+
+        let (windows, focus_stack) = self
+            .workspaces
+            .get(&view.workspace_id)
+            .map(|workspace| (workspace.windows.clone(), workspace.focus_stack.clone()))
+            .unwrap_or_default();
+        let mut destinations = vec![target_id];
+        for window in &windows {
+            let to = destination(&self.workspaces, window);
+            self.migrate_window(view.workspace_id, to, window);
+            if to != target_id && !self.is_workspace_shown(to) {
+                self.space.unmap_elem(window);
+            }
+            if !destinations.contains(&to) {
+                destinations.push(to);
+            }
+        }
+        for workspace_id in destinations {
+            self.restore_focus_stack(workspace_id, &focus_stack);
+        }
+
+        for hidden in view.hidden {
+            let to = destination(&self.workspaces, &hidden.window);
+            if to != target_id && self.is_workspace_shown(to) && hidden.unmapped.is_none() {
+                self.space.map_element(hidden.window.clone(), (0, 0), false);
+            }
+            if let Some(workspace) = self.workspaces.get_mut(&to) {
+                workspace.add_window(hidden.window.clone(), hidden.floating);
+                if let Some(unmapped) = hidden.unmapped {
+                    workspace.unmapped_windows.insert(hidden.window, unmapped);
+                }
+            }
+        }
+
+        // maps the migrated windows and unmaps the now empty synthetic workspace
+        self.switch_workspace_on_virtual(vout_id, &target_name);
+
+        if let Some(name) = self.get_workspace_name(view.workspace_id) {
+            self.workspace_names.remove(&name);
+        }
+        self.workspaces.remove(&view.workspace_id);
+
+        tracing::info!(
+            "Virtual output {:?} left tag view, windows returned to their workspaces",
+            vout_id
+        );
+    }
+
+    /// Whether a workspace is active on any virtual output
+    fn is_workspace_shown(&self, workspace_id: WorkspaceId) -> bool {
+        self.virtual_output_manager
+            .all()
+            .any(|vout| vout.active_workspace() == Some(workspace_id))
+    }
+
+    /// Move a window between workspaces, keeping its floating and fullscreen state
+    pub(super) fn migrate_window(&mut self, from: WorkspaceId, to: WorkspaceId, window: &Window) {
+        let Some(source) = self.workspaces.get_mut(&from) else {
+            return;
+        };
+        let floating = source.floating_windows.contains(window);
+        let fullscreen = FullscreenTransfer::capture(source, window);
+        let unmapped = source.unmapped_windows.get(window).copied();
+        source.remove_window(window);
+
+        let Some(destination) = self.workspaces.get_mut(&to) else {
+            return;
+        };
+        destination.add_window(window.clone(), floating);
+        if let Some(location) = unmapped {
+            destination
+                .unmapped_windows
+                .insert(window.clone(), location);
+        }
+
+        if let Some(fullscreen) = fullscreen {
+            self.transfer_fullscreen(window, fullscreen, to);
+        }
+    }
+
+    /// Replay a focus history on a workspace that just received migrated windows
+    fn restore_focus_stack(&mut self, workspace_id: WorkspaceId, focus_stack: &[Window]) {
+        if let Some(workspace) = self.workspaces.get_mut(&workspace_id) {
+            for window in focus_stack {
+                if workspace.windows.contains(window) {
+                    workspace.append_focus(window);
+                }
+            }
+        }
+    }
+
+    /// Recompute which windows of a tag view are visible after a mask change
+    pub(super) fn refresh_tag_view(&mut self, vout_id: VirtualOutputId) {
+        let Some(view_mask) = self
+            .virtual_output_manager
+            .get(vout_id)
+            .map(|vout| vout.view_mask)
+        else {
+            return;
+        };
+        let Some(view) = self.tag_views.get_mut(&vout_id) else {
+            return;
+        };
+        let Some(workspace) = self.workspaces.get_mut(&view.workspace_id) else {
+            return;
+        };
+        let window_tags = &self.window_tags;
+        let visible = |window: &Window| {
+            window_tags.get(window).copied().unwrap_or(DEFAULT_TAG_MASK) & view_mask != 0
+        };
+
+        // park windows that dropped out of the view
+        let newly_hidden: Vec<Window> = workspace
+            .windows
+            .iter()
+            .filter(|&window| !visible(window))
+            .cloned()
+            .collect();
+        for window in newly_hidden {
+            let floating = workspace.floating_windows.contains(&window);
+            let unmapped = workspace.unmapped_windows.get(&window).copied();
+            if workspace.fullscreen.as_ref() == Some(&window) {
+                unset_fullscreen(&window);
+            }
+            workspace.remove_window(&window);
+            self.space.unmap_elem(&window);
+            view.hidden.push(HiddenWindow {
+                window,
+                floating,
+                unmapped,
+            });
+        }
+
+        // bring back parked windows matching the view, in the order they were parked
+        let (shown, still_hidden): (Vec<_>, Vec<_>) = std::mem::take(&mut view.hidden)
+            .into_iter()
+            .partition(|hidden| visible(&hidden.window));
+        view.hidden = still_hidden;
+        for hidden in shown {
+            workspace.add_window(hidden.window.clone(), hidden.floating);
+            match hidden.unmapped {
+                // keeps its slot, mapped once the client has content again
+                Some(unmapped) => {
+                    workspace.unmapped_windows.insert(hidden.window, unmapped);
+                }
+                None => self.space.map_element(hidden.window, (0, 0), false),
+            }
+        }
+
+        workspace.needs_arrange = true;
+
+        // focus must not stay on a window that just got hidden
+        let focus_hidden = self
+            .focused_window
+            .as_ref()
+            .is_some_and(|focused| view.hidden.iter().any(|hidden| &hidden.window == focused));
+        if focus_hidden {
+            self.refresh_focus();
+        }
+    }
+
+    /// Replace the view mask of the focused virtual output with a single tag
+    pub fn view_tag(&mut self, tag: u32) -> Vec<Output> {
+        self.update_view_mask(tag, |_, bit| bit)
+    }
+
+    /// Toggle a tag in the view mask of the focused virtual output
+    pub fn toggle_view_tag(&mut self, tag: u32) -> Vec<Output> {
+        self.update_view_mask(tag, |mask, bit| mask ^ bit)
+    }
+
+    fn update_view_mask(&mut self, tag: u32, f: impl FnOnce(u32, u32) -> u32) -> Vec<Output> {
+        let (Some(bit), Some(vout_id)) = (tag_mask(tag), self.tag_target_virtual_output()) else {
+            return Vec::new();
+        };
+        let Some(vout) = self.virtual_output_manager.get_mut(vout_id) else {
+            return Vec::new();
+        };
+
+        // an empty view would hide everything with no way to tell, keep at least one tag
+        let mask = f(vout.view_mask, bit) & ALL_TAGS;
+        if mask == 0 || mask == vout.view_mask {
+            return Vec::new();
+        }
+        vout.view_mask = mask;
+        tracing::debug!("View mask of {:?} set to {:#b}", vout_id, mask);
+
+        if !self.is_tag_view(vout_id) {
+            return Vec::new();
+        }
+        self.refresh_tag_view(vout_id);
+        self.physical_outputs_of(vout_id)
+    }
+
+    /// Replace the tags of the focused window with a single tag
+    pub fn tag_window(&mut self, tag: u32) -> Vec<Output> {
+        self.update_window_tags(tag, |_, bit| bit)
+    }
+
+    /// Toggle a tag on the focused window
+    pub fn toggle_window_tag(&mut self, tag: u32) -> Vec<Output> {
+        self.update_window_tags(tag, |mask, bit| mask ^ bit)
+    }
+
+    fn update_window_tags(&mut self, tag: u32, f: impl FnOnce(u32, u32) -> u32) -> Vec<Output> {
+        let (Some(bit), Some(window)) = (tag_mask(tag), self.focused_window.clone()) else {
+            return Vec::new();
+        };
+
+        // a window without tags could never be shown again
+        let current = self.window_tags(&window);
+        let mask = f(current, bit) & ALL_TAGS;
+        if mask == 0 || mask == current {
+            return Vec::new();
+        }
+        self.window_tags.insert(window.clone(), mask);
+        tracing::debug!("Window tags set to {:#b}", mask);
+
+        let Some(vout_id) = self.tag_view_of_window(&window) else {
+            return Vec::new();
+        };
+        self.refresh_tag_view(vout_id);
+        self.physical_outputs_of(vout_id)
+    }
+
+    /// Physical outputs backing a virtual output
+    pub(super) fn physical_outputs_of(&self, vout_id: VirtualOutputId) -> Vec<Output> {
+        self.virtual_output_manager
+            .get(vout_id)
+            .map(|vout| {
+                vout.regions
+                    .iter()
+                    .map(|region| region.physical_output.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(name: &str, vout_id: Option<VirtualOutputId>) -> Workspace {
+        let mut workspace = Workspace::new(name.to_string());
+        workspace.virtual_output_id = vout_id;
+        workspace
+    }
+
+    #[test]
+    fn tag_masks() {
+        assert_eq!(tag_mask(0), None);
+        assert_eq!(tag_mask(1), Some(0b1));
+        assert_eq!(tag_mask(9), Some(0b1_0000_0000));
+        assert_eq!(tag_mask(10), None);
+    }
+
+    #[test]
+    fn tag_rules_parse() {
+        assert_eq!(
+            parse_tag_rules("firefox:2, mpv:3+4,org.gnome.Nautilus:1"),
+            vec![
+                ("firefox".to_string(), 0b10),
+                ("mpv".to_string(), 0b1100),
+                ("org.gnome.Nautilus".to_string(), 0b1),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_tag_rules_are_skipped() {
+        assert_eq!(
+            parse_tag_rules("nocolon,:1,zero:0,ten:10,partly:2+x,ok:9"),
+            vec![("ok".to_string(), 0b1_0000_0000)]
+        );
+    }
+
+    #[test]
+    fn tag_view_gathers_every_workspace_of_the_virtual_output() {
+        let left = VirtualOutputId(0);
+        let right = VirtualOutputId(1);
+        let workspaces = HashMap::from([
+            (WorkspaceId(1), workspace("1", Some(left))),
+            (WorkspaceId(2), workspace("5", Some(left))),
+            (WorkspaceId(3), workspace("2", Some(left))),
+            (WorkspaceId(4), workspace("3", Some(right))),
+            (WorkspaceId(5), workspace("4", None)),
+            (WorkspaceId(6), workspace("tags:1", Some(left))),
+        ]);
+
+        // the active workspace leads, hidden ones follow by name
+        assert_eq!(
+            tag_view_sources(&workspaces, left, Some(WorkspaceId(2))),
+            vec![WorkspaceId(2), WorkspaceId(1), WorkspaceId(3)]
+        );
+        assert_eq!(
+            tag_view_sources(&workspaces, right, Some(WorkspaceId(4))),
+            vec![WorkspaceId(4)]
+        );
+    }
+
+    #[test]
+    fn tag_view_without_active_workspace_gathers_hidden_ones() {
+        let vout_id = VirtualOutputId(0);
+        let workspaces = HashMap::from([
+            (WorkspaceId(1), workspace("1", Some(vout_id))),
+            (WorkspaceId(2), workspace("2", Some(vout_id))),
+        ]);
+        assert_eq!(
+            tag_view_sources(&workspaces, vout_id, None),
+            vec![WorkspaceId(1), WorkspaceId(2)]
+        );
+        // a stale active workspace is skipped
+        assert_eq!(
+            tag_view_sources(&workspaces, vout_id, Some(WorkspaceId(7))),
+            vec![WorkspaceId(1), WorkspaceId(2)]
+        );
+    }
+}
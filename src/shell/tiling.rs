@@ -10,6 +10,152 @@ use tracing::debug;
 use crate::shell::BORDER_WIDTH;
 use crate::utils::coordinates::VirtualOutputRelativeRect;
 
+/// Direction in which the master area is split from the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// Master on the left, stack on the right
+    Horizontal,
+    /// Master on top, stack at the bottom
+    Vertical,
+}
+
+impl Orientation {
+    /// Default orientation for an output: portrait outputs stack vertically
+    pub fn default_for(output_size: Size<i32, Logical>) -> Self {
+        if output_size.h > output_size.w {
+            Orientation::Vertical
+        } else {
+            Orientation::Horizontal
+        }
+    }
+}
+
+/// Smallest width and height a tiled window is given while the area has room for it
+pub const MIN_TILE_SIZE: i32 = 64;
+
+/// Compute the master/stack layout for `count` windows in `area`
+///
+/// Slots are returned in window order: masters first, then the stack. `gap`
+/// separates windows from each other and from the area's edges, `weights`
+/// gives each window's share of its column (missing entries weigh 1.0) and
+/// rounding leftovers go to the first window of a column. Windows are kept at
+/// [`MIN_TILE_SIZE`] or more as long as the area fits them, past that they
+/// share what is left equally. With `n_master` at 0 the master area stays
+/// empty and the stack keeps its place on the right.
+pub fn tile(
+    area: Rectangle<i32, Logical>,
+    count: usize,
+    master_factor: f32,
+    n_master: usize,
+    weights: &[f32],
+    gap: i32,
+    orientation: Orientation,
+) -> Vec<Rectangle<i32, Logical>> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    // the layout is computed for master-left and transposed for master-top
+    let vertical = orientation == Orientation::Vertical;
+    let (area_x, area_y, area_width, area_height) = if vertical {
+        (area.loc.y, area.loc.x, area.size.h, area.size.w)
+    } else {
+        (area.loc.x, area.loc.y, area.size.w, area.size.h)
+    };
+    let place = |x: i32, y: i32, w: i32, h: i32| {
+        // an area smaller than its gaps still never hands out empty sizes
+        let (w, h) = (w.max(1), h.max(1));
+        if vertical {
+            Rectangle::new(Point::from((y, x)), Size::from((h, w)))
+        } else {
+            Rectangle::new(Point::from((x, y)), Size::from((w, h)))
+        }
+    };
+
+    // calculate space available for windows (excluding all gaps)
+    let (master_width, stack_width) = if count > n_master {
+        // two columns: left, middle and right gap
+        let total_window_space = area_width - 3 * gap;
+
+        // master gets its portion, rounded up (gets remainder pixel)
+        let mut master_w = (total_window_space as f32 * master_factor).ceil() as i32;
+        if total_window_space >= 2 * MIN_TILE_SIZE {
+            master_w = master_w.clamp(MIN_TILE_SIZE, total_window_space - MIN_TILE_SIZE);
+        }
+
+        (master_w, total_window_space - master_w)
+    } else {
+        // single column: left and right gap
+        let window_w = area_width - 2 * gap;
+        (window_w, 0)
+    };
+
+    let master_count = count.min(n_master);
+    let stack_x = area_x + gap + master_width + gap;
+
+    let mut slots = Vec::with_capacity(count);
+    for (column, x, width) in [
+        (0..master_count, area_x + gap, master_width),
+        (master_count..count, stack_x, stack_width),
+    ] {
+        if column.is_empty() {
+            continue;
+        }
+
+        let column_weights: Vec<f32> = column
+            .clone()
+            .map(|i| weights.get(i).copied().unwrap_or(1.0).max(0.0))
+            .collect();
+        let total_height = area_height - (column.len() as i32 + 1) * gap;
+
+        let mut y = area_y + gap;
+        for h in split_weighted(total_height, &column_weights) {
+            slots.push(place(x, y, width, h));
+            y += h + gap;
+        }
+    }
+
+    slots
+}
+
+/// Split `total` pixels by weight, the first entry takes the rounding leftover
+///
+/// Entries below [`MIN_TILE_SIZE`] are grown back to it at the expense of the
+/// largest ones, unless `total` can't fit every entry at the minimum.
+fn split_weighted(total: i32, weights: &[f32]) -> Vec<i32> {
+    let sum: f32 = weights.iter().sum();
+    let mut sizes: Vec<i32> = if sum > 0.0 {
+        weights
+            .iter()
+            .map(|weight| (total as f32 * weight / sum).floor() as i32)
+            .collect()
+    } else {
+        vec![total / weights.len() as i32; weights.len()]
+    };
+
+    let leftover = total - sizes.iter().sum::<i32>();
+    if let Some(first) = sizes.first_mut() {
+        *first += leftover;
+    }
+
+    if total < MIN_TILE_SIZE * sizes.len() as i32 {
+        return sizes;
+    }
+    let mut missing = 0;
+    for size in sizes.iter_mut().filter(|size| **size < MIN_TILE_SIZE) {
+        missing += MIN_TILE_SIZE - *size;
+        *size = MIN_TILE_SIZE;
+    }
+    while missing > 0 {
+        // the total fits every entry at the minimum, so some entry is above it
+        let largest = sizes.iter_mut().max().unwrap();
+        let taken = (*largest - MIN_TILE_SIZE).min(missing);
+        *largest -= taken;
+        missing -= taken;
+    }
+    sizes
+}
+
 /// Tiling layout implementation inspired by dwm/dwl
 #[derive(Debug)]
 pub struct TilingLayout {
@@ -21,6 +167,12 @@ pub struct TilingLayout {
 
     /// Available area for tiling (excluding exclusive zones)
     available_area: VirtualOutputRelativeRect,
+
+    /// Master/stack split orientation
+    orientation: Orientation,
+
+    /// Orientation was set by the user and must not follow the area's aspect ratio
+    orientation_overridden: bool,
 }
 
 impl TilingLayout {
@@ -49,145 +201,36 @@ impl TilingLayout {
             master_factor,
             n_master,
             available_area: available_area_rect,
+            // follows the virtual output once the workspace is shown
+            orientation: Orientation::Horizontal,
+            orientation_overridden: false,
         }
     }
 
     /// Calculate positions for all windows according to the tiling layout
     /// Returns vec of (Window, Rectangle) for positioning
     pub fn tile(&self, windows: &[Window]) -> Vec<(Window, Rectangle<i32, Logical>)> {
-        if windows.is_empty() {
-            return Vec::new();
-        }
-
-        let n = windows.len();
-        let mut positions = Vec::with_capacity(n);
-
-        // use the available area's position and size
-        let area_x = self.available_area.location().as_point().x;
-        let area_y = self.available_area.location().as_point().y;
-        let area_width = self.available_area.size().w;
-        let area_height = self.available_area.size().h;
-
-        // calculate space available for windows (excluding all borders)
-        let (master_window_width, stack_window_width) = if n > self.n_master {
-            // we have 2 columns, so need 3 borders: left, middle, right
-            let total_window_space = area_width - 3 * BORDER_WIDTH;
-
-            // master gets its portion, rounded up (gets remainder pixel)
-            let master_w = ((total_window_space as f32 * self.master_factor).ceil() as i32).max(1);
-            let stack_w = (total_window_space - master_w).max(1);
-
-            (master_w, stack_w)
-        } else {
-            // single column, just 2 borders: left and right
-            let window_w = area_width - 2 * BORDER_WIDTH;
-            (window_w, 0)
-        };
-
-        // tile master windows (left side)
-        let master_count = n.min(self.n_master);
-
-        // calculate vertical space for master windows
-        let total_height_space = area_height - (master_count + 1) as i32 * BORDER_WIDTH;
-
-        for i in 0..master_count {
-            // calculate window position
-            let x = area_x + BORDER_WIDTH;
-
-            // calculate height for this window - first window gets remainder pixels
-            let base_height = total_height_space / master_count as i32;
-            let remainder = total_height_space % master_count as i32;
-            let h = if i == 0 {
-                base_height + remainder
-            } else {
-                base_height
-            };
-
-            // calculate Y position
-            let y = if i == 0 {
-                area_y + BORDER_WIDTH
-            } else {
-                // sum heights of previous windows plus borders
-                let mut y_pos = area_y + BORDER_WIDTH;
-                for j in 0..i {
-                    let prev_h = if j == 0 {
-                        base_height + remainder
-                    } else {
-                        base_height
-                    };
-                    y_pos += prev_h + BORDER_WIDTH;
-                }
-                y_pos
-            };
-
-            let w = master_window_width;
-
-            // create virtual-output-relative rectangle for this window
-            let rect = Rectangle::new(
-                Point::from((x, y)),              // relative to virtual output origin
-                Size::from((w.max(1), h.max(1))), // ensure minimum size
-            );
-            positions.push((windows[i].clone(), rect));
-        }
-
-        // tile stack windows (right side)
-        if n > self.n_master {
-            let stack_count = n - self.n_master;
-
-            // calculate vertical space for stack windows
-            let total_height_space = area_height - (stack_count + 1) as i32 * BORDER_WIDTH;
-
-            for i in 0..stack_count {
-                let stack_i = i + self.n_master;
-
-                // stack X position: master windows + left border + master width + middle border
-                let x = area_x + BORDER_WIDTH + master_window_width + BORDER_WIDTH;
-
-                // calculate height for this window - first window gets remainder pixels
-                let base_height = total_height_space / stack_count as i32;
-                let remainder = total_height_space % stack_count as i32;
-                let h = if i == 0 {
-                    base_height + remainder
-                } else {
-                    base_height
-                };
-
-                // calculate Y position
-                let y = if i == 0 {
-                    area_y + BORDER_WIDTH
-                } else {
-                    // sum heights of previous windows plus borders
-                    let mut y_pos = area_y + BORDER_WIDTH;
-                    for j in 0..i {
-                        let prev_h = if j == 0 {
-                            base_height + remainder
-                        } else {
-                            base_height
-                        };
-                        y_pos += prev_h + BORDER_WIDTH;
-                    }
-                    y_pos
-                };
-
-                let w = stack_window_width;
-
-                // create virtual-output-relative rectangle for this window
-                let rect = Rectangle::new(
-                    Point::from((x, y)),              // relative to virtual output origin
-                    Size::from((w.max(1), h.max(1))), // ensure minimum size
-                );
-                positions.push((windows[stack_i].clone(), rect));
-            }
-        }
+        // every window weighs the same until per-window weights are configurable
+        let weights = vec![1.0; windows.len()];
+        let slots = tile(
+            self.available_area.as_rectangle(),
+            windows.len(),
+            self.master_factor,
+            self.n_master,
+            &weights,
+            BORDER_WIDTH,
+            self.orientation,
+        );
 
         debug!(
-            "Tiled {} windows (master={}, stack={}) in area {:?}",
-            n,
-            master_count,
-            n.saturating_sub(self.n_master),
+            "Tiled {} windows (master={}, stack={}, {:?}) in area {:?}",
+            windows.len(),
+            windows.len().min(self.n_master),
+            windows.len().saturating_sub(self.n_master),
+            self.orientation,
             self.available_area
         );
-        positions
+        windows.iter().cloned().zip(slots).collect()
     }
 
     /// Adjust the master area width factor
@@ -211,7 +254,38 @@ impl TilingLayout {
         self.available_area = area.into();
         debug!("Available area updated to {:?}", self.available_area);
     }
-    
+
+    /// Follow the shape of the virtual output the workspace is shown on
+    ///
+    /// Unless the user picked an orientation, portrait virtual outputs get master-top, so
+    /// a workspace moving to one switches over. Only the output counts: a tall panel
+    /// doesn't make a landscape monitor portrait. Returns whether the orientation changed.
+    pub fn set_output_size(&mut self, size: Size<i32, Logical>) -> bool {
+        if self.orientation_overridden {
+            return false;
+        }
+        let orientation = Orientation::default_for(size);
+        let changed = orientation != self.orientation;
+        if changed {
+            self.orientation = orientation;
+            debug!(
+                "Orientation follows output size {:?}: {:?}",
+                size, orientation
+            );
+        }
+        changed
+    }
+
+    /// Switch between master-left and master-top, pinning the choice for this workspace
+    pub fn toggle_orientation(&mut self) {
+        self.orientation = match self.orientation {
+            Orientation::Horizontal => Orientation::Vertical,
+            Orientation::Vertical => Orientation::Horizontal,
+        };
+        self.orientation_overridden = true;
+        debug!("Orientation set to {:?}", self.orientation);
+    }
+
     /// Get current available area
     pub fn available_area(&self) -> VirtualOutputRelativeRect {
         self.available_area
@@ -229,3 +303,356 @@ impl TilingLayout {
         self.n_master
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::new(Point::from((x, y)), Size::from((w, h)))
+    }
+
+    fn layout() -> TilingLayout {
+        TilingLayout::new(rect(0, 0, 1920, 1080))
+    }
+
+    #[test]
+    fn workspace_created_on_portrait_output_is_master_top() {
+        let mut layout = layout();
+        assert!(layout.set_output_size(Size::from((1080, 1920))));
+        assert_eq!(layout.orientation, Orientation::Vertical);
+    }
+
+    #[test]
+    fn migration_from_landscape_to_portrait_follows_the_output() {
+        let mut layout = layout();
+        assert!(!layout.set_output_size(Size::from((2560, 1440))));
+        assert_eq!(layout.orientation, Orientation::Horizontal);
+
+        assert!(layout.set_output_size(Size::from((1440, 2560))));
+        assert_eq!(layout.orientation, Orientation::Vertical);
+    }
+
+    #[test]
+    fn manual_orientation_survives_migration() {
+        let mut layout = layout();
+        layout.set_output_size(Size::from((1440, 2560)));
+        layout.toggle_orientation();
+        assert_eq!(layout.orientation, Orientation::Horizontal);
+
+        assert!(!layout.set_output_size(Size::from((2560, 1440))));
+        assert!(!layout.set_output_size(Size::from((1440, 2560))));
+        assert_eq!(layout.orientation, Orientation::Horizontal);
+    }
+
+    #[test]
+    fn tall_panel_keeps_a_landscape_layout() {
+        let mut layout = layout();
+        layout.set_output_size(Size::from((1920, 1080)));
+        // a 1320px wide side panel leaves a portrait available area
+        layout.set_available_area(rect(1320, 0, 600, 1080));
+        assert_eq!(layout.orientation, Orientation::Horizontal);
+    }
+
+    fn golden(size: (i32, i32), count: usize) -> Vec<Rectangle<i32, Logical>> {
+        let area = rect(0, 0, size.0, size.1);
+        tile(area, count, 0.5, 1, &[], 1, Orientation::Horizontal)
+    }
+
+    #[test]
+    fn golden_1920x1080() {
+        let size = (1920, 1080);
+        assert_eq!(golden(size, 1), [rect(1, 1, 1918, 1078)]);
+        assert_eq!(
+            golden(size, 2),
+            [rect(1, 1, 959, 1078), rect(961, 1, 958, 1078)]
+        );
+        assert_eq!(
+            golden(size, 3),
+            [
+                rect(1, 1, 959, 1078),
+                rect(961, 1, 958, 539),
+                rect(961, 541, 958, 538),
+            ]
+        );
+        assert_eq!(
+            golden(size, 4),
+            [
+                rect(1, 1, 959, 1078),
+                rect(961, 1, 958, 360),
+                rect(961, 362, 958, 358),
+                rect(961, 721, 958, 358),
+            ]
+        );
+        assert_eq!(
+            golden(size, 5),
+            [
+                rect(1, 1, 959, 1078),
+                rect(961, 1, 958, 271),
+                rect(961, 273, 958, 268),
+                rect(961, 542, 958, 268),
+                rect(961, 811, 958, 268),
+            ]
+        );
+    }
+
+    #[test]
+    fn golden_2560x1440() {
+        let size = (2560, 1440);
+        assert_eq!(golden(size, 1), [rect(1, 1, 2558, 1438)]);
+        assert_eq!(
+            golden(size, 2),
+            [rect(1, 1, 1279, 1438), rect(1281, 1, 1278, 1438)]
+        );
+        assert_eq!(
+            golden(size, 3),
+            [
+                rect(1, 1, 1279, 1438),
+                rect(1281, 1, 1278, 719),
+                rect(1281, 721, 1278, 718),
+            ]
+        );
+        assert_eq!(
+            golden(size, 4),
+            [
+                rect(1, 1, 1279, 1438),
+                rect(1281, 1, 1278, 480),
+                rect(1281, 482, 1278, 478),
+                rect(1281, 961, 1278, 478),
+            ]
+        );
+        assert_eq!(
+            golden(size, 5),
+            [
+                rect(1, 1, 1279, 1438),
+                rect(1281, 1, 1278, 361),
+                rect(1281, 363, 1278, 358),
+                rect(1281, 722, 1278, 358),
+                rect(1281, 1081, 1278, 358),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_master_keeps_the_stack_offset() {
+        let slots = tile(
+            rect(0, 0, 1920, 1080),
+            3,
+            0.5,
+            0,
+            &[],
+            1,
+            Orientation::Horizontal,
+        );
+        assert_eq!(
+            slots,
+            [
+                rect(961, 1, 958, 360),
+                rect(961, 362, 958, 358),
+                rect(961, 721, 958, 358),
+            ]
+        );
+    }
+
+    #[test]
+    fn narrow_factor_keeps_the_minimum_width() {
+        let slots = tile(
+            rect(0, 0, 640, 480),
+            2,
+            0.9,
+            1,
+            &[],
+            1,
+            Orientation::Horizontal,
+        );
+        assert_eq!(slots[1].size.w, MIN_TILE_SIZE);
+        assert_eq!(slots[0].size.w, 640 - 3 - MIN_TILE_SIZE);
+    }
+
+    #[test]
+    fn zero_weight_keeps_the_minimum_height() {
+        let slots = tile(
+            rect(0, 0, 1920, 1080),
+            3,
+            0.5,
+            1,
+            &[1.0, 0.0, 1.0],
+            1,
+            Orientation::Horizontal,
+        );
+        assert_eq!(slots[1].size.h, MIN_TILE_SIZE);
+        assert_eq!(slots[2].size.h, 1080 - 3 - MIN_TILE_SIZE);
+    }
+
+    /// Swap the axes of a master-top slot so it can be checked as master-left
+    fn transpose(slot: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+        rect(slot.loc.y, slot.loc.x, slot.size.h, slot.size.w)
+    }
+
+    /// Check a master-left layout: columns sit side by side and windows on top of each
+    /// other with exactly `gap` between them and around the area, so only gaps are left
+    fn assert_covered(area: Rectangle<i32, Logical>, slots: &[Rectangle<i32, Logical>], gap: i32) {
+        let mut columns: Vec<Vec<Rectangle<i32, Logical>>> = Vec::new();
+        for slot in slots {
+            match columns
+                .iter_mut()
+                .find(|column| column[0].loc.x == slot.loc.x)
+            {
+                Some(column) => column.push(*slot),
+                None => columns.push(vec![*slot]),
+            }
+        }
+        columns.sort_by_key(|column| column[0].loc.x);
+
+        let mut x = area.loc.x + gap;
+        for column in &columns {
+            assert_eq!(column[0].loc.x, x, "column doesn't follow a gap");
+            let mut y = area.loc.y + gap;
+            for slot in column {
+                assert_eq!(slot.loc.x, x);
+                assert_eq!(slot.size.w, column[0].size.w);
+                assert_eq!(slot.loc.y, y, "window doesn't follow a gap");
+                y += slot.size.h + gap;
+            }
+            assert_eq!(y, area.loc.y + area.size.h, "column leaves uncovered space");
+            x += column[0].size.w + gap;
+        }
+        assert_eq!(x, area.loc.x + area.size.w, "columns leave uncovered space");
+    }
+
+    /// Check the properties every layout keeps while the area has room for the minimum
+    fn check_layout(
+        area: Rectangle<i32, Logical>,
+        orientation: Orientation,
+        count: usize,
+        n_master: usize,
+        factor: f32,
+        weights: &[f32],
+        gap: i32,
+    ) {
+        let slots = tile(area, count, factor, n_master, weights, gap, orientation);
+        let case = format!(
+            "{area:?} {orientation:?} count={count} n_master={n_master} \
+             factor={factor} weights={weights:?} gap={gap}"
+        );
+        assert_eq!(slots.len(), count, "{case}");
+
+        for (i, slot) in slots.iter().enumerate() {
+            assert!(area.contains_rect(*slot), "{case}: {slot:?} outside");
+            assert!(
+                slot.size.w >= MIN_TILE_SIZE && slot.size.h >= MIN_TILE_SIZE,
+                "{case}: {slot:?} below the minimum"
+            );
+            for other in &slots[i + 1..] {
+                assert!(
+                    !slot.overlaps(*other),
+                    "{case}: {slot:?} overlaps {other:?}"
+                );
+            }
+        }
+
+        if orientation == Orientation::Vertical {
+            let slots: Vec<_> = slots.into_iter().map(transpose).collect();
+            assert_covered(transpose(area), &slots, gap);
+        } else {
+            assert_covered(area, &slots, gap);
+        }
+    }
+
+    /// Check that a window added after `count` ones only moves the windows sharing its column
+    ///
+    /// Every window keeps its column, the other column keeps its slots and only gets
+    /// narrower when the new window opens the stack. Removing the last window is the
+    /// same change the other way around.
+    fn check_stable(
+        area: Rectangle<i32, Logical>,
+        orientation: Orientation,
+        count: usize,
+        n_master: usize,
+        factor: f32,
+        weights: &[f32],
+        gap: i32,
+    ) {
+        let layout = |count| {
+            let slots = tile(area, count, factor, n_master, weights, gap, orientation);
+            if orientation == Orientation::Vertical {
+                slots.into_iter().map(transpose).collect()
+            } else {
+                slots
+            }
+        };
+        let (before, after): (Vec<_>, Vec<_>) = (layout(count), layout(count + 1));
+        let case = format!(
+            "{area:?} {orientation:?} count={count}+1 n_master={n_master} \
+             factor={factor} weights={weights:?} gap={gap}"
+        );
+
+        let added_to_master = count < n_master;
+        let opens_stack = count == n_master;
+        for (i, (old, new)) in before.iter().zip(&after).enumerate() {
+            assert_eq!(old.loc.x, new.loc.x, "{case}: window {i} changed column");
+            if (i < n_master) == added_to_master {
+                assert_eq!(old.size.w, new.size.w, "{case}: window {i} resized");
+            } else if opens_stack {
+                assert_eq!(old.loc.y, new.loc.y, "{case}: window {i} moved");
+                assert_eq!(old.size.h, new.size.h, "{case}: window {i} resized");
+                assert!(new.size.w <= old.size.w, "{case}: window {i} widened");
+            } else {
+                assert_eq!(old, new, "{case}: window {i} moved");
+            }
+        }
+
+        // the new window goes last in its column
+        let added = after[count];
+        assert!(
+            after[..count]
+                .iter()
+                .filter(|slot| slot.loc.x == added.loc.x)
+                .all(|slot| slot.loc.y < added.loc.y),
+            "{case}: {added:?} not last in its column"
+        );
+    }
+
+    #[test]
+    fn layouts_cover_the_area_without_overlap() {
+        let areas = [
+            rect(0, 0, 1920, 1080),
+            rect(0, 32, 2560, 1408),
+            rect(100, 0, 1080, 1920),
+            rect(0, 0, 640, 480),
+        ];
+        let weights: [&[f32]; 4] = [&[], &[2.0, 1.0, 0.5], &[1.0, 0.0, 0.0, 3.0], &[0.0; 6]];
+
+        for area in areas {
+            for orientation in [Orientation::Horizontal, Orientation::Vertical] {
+                for (count, n_master) in (1..=6).flat_map(|count| (1..=3).map(move |n| (count, n)))
+                {
+                    for factor in [0.1, 0.3, 0.5, 0.55, 0.9] {
+                        for weights in weights {
+                            for gap in [0, 1, 8] {
+                                check_layout(
+                                    area,
+                                    orientation,
+                                    count,
+                                    n_master,
+                                    factor,
+                                    weights,
+                                    gap,
+                                );
+                                check_stable(
+                                    area,
+                                    orientation,
+                                    count,
+                                    n_master,
+                                    factor,
+                                    weights,
+                                    gap,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -5,12 +5,39 @@ use smithay::output::Output;
 use smithay::utils::{Physical, Point, Rectangle, Size};
 use std::collections::{HashMap, HashSet};
 
+use super::tags::DEFAULT_TAG_MASK;
 use super::workspace::WorkspaceId;
 use crate::utils::coordinates::{GlobalPoint, GlobalRect, OutputExt};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct VirtualOutputId(pub u32);
 
+/// Direction towards an adjacent virtual output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Global logical rectangle of a region of an output
+///
+/// Regions are given post-rotation and pre-scaling, relative to the output, so they
+/// only get scaled before being placed at the output's logical location.
+fn logical_rect(output: &Output, physical_rect: Rectangle<i32, Physical>) -> GlobalRect {
+    let scale = output.current_scale().fractional_scale();
+    let output_position = output.current_location_typed().as_point();
+    let rect = physical_rect.to_f64().to_logical(scale).to_i32_round();
+    GlobalRect::new(
+        GlobalPoint::new(
+            output_position.x + rect.loc.x,
+            output_position.y + rect.loc.y,
+        ),
+        rect.size,
+    )
+}
+
 #[derive(Debug, Clone)]
 pub struct VirtualRegion {
     pub physical_output: Output,
@@ -24,6 +51,10 @@ pub struct VirtualOutput {
     pub regions: Vec<VirtualRegion>,
     pub logical_geometry: GlobalRect,
     pub active_workspace: Option<WorkspaceId>, // TODO: Make private once Shell APIs are updated
+    /// Tags shown while in tag view
+    pub view_mask: u32,
+    /// Cascade position of the next floating window
+    pub floating_cascade: u32,
 }
 
 impl VirtualOutput {
@@ -44,21 +75,8 @@ impl VirtualOutput {
         physical_output: Output,
         physical_rect: Rectangle<i32, Physical>,
     ) -> Self {
-        let scale = physical_output.current_scale().fractional_scale();
         let output_position = physical_output.current_location_typed();
-
-        // convert physical rectangle to logical coordinates, including global output position
-        let logical_x =
-            ((physical_rect.loc.x + output_position.as_point().x) as f64 / scale) as i32;
-        let logical_y =
-            ((physical_rect.loc.y + output_position.as_point().y) as f64 / scale) as i32;
-        let logical_w = (physical_rect.size.w as f64 / scale) as i32;
-        let logical_h = (physical_rect.size.h as f64 / scale) as i32;
-
-        let logical_rect = GlobalRect::new(
-            GlobalPoint::new(logical_x, logical_y),
-            Size::new(logical_w, logical_h),
-        );
+        let logical_rect = logical_rect(&physical_output, physical_rect);
 
         let region = VirtualRegion {
             physical_output: physical_output.clone(),
@@ -82,6 +100,8 @@ impl VirtualOutput {
             regions: vec![region],
             logical_geometry: logical_rect,
             active_workspace: None,
+            view_mask: DEFAULT_TAG_MASK,
+            floating_cascade: 0,
         }
     }
 
@@ -98,20 +118,7 @@ impl VirtualOutput {
         let mut logical_bounds_max = GlobalPoint::new(i32::MIN, i32::MIN);
 
         for (output, physical_rect) in regions_config {
-            let scale = output.current_scale().fractional_scale();
-            let output_position = output.current_location_typed();
-
-            // convert physical rectangle to logical coordinates, including global output position
-            let logical_rect = GlobalRect::new(
-                GlobalPoint::new(
-                    ((physical_rect.loc.x + output_position.as_point().x) as f64 / scale) as i32,
-                    ((physical_rect.loc.y + output_position.as_point().y) as f64 / scale) as i32,
-                ),
-                Size::new(
-                    (physical_rect.size.w as f64 / scale) as i32,
-                    (physical_rect.size.h as f64 / scale) as i32,
-                ),
-            );
+            let logical_rect = logical_rect(&output, physical_rect);
 
             // track overall logical bounds
             logical_bounds_min = GlobalPoint::new(
@@ -161,6 +168,8 @@ impl VirtualOutput {
             regions,
             logical_geometry,
             active_workspace: None,
+            view_mask: DEFAULT_TAG_MASK,
+            floating_cascade: 0,
         }
     }
 
@@ -206,10 +215,10 @@ impl VirtualOutputManager {
         self.next_id += 1;
 
         let mode = output.current_mode().unwrap();
-        // physical rectangle at origin of this output (in physical coordinates)
+        // physical rectangle at origin of this output, post-rotation like configured ones
         let physical_rect = Rectangle::new(
             Point::new(0, 0), // origin in physical space
-            mode.size,
+            output.current_transform().transform_size(mode.size),
         );
 
         let virtual_output = VirtualOutput::from_split(id, output.clone(), physical_rect);
@@ -237,28 +246,7 @@ impl VirtualOutputManager {
 
             for (output_name, physical_rect) in &virtual_output.config {
                 if let Some(&output) = outputs_by_name.get(output_name) {
-                    let scale = output.current_scale().fractional_scale();
-                    let output_position = output.current_location_typed();
-
-                    // convert physical rectangle to logical coordinates, including global output position
-                    let pre_transform_logical = Size::new(
-                        (physical_rect.size.w as f64 / scale) as i32,
-                        (physical_rect.size.h as f64 / scale) as i32,
-                    );
-
-                    // virtual output coordinates are specified post-rotation, pre-scaling
-                    // so we only apply scaling, not transform
-                    let logical_size = pre_transform_logical;
-
-                    let logical_rect = GlobalRect::new(
-                        GlobalPoint::new(
-                            ((physical_rect.loc.x + output_position.as_point().x) as f64 / scale)
-                                as i32,
-                            ((physical_rect.loc.y + output_position.as_point().y) as f64 / scale)
-                                as i32,
-                        ),
-                        logical_size,
-                    );
+                    let logical_rect = logical_rect(output, *physical_rect);
 
                     // track overall logical bounds
                     logical_bounds_min = GlobalPoint::new(
@@ -345,85 +333,118 @@ impl VirtualOutputManager {
         self.virtual_outputs.values()
     }
 
-    /// Load configuration from environment variable
-    pub fn load_config(&mut self, physical_outputs: &[Output]) {
+    /// Closest virtual output lying entirely on the given side of another one
+    ///
+    /// Candidates are ranked by the gap between the facing edges, then by how far their
+    /// centers are apart along the other axis.
+    pub fn neighbor(&self, id: VirtualOutputId, direction: Direction) -> Option<VirtualOutputId> {
+        let source = self.get(id)?.logical_geometry.as_rectangle();
+        let source_center = source.loc + source.size.downscale(2).to_point();
+
+        self.all()
+            .filter(|vout| vout.id != id)
+            .filter_map(|vout| {
+                let rect = vout.logical_geometry.as_rectangle();
+                let center = rect.loc + rect.size.downscale(2).to_point();
+                let (gap, offset) = match direction {
+                    Direction::Left => (
+                        source.loc.x - (rect.loc.x + rect.size.w),
+                        center.y - source_center.y,
+                    ),
+                    Direction::Right => (
+                        rect.loc.x - (source.loc.x + source.size.w),
+                        center.y - source_center.y,
+                    ),
+                    Direction::Up => (
+                        source.loc.y - (rect.loc.y + rect.size.h),
+                        center.x - source_center.x,
+                    ),
+                    Direction::Down => (
+                        rect.loc.y - (source.loc.y + source.size.h),
+                        center.x - source_center.x,
+                    ),
+                };
+                (gap >= 0).then_some(((gap, offset.abs()), vout.id))
+            })
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, id)| id)
+    }
+
+    /// Replace the virtual outputs by the ones of a SWL_VIRTUAL_OUTPUTS config
+    pub fn apply_config(&mut self, config: &str, physical_outputs: &[Output]) {
         // example: SWL_VIRTUAL_OUTPUTS="DP-1:0,0,1920x1080;DP-1:1920,0,1920x1080"
         // this would split DP-1 into two 1920x1080 virtual outputs
+        tracing::info!("Loading virtual output config: {}", config);
+
+        // clear existing virtual outputs
+        self.virtual_outputs.clear();
+        self.physical_mapping.clear();
+        self.next_id = 1;
+
+        // create a lookup map for outputs by name
+        let outputs_by_name: HashMap<String, &Output> =
+            physical_outputs.iter().map(|o| (o.name(), o)).collect();
+
+        let specs: Vec<&str> = config.split(';').collect();
+
+        for spec in specs.iter() {
+            // parse "output_name:x,y,widthxheight" format
+            let parts: Vec<&str> = spec.split(':').collect();
 
-        if let Ok(config) = std::env::var("SWL_VIRTUAL_OUTPUTS") {
-            tracing::info!("Loading virtual output config: {}", config);
-
-            // clear existing virtual outputs
-            self.virtual_outputs.clear();
-            self.physical_mapping.clear();
-            self.next_id = 1;
-
-            // create a lookup map for outputs by name
-            let outputs_by_name: HashMap<String, &Output> =
-                physical_outputs.iter().map(|o| (o.name(), o)).collect();
-
-            let specs: Vec<&str> = config.split(';').collect();
-
-            for spec in specs.iter() {
-                // parse "output_name:x,y,widthxheight" format
-                let parts: Vec<&str> = spec.split(':').collect();
-
-                if parts.len() == 2 {
-                    let output_name = parts[0];
-                    let rect_spec = parts[1];
-
-                    // parse rectangle
-                    if let Some(rect) = self.parse_rectangle_spec(rect_spec) {
-                        if let Some(&output) = outputs_by_name.get(output_name) {
-                            let id = VirtualOutputId(self.next_id);
-                            self.next_id += 1;
-
-                            let virtual_output =
-                                VirtualOutput::from_split(id, output.clone(), rect);
-
-                            // add to mapping
-                            self.physical_mapping
-                                .entry(output.name())
-                                .or_insert_with(Vec::new)
-                                .push(id);
-
-                            self.virtual_outputs.insert(id, virtual_output);
-
-                            tracing::info!(
-                                "Created virtual output {} for {}:{:?}",
-                                id.0,
-                                output_name,
-                                rect
-                            );
-                        } else {
-                            tracing::warn!(
-                                "Physical output {} not found for virtual output config",
-                                output_name
-                            );
-                        }
+            if parts.len() == 2 {
+                let output_name = parts[0];
+                let rect_spec = parts[1];
+
+                // parse rectangle
+                if let Some(rect) = self.parse_rectangle_spec(rect_spec) {
+                    if let Some(&output) = outputs_by_name.get(output_name) {
+                        let id = VirtualOutputId(self.next_id);
+                        self.next_id += 1;
+
+                        let virtual_output = VirtualOutput::from_split(id, output.clone(), rect);
+
+                        // add to mapping
+                        self.physical_mapping
+                            .entry(output.name())
+                            .or_insert_with(Vec::new)
+                            .push(id);
+
+                        self.virtual_outputs.insert(id, virtual_output);
+
+                        tracing::info!(
+                            "Created virtual output {} for {}:{:?}",
+                            id.0,
+                            output_name,
+                            rect
+                        );
                     } else {
-                        tracing::warn!("Failed to parse rectangle spec: {}", rect_spec);
+                        tracing::warn!(
+                            "Physical output {} not found for virtual output config",
+                            output_name
+                        );
                     }
                 } else {
-                    tracing::warn!("Invalid virtual output spec: {}", spec);
+                    tracing::warn!("Failed to parse rectangle spec: {}", rect_spec);
                 }
+            } else {
+                tracing::warn!("Invalid virtual output spec: {}", spec);
             }
+        }
 
-            // create default 1:1 virtual outputs for any physical outputs not mentioned in config
-            let configured_outputs: HashSet<String> = config
-                .split(';')
-                .filter_map(|spec| spec.split(':').next())
-                .map(|s| s.to_string())
-                .collect();
-
-            for output in physical_outputs {
-                if !configured_outputs.contains(&output.name()) {
-                    tracing::debug!(
-                        "Creating default virtual output for unconfigured output: {}",
-                        output.name()
-                    );
-                    self.create_default(output);
-                }
+        // create default 1:1 virtual outputs for any physical outputs not mentioned in config
+        let configured_outputs: HashSet<String> = config
+            .split(';')
+            .filter_map(|spec| spec.split(':').next())
+            .map(|s| s.to_string())
+            .collect();
+
+        for output in physical_outputs {
+            if !configured_outputs.contains(&output.name()) {
+                tracing::debug!(
+                    "Creating default virtual output for unconfigured output: {}",
+                    output.name()
+                );
+                self.create_default(output);
             }
         }
     }
@@ -472,3 +493,100 @@ impl VirtualOutputManager {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::Transform;
+
+    use super::*;
+    use crate::utils::testing;
+
+    #[test]
+    fn default_virtual_output_covers_the_output() {
+        let output = testing::output("DP-1", (2560, 1440), Transform::Normal, 1.0, (1920, 0));
+        let mut manager = VirtualOutputManager::new();
+        let id = manager.create_default(&output);
+
+        let geometry = manager.get(id).unwrap().logical_geometry.as_rectangle();
+        assert_eq!(
+            geometry,
+            Rectangle::new((1920, 0).into(), (2560, 1440).into())
+        );
+    }
+
+    #[test]
+    fn default_virtual_output_is_post_rotation() {
+        // fullscreen windows take the virtual output size, it must be portrait here
+        let output = testing::output("DP-1", (1920, 1080), Transform::_90, 1.0, (0, 0));
+        let mut manager = VirtualOutputManager::new();
+        let id = manager.create_default(&output);
+
+        assert_eq!(
+            manager.get(id).unwrap().logical_geometry.size(),
+            Size::new(1080, 1920)
+        );
+    }
+
+    #[test]
+    fn default_virtual_output_is_scaled() {
+        let output = testing::output("DP-1", (3840, 2160), Transform::Normal, 2.0, (0, 0));
+        let mut manager = VirtualOutputManager::new();
+        let id = manager.create_default(&output);
+
+        assert_eq!(
+            manager.get(id).unwrap().logical_geometry.size(),
+            Size::new(1920, 1080)
+        );
+    }
+
+    #[test]
+    fn configured_virtual_outputs_are_post_rotation() {
+        // a portrait output split in a top and a bottom half
+        let output = testing::output("DP-1", (1920, 1080), Transform::_90, 1.0, (0, 0));
+        let mut manager = VirtualOutputManager::new();
+        manager.apply_config("DP-1:0,0,1080x960;DP-1:0,960,1080x960", &[output.clone()]);
+        manager.update_all(&[output]);
+
+        let geometries: Vec<_> = manager
+            .all()
+            .map(|vout| vout.logical_geometry.as_rectangle())
+            .collect();
+        assert_eq!(
+            geometries,
+            [
+                Rectangle::new((0, 0).into(), (1080, 960).into()),
+                Rectangle::new((0, 960).into(), (1080, 960).into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn configured_virtual_outputs_are_placed_in_logical_space() {
+        // the output location is logical already, only the region gets scaled
+        let output = testing::output("DP-1", (3840, 2160), Transform::Normal, 2.0, (1920, 0));
+        let mut manager = VirtualOutputManager::new();
+        manager.apply_config("DP-1:1920,0,1920x2160", &[output.clone()]);
+        manager.update_all(&[output]);
+
+        let vout = manager.all().next().unwrap();
+        assert_eq!(
+            vout.logical_geometry.as_rectangle(),
+            Rectangle::new((2880, 0).into(), (960, 1080).into())
+        );
+    }
+
+    #[test]
+    fn update_keeps_the_default_virtual_output() {
+        let output = testing::output("DP-1", (1920, 1080), Transform::_270, 1.5, (640, 0));
+        let mut manager = VirtualOutputManager::new();
+        let id = manager.create_default(&output);
+        let created = manager.get(id).unwrap().logical_geometry;
+
+        manager.update_all(&[output]);
+        assert_eq!(manager.get(id).unwrap().logical_geometry, created);
+        assert_eq!(
+            created.as_rectangle(),
+            Rectangle::new((640, 0).into(), (720, 1280).into())
+        );
+    }
+}
@@ -32,6 +32,32 @@ pub enum LayoutMode {
     Tabbed,
 }
 
+/// A window its client unmapped (null buffer commit), waiting for content again
+#[derive(Debug, Clone, Copy)]
+pub struct UnmappedWindow {
+    /// Location in the space before the unmap
+    pub location: Point<i32, smithay::utils::Logical>,
+    /// Whether the configure answering the client's new initial commit went out
+    configured: bool,
+}
+
+impl UnmappedWindow {
+    pub fn new(location: Point<i32, smithay::utils::Logical>) -> Self {
+        Self {
+            location,
+            configured: false,
+        }
+    }
+
+    /// Whether a commit without buffer has to be answered with a configure
+    ///
+    /// Clients restart the initial commit sequence after unmapping, so the first
+    /// such commit of every unmap gets one, later ones don't.
+    pub fn take_initial_configure(&mut self) -> bool {
+        !std::mem::replace(&mut self.configured, true)
+    }
+}
+
 /// A workspace containing windows
 #[derive(Debug)]
 pub struct Workspace {
@@ -69,11 +95,15 @@ pub struct Workspace {
     /// Current layout mode
     pub layout_mode: LayoutMode,
 
-    /// Active tab index (for tabbed mode)
-    pub active_tab_index: usize,
+    /// Last active tab index, used while no tiled window has been focused
+    tab_index_hint: usize,
 
     /// Associated virtual output (if any)
     pub virtual_output_id: Option<VirtualOutputId>,
+
+    /// Windows unmapped by their client (null buffer), they keep their slot until
+    /// the client maps them again
+    pub unmapped_windows: HashMap<Window, UnmappedWindow>,
 }
 
 impl Workspace {
@@ -97,8 +127,9 @@ impl Workspace {
                 Size::from((1920, 1080)), // default size
             )),
             layout_mode: LayoutMode::Tiling,
-            active_tab_index: 0,
+            tab_index_hint: 0,
             virtual_output_id: None,
+            unmapped_windows: HashMap::new(),
         }
     }
 
@@ -117,26 +148,17 @@ impl Workspace {
         if floating {
             self.floating_windows.insert(window);
         }
-        // In tabbed mode, new tiled windows become the active tab
-        if matches!(self.layout_mode, LayoutMode::Tabbed) && !floating {
-            let tiled_count = self.tiled_windows().count();
-            self.active_tab_index = tiled_count.saturating_sub(1);
+        // without focus history, new tiled windows become the active tab
+        if !floating {
+            self.tab_index_hint = self.tiled_windows().count().saturating_sub(1);
         }
         self.needs_arrange = true;
     }
 
     /// Remove a window from this workspace
     pub fn remove_window(&mut self, window: &Window) -> bool {
-        // Check if this was a tiled window and the active tab
-        let was_tiled = !self.floating_windows.contains(window);
-        let was_active = if was_tiled && matches!(self.layout_mode, LayoutMode::Tabbed) {
-            self.tiled_windows()
-                .nth(self.active_tab_index)
-                .map(|w| w == window)
-                .unwrap_or(false)
-        } else {
-            false
-        };
+        // remember the active tab position so the neighbour takes over without focus history
+        let active_index = self.active_tab_index();
 
         // Remove from windows list
         let original_len = self.windows.len();
@@ -155,20 +177,16 @@ impl Workspace {
         // remove from cached geometry offsets
         self.cached_geometry_offsets.remove(window);
 
+        // forget a pending client-side unmap
+        self.unmapped_windows.remove(window);
+
         // Clear fullscreen if it was this window
         if self.fullscreen.as_ref() == Some(window) {
             self.fullscreen = None;
         }
 
-        // Adjust active_tab_index if needed
-        if was_active && matches!(self.layout_mode, LayoutMode::Tabbed) {
-            let tiled_count = self.tiled_windows().count();
-            if tiled_count > 0 {
-                self.active_tab_index = self.active_tab_index.min(tiled_count - 1);
-            } else {
-                self.active_tab_index = 0;
-            }
-        }
+        let tiled_count = self.tiled_windows().count();
+        self.tab_index_hint = active_index.min(tiled_count.saturating_sub(1));
 
         if was_present {
             self.needs_arrange = true;
@@ -190,18 +208,25 @@ impl Workspace {
 
     /// Get tiled windows (non-floating, non-fullscreen)
     pub fn tiled_windows(&self) -> impl Iterator<Item = &Window> {
-        self.windows
-            .iter()
+        self.mapped_windows()
             .filter(|w| !self.floating_windows.contains(w))
             .filter(|w| self.fullscreen.is_none() || self.fullscreen.as_ref() == Some(w))
     }
 
+    /// Get windows that have content, skipping the ones unmapped by their client
+    pub fn mapped_windows(&self) -> impl Iterator<Item = &Window> {
+        self.windows
+            .iter()
+            .filter(|w| !self.unmapped_windows.contains_key(w))
+    }
+
     /// Clean up dead windows
     pub fn refresh(&mut self) {
         self.windows.retain(|w| w.alive());
         self.focus_stack.retain(|w| w.alive());
         self.floating_windows.retain(|w| w.alive());
         self.window_rectangles.retain(|w, _| w.alive());
+        self.unmapped_windows.retain(|w, _| w.alive());
 
         if let Some(fullscreen) = &self.fullscreen {
             if !fullscreen.alive() {
@@ -214,6 +239,33 @@ impl Workspace {
     pub fn append_focus(&mut self, window: &Window) {
         self.focus_stack.retain(|w| w != window);
         self.focus_stack.push(window.clone());
+
+        if let Some(idx) = self.tiled_windows().position(|w| w == window) {
+            self.tab_index_hint = idx;
+        }
+    }
+
+    /// Index of the active tab among the tiled windows
+    ///
+    /// The active tab is the most recently focused tiled window, so it can't
+    /// drift apart from focus. The cached index only applies while none of
+    /// the tiled windows is in the focus stack.
+    pub fn active_tab_index(&self) -> usize {
+        let tiled: Vec<_> = self.tiled_windows().collect();
+        active_tab_position(&tiled, &self.focus_stack, self.tab_index_hint)
+    }
+
+    /// The window shown in tabbed mode
+    pub fn active_tab(&self) -> Option<&Window> {
+        self.tiled_windows().nth(self.active_tab_index())
+    }
+
+    /// Make the tiled window at the given index the active tab by focusing it
+    pub fn select_tab(&mut self, index: usize) -> Option<Window> {
+        let window = self.tiled_windows().nth(index).cloned()?;
+        self.append_focus(&window);
+        self.needs_arrange = true;
+        Some(window)
     }
 
     /// Update the output area for tiling (in virtual-output-relative coordinates)
@@ -227,29 +279,22 @@ impl Workspace {
         }
     }
 
+    /// Adapt the layout to the size of the virtual output the workspace is shown on
+    pub fn update_output_size(&mut self, size: Size<i32, smithay::utils::Logical>) {
+        if self.tiling.set_output_size(size) {
+            self.needs_arrange = true;
+        }
+    }
+
     /// Toggle between tiling and tabbed layout modes
+    ///
+    /// Both modes present the windows in their workspace order and the active
+    /// tab follows focus, so toggling never reorders nor changes the visible window.
     pub fn toggle_layout_mode(&mut self) {
-        match self.layout_mode {
-            LayoutMode::Tiling => {
-                self.layout_mode = LayoutMode::Tabbed;
-                self.active_tab_index = 0;
-                // Find index of currently focused window if any
-                if let Some(focused) = self.focus_stack.last() {
-                    let idx = self
-                        .tiled_windows()
-                        .enumerate()
-                        .find(|(_, w)| *w == focused)
-                        .map(|(idx, _)| idx);
-                    if let Some(idx) = idx {
-                        self.active_tab_index = idx;
-                    }
-                }
-            }
-            LayoutMode::Tabbed => {
-                self.layout_mode = LayoutMode::Tiling;
-                // active_tab_index is ignored in tiling mode
-            }
-        }
+        self.layout_mode = match self.layout_mode {
+            LayoutMode::Tiling => LayoutMode::Tabbed,
+            LayoutMode::Tabbed => LayoutMode::Tiling,
+        };
         self.needs_arrange = true;
     }
 
@@ -259,20 +304,12 @@ impl Workspace {
             return None;
         }
 
-        let tiled: Vec<_> = self.tiled_windows().cloned().collect();
-        if tiled.is_empty() {
+        let tiled_count = self.tiled_windows().count();
+        if tiled_count == 0 {
             return None;
         }
 
-        self.active_tab_index = (self.active_tab_index + 1) % tiled.len();
-        self.needs_arrange = true;
-
-        // Update focus stack to match the active tab
-        if let Some(window) = tiled.get(self.active_tab_index) {
-            self.append_focus(window);
-        }
-
-        tiled.get(self.active_tab_index).cloned()
+        self.select_tab((self.active_tab_index() + 1) % tiled_count)
     }
 
     /// Switch to the previous tab in tabbed mode
@@ -281,24 +318,16 @@ impl Workspace {
             return None;
         }
 
-        let tiled: Vec<_> = self.tiled_windows().cloned().collect();
-        if tiled.is_empty() {
+        let tiled_count = self.tiled_windows().count();
+        if tiled_count == 0 {
             return None;
         }
 
-        self.active_tab_index = if self.active_tab_index == 0 {
-            tiled.len() - 1
-        } else {
-            self.active_tab_index - 1
+        let previous = match self.active_tab_index() {
+            0 => tiled_count - 1,
+            active => active - 1,
         };
-        self.needs_arrange = true;
-
-        // Update focus stack to match the active tab
-        if let Some(window) = tiled.get(self.active_tab_index) {
-            self.append_focus(window);
-        }
-
-        tiled.get(self.active_tab_index).cloned()
+        self.select_tab(previous)
     }
 
     /// Validate workspace consistency
@@ -329,17 +358,98 @@ impl Workspace {
             }
         }
 
-        // Check active tab index
+        // Check the active tab is a tiled window, the last focused one if any was
         if matches!(self.layout_mode, LayoutMode::Tabbed) {
-            let tiled_count = self.tiled_windows().count();
-            if self.active_tab_index >= tiled_count && tiled_count > 0 {
+            let tiled: Vec<_> = self.tiled_windows().collect();
+            let active = self.active_tab();
+            let last_focused = self
+                .focus_stack
+                .iter()
+                .rev()
+                .find(|focused| tiled.contains(focused));
+            if !tiled.is_empty() && active.is_none() {
                 tracing::error!(
-                    "Workspace {} has invalid active_tab_index {} for {} tiled windows",
+                    "Workspace {} has no active tab for {} tiled windows",
                     self.name,
-                    self.active_tab_index,
-                    tiled_count
+                    tiled.len()
+                );
+            } else if last_focused.is_some() && active != last_focused {
+                tracing::error!(
+                    "Workspace {} shows a tab other than its last focused tiled window",
+                    self.name
                 );
             }
         }
     }
 }
+
+/// Position of the active tab among the tiled windows
+///
+/// The most recently focused tiled window, or the hint clamped to the tiled windows
+/// while none of them was focused.
+fn active_tab_position<T: PartialEq>(tiled: &[&T], focus_stack: &[T], hint: usize) -> usize {
+    focus_stack
+        .iter()
+        .rev()
+        .find_map(|focused| tiled.iter().position(|w| *w == focused))
+        .unwrap_or_else(|| hint.min(tiled.len().saturating_sub(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmapped_window_is_configured_once_per_unmap() {
+        let mut unmapped = UnmappedWindow::new(Point::from((10, 20)));
+        assert!(unmapped.take_initial_configure());
+        assert!(!unmapped.take_initial_configure());
+        assert!(!unmapped.take_initial_configure());
+
+        // the next unmap starts over, even though the window was configured before
+        let mut unmapped = UnmappedWindow::new(unmapped.location);
+        assert!(unmapped.take_initial_configure());
+    }
+
+    #[test]
+    fn active_tab_is_the_last_focused_tiled_window() {
+        let tiled = [&1, &2, &3];
+        assert_eq!(active_tab_position(&tiled, &[3, 1], 0), 0);
+        // focused windows that aren't tiled (floating, other workspaces) are skipped
+        assert_eq!(active_tab_position(&tiled, &[2, 7, 9], 0), 1);
+    }
+
+    #[test]
+    fn active_tab_hint_applies_until_a_tiled_window_got_focus() {
+        let tiled = [&1, &2, &3];
+        assert_eq!(active_tab_position(&tiled, &[], 1), 1);
+        // the hinted tab was closed, the last one takes over
+        assert_eq!(active_tab_position(&tiled, &[], 5), 2);
+        assert_eq!(active_tab_position::<i32>(&[], &[], 5), 0);
+    }
+
+    #[test]
+    fn toggling_the_layout_mode_round_trips() {
+        let mut workspace = Workspace::new("1".to_string());
+        workspace.toggle_layout_mode();
+        assert!(matches!(workspace.layout_mode, LayoutMode::Tabbed));
+        assert!(workspace.needs_arrange);
+
+        workspace.needs_arrange = false;
+        workspace.toggle_layout_mode();
+        assert!(matches!(workspace.layout_mode, LayoutMode::Tiling));
+        assert!(workspace.needs_arrange);
+        assert_eq!(workspace.active_tab_index(), 0);
+    }
+
+    #[test]
+    fn tabs_only_cycle_in_tabbed_mode() {
+        let mut workspace = Workspace::new("1".to_string());
+        assert!(workspace.next_tab().is_none());
+        workspace.toggle_layout_mode();
+        // no tiled windows to cycle through either
+        assert!(workspace.next_tab().is_none());
+        assert!(workspace.prev_tab().is_none());
+        workspace.validate_consistency();
+    }
+}
@@ -1,12 +1,20 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 use crate::{
-    backend::kms::{Device, KmsState},
+    backend::kms::{
+        surface::{self, fingerprint, jank, render_path},
+        Device, KmsState,
+    },
     backend::render::cursor::{CursorState, CursorStateInner},
-    input::keybindings::Keybindings,
-    shell::Shell,
-    wayland::output_configuration::{
-        OutputConfiguration, OutputConfigurationHandler, OutputConfigurationState,
+    input::{confirm::PendingConfirmation, keybindings::Keybindings, layout::WindowLayouts},
+    shell::{app_id_and_title, Shell},
+    wayland::{
+        diagnostics::{ClientDiagnostics, SharedClientDiagnostics},
+        globals::{client_filter, GlobalEntry, GlobalFilter, Globals},
+        output_configuration::{
+            OutputConfiguration, OutputConfigurationHandler, OutputConfigurationState,
+        },
+        responsiveness::ClientPings,
     },
 };
 use smithay::{
@@ -23,9 +31,16 @@ use smithay::{
     input::{keyboard::XkbConfig, Seat, SeatState},
     output::Output,
     reexports::{
-        calloop::{LoopHandle, LoopSignal},
-        wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle},
+        calloop::{
+            channel::{self, Sender},
+            LoopHandle, LoopSignal,
+        },
+        wayland_protocols::wp::linux_dmabuf::zv1::server::zwp_linux_dmabuf_v1::ZwpLinuxDmabufV1,
+        wayland_server::{
+            backend::ClientId, protocol::wl_surface::WlSurface, DisplayHandle, Resource,
+        },
     },
+    utils::{Logical, Point},
     wayland::{
         compositor::CompositorState,
         cursor_shape::CursorShapeManagerState,
@@ -91,8 +106,17 @@ pub struct State {
     #[allow(dead_code)] // will be used for server-side cursor rendering
     pub cursor_state: CursorState,
     pub keybindings: Keybindings,
+    pub window_layouts: WindowLayouts,
+    pub pending_confirmation: Option<PendingConfirmation>,
     session_active: bool,
     pub needs_focus_refresh: bool,
+    /// surface and its origin last handed to the pointer as focus
+    pub pointer_focus: Option<(WlSurface, Point<f64, Logical>)>,
+    pub client_diagnostics: SharedClientDiagnostics,
+    /// disconnects reported by the client data, handled on the loop
+    pub client_disconnects: Sender<ClientId>,
+    pub client_pings: ClientPings,
+    pub globals: Globals,
     // additional protocol support
     #[allow(dead_code)]
     pub viewporter_state: ViewporterState,
@@ -262,6 +286,12 @@ impl OutputConfigurationHandler for State {
         // update cursor bounds after position changes
         self.update_cursor_bounds();
 
+        // the exclusive zone cap depends on the output size, which may have changed
+        for output in self.outputs.clone() {
+            self.clamp_exclusive_zones(&output);
+            smithay::desktop::layer_map_for_output(&output).arrange();
+        }
+
         // trigger re-arrangement of windows and update geometry
         let mut shell = self.shell.write().unwrap();
         for output in &self.outputs {
@@ -316,6 +346,15 @@ impl BackendData {
             BackendData::Uninitialized => {}
         }
     }
+
+    /// Force or stop forcing the offscreen render path for the given output
+    #[cfg(feature = "dbus")]
+    pub fn force_offscreen(&mut self, output: &Output, force: bool) {
+        match self {
+            BackendData::Kms(kms) => kms.force_offscreen(output, force),
+            BackendData::Uninitialized => {}
+        }
+    }
 }
 
 impl State {
@@ -325,24 +364,53 @@ impl State {
         loop_handle: LoopHandle<'static, State>,
         loop_signal: LoopSignal,
     ) -> Self {
+        let globals = Globals::new();
+
         // create compositor state
         let compositor_state = CompositorState::new::<State>(&display_handle);
+        globals.register(&display_handle, &compositor_state.compositor_global(), None);
+        globals.register(
+            &display_handle,
+            &compositor_state.subcompositor_global(),
+            None,
+        );
         let xdg_shell_state = XdgShellState::new::<State>(&display_handle);
+        globals.register(&display_handle, &xdg_shell_state.global(), None);
         let xdg_decoration_state =
             smithay::wayland::shell::xdg::decoration::XdgDecorationState::new::<State>(
                 &display_handle,
             );
+        globals.register(&display_handle, &xdg_decoration_state.global(), None);
         let layer_shell_state = WlrLayerShellState::new::<State>(&display_handle);
+        globals.register(&display_handle, &layer_shell_state.shell_global(), None);
         let shm_state = ShmState::new::<State>(&display_handle, vec![]);
+        globals.register(&display_handle, &shm_state.global(), None);
         let data_device_state = DataDeviceState::new::<State>(&display_handle);
+        globals.register(&display_handle, &data_device_state.global(), None);
         let dmabuf_state = DmabufState::new();
         let output_manager_state =
             OutputManagerState::new_with_xdg_output::<State>(&display_handle);
-        let output_configuration_state = OutputConfigurationState::new(&display_handle, |_| true);
+        if let Some(global) = output_manager_state.xdg_output_manager_global() {
+            globals.register(&display_handle, &global, None);
+        }
+        // no client is kept from configuring outputs
+        let output_configuration_filter: GlobalFilter = None;
+        let output_configuration_state = OutputConfigurationState::new(
+            &display_handle,
+            client_filter(output_configuration_filter),
+        );
+        globals.register(
+            &display_handle,
+            &output_configuration_state.global(),
+            output_configuration_filter,
+        );
 
         // create seat state and the default seat
         let mut seat_state = SeatState::new();
         let mut seat = seat_state.new_wl_seat(&display_handle, "seat0");
+        if let Some(global) = seat.global() {
+            globals.register(&display_handle, &global, None);
+        }
 
         // parse keyboard configuration from environment variables
         let xkb_layout = std::env::var("SWL_XKB_LAYOUT")
@@ -401,23 +469,57 @@ impl State {
         // create presentation state
         // using CLOCK_MONOTONIC (id = 1) as the clock
         let presentation_state = PresentationState::new::<State>(&display_handle, 1);
+        globals.register(&display_handle, &presentation_state.global(), None);
 
         // initialize additional protocol support
         let viewporter_state = ViewporterState::new::<State>(&display_handle);
+        globals.register(&display_handle, &viewporter_state.global(), None);
         let pointer_gestures_state = PointerGesturesState::new::<State>(&display_handle);
+        globals.register(&display_handle, &pointer_gestures_state.global(), None);
         let relative_pointer_manager_state =
             RelativePointerManagerState::new::<State>(&display_handle);
+        globals.register(
+            &display_handle,
+            &relative_pointer_manager_state.global(),
+            None,
+        );
         let text_input_manager_state = TextInputManagerState::new::<State>(&display_handle);
+        globals.register(&display_handle, &text_input_manager_state.global(), None);
         let primary_selection_state = PrimarySelectionState::new::<State>(&display_handle);
+        globals.register(&display_handle, &primary_selection_state.global(), None);
+        // no client is kept from the clipboard
+        let data_control_filter: GlobalFilter = None;
         let data_control_state = DataControlState::new::<State, _>(
             &display_handle,
             Some(&primary_selection_state),
-            |_| true,
+            client_filter(data_control_filter),
+        );
+        globals.register(
+            &display_handle,
+            &data_control_state.global(),
+            data_control_filter,
         );
         let xdg_activation_state = XdgActivationState::new::<State>(&display_handle);
+        globals.register(&display_handle, &xdg_activation_state.global(), None);
         let fractional_scale_manager_state =
             FractionalScaleManagerState::new::<State>(&display_handle);
+        globals.register(
+            &display_handle,
+            &fractional_scale_manager_state.global(),
+            None,
+        );
         let cursor_shape_manager_state = CursorShapeManagerState::new::<State>(&display_handle);
+        globals.register(&display_handle, &cursor_shape_manager_state.global(), None);
+
+        // client data can't reach the state, it reports disconnects through a channel
+        let (client_disconnects, disconnect_receiver) = channel::channel::<ClientId>();
+        if let Err(err) = loop_handle.insert_source(disconnect_receiver, |event, _, state| {
+            if let channel::Event::Msg(client_id) = event {
+                state.client_disconnected(&client_id);
+            }
+        }) {
+            tracing::error!("Failed to watch client disconnects: {}", err.error);
+        }
 
         Self {
             display_handle: display_handle.clone(),
@@ -446,8 +548,15 @@ impl State {
             popups: PopupManager::default(),
             cursor_state: Mutex::new(CursorStateInner::default()),
             keybindings: Keybindings::new(),
+            window_layouts: WindowLayouts::new(),
+            pending_confirmation: None,
             session_active: false,
             needs_focus_refresh: false,
+            pointer_focus: None,
+            client_diagnostics: Arc::new(Mutex::new(ClientDiagnostics::new())),
+            client_disconnects,
+            client_pings: ClientPings::new(),
+            globals,
             viewporter_state,
             pointer_gestures_state,
             relative_pointer_manager_state,
@@ -460,9 +569,16 @@ impl State {
         }
     }
 
+    /// Drop the per-client bookkeeping of a client that went away
+    pub fn client_disconnected(&mut self, client_id: &ClientId) {
+        self.client_diagnostics.lock().unwrap().remove(client_id);
+    }
+
     pub fn session_active(&mut self, active: bool) {
         self.session_active = active;
         if active {
+            self.client_pings.session_resumed(std::time::Instant::now());
+
             // resume operations
             if let BackendData::Kms(kms) = &mut self.backend {
                 if let Err(err) = kms.libinput.resume() {
@@ -500,11 +616,7 @@ impl State {
         if let Some(window) = window {
             // restore keyboard focus to the window's surface
             let surface = window.toplevel().unwrap().wl_surface().clone();
-            keyboard.set_focus(
-                self,
-                Some(surface),
-                smithay::utils::SERIAL_COUNTER.next_serial(),
-            );
+            self.set_keyboard_focus(Some(surface), smithay::utils::SERIAL_COUNTER.next_serial());
 
             // also update pointer focus if needed
             if let Some(output) = self.outputs.first() {
@@ -514,11 +626,67 @@ impl State {
             tracing::info!("Focus restored to window");
         } else {
             // no window to focus, clear keyboard focus
-            keyboard.set_focus(self, None, smithay::utils::SERIAL_COUNTER.next_serial());
+            self.set_keyboard_focus(None, smithay::utils::SERIAL_COUNTER.next_serial());
             tracing::info!("No window to restore focus to, cleared focus");
         }
     }
 
+    /// Pointer focus for a cursor location, remembered for refresh_pointer_focus
+    pub fn pointer_focus_at(
+        &mut self,
+        location: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<f64, Logical>)> {
+        let focus = self.shell.read().unwrap().surface_under(location);
+        self.pointer_focus = focus.clone();
+        focus
+    }
+
+    /// Timestamp for events the compositor synthesizes, on the clock input events use
+    pub fn synthetic_event_time() -> u32 {
+        use smithay::utils::{Clock, Monotonic};
+
+        std::time::Duration::from(Clock::<Monotonic>::new().now()).as_millis() as u32
+    }
+
+    /// Recompute pointer focus at the current cursor position
+    ///
+    /// Windows can move or vanish under a stationary cursor (arrange, unmap). When
+    /// the surface under the cursor changed or moved, a motion event at the same
+    /// location is synthesized so clients get proper enter/leave events and
+    /// surface-local coordinates.
+    pub fn refresh_pointer_focus(&mut self) {
+        use smithay::input::pointer::MotionEvent;
+
+        let pointer = self.seat.get_pointer().unwrap();
+
+        // grabs (move, resize, drag and drop) manage focus themselves
+        if pointer.is_grabbed() {
+            return;
+        }
+
+        let location = pointer.current_location();
+        let previous_focus = self.pointer_focus.clone();
+        let surface_under = self.pointer_focus_at(location);
+        if surface_under == previous_focus
+            && surface_under.as_ref().map(|(surface, _)| surface)
+                == pointer.current_focus().as_ref()
+        {
+            return;
+        }
+
+        tracing::debug!("Surface under the cursor changed or moved, refreshing pointer focus");
+        pointer.motion(
+            self,
+            surface_under,
+            &MotionEvent {
+                location,
+                serial: smithay::utils::SERIAL_COUNTER.next_serial(),
+                time: Self::synthetic_event_time(),
+            },
+        );
+        pointer.frame(self);
+    }
+
     pub fn process_input_event<B: smithay::backend::input::InputBackend>(
         &mut self,
         event: InputEvent<B>,
@@ -579,6 +747,55 @@ impl State {
     }
 
     /// Update cached cursor bounds based on current output positions
+    /// Log what the D-Bus service reports, for builds without it
+    ///
+    /// Frame pacing, VRR, render path and skipped frames of each output, late buffers
+    /// of each window and clients with protocol issues.
+    pub fn log_diagnostics(&self) {
+        let shell = self.shell.read().unwrap();
+        for output in shell.space.outputs() {
+            let stats = jank::output_jank(output);
+            let vrr = surface::vrr_status(output);
+            let path = render_path::render_path(output);
+            tracing::info!(
+                "{}: {} missed deadlines and {} missed vblanks over {} frames, {} skipped, \
+                 vrr active: {} (floor {:.1}Hz), direct: {}, forced offscreen: {}, \
+                 fallback frames left: {}",
+                output.name(),
+                stats.missed_deadline,
+                stats.missed_vblank,
+                stats.frames,
+                fingerprint::skipped_frames(output),
+                vrr.active,
+                vrr.min_refresh_hz,
+                path.direct,
+                path.forced,
+                path.fallback_frames
+            );
+        }
+
+        for workspace in shell.workspaces.values() {
+            for window in &workspace.windows {
+                let Some((app_id, title)) = app_id_and_title(window) else {
+                    continue;
+                };
+                let (late, frames) = jank::window_late_buffers(window);
+                tracing::info!(
+                    "{} ({}) on workspace {}: {} of {} buffers late",
+                    app_id,
+                    title,
+                    workspace.name,
+                    late,
+                    frames
+                );
+            }
+        }
+
+        for client in self.client_diagnostics.lock().unwrap().list() {
+            tracing::info!("Client issues: {}", client);
+        }
+    }
+
     pub fn update_cursor_bounds(&mut self) {
         if self.outputs.is_empty() {
             self.cursor_bounds = None;
@@ -699,6 +916,12 @@ impl State {
                             );
 
                         self.dmabuf_global = Some(global);
+                        // smithay keeps the id of the dmabuf global to itself
+                        self.globals.insert(GlobalEntry {
+                            interface: ZwpLinuxDmabufV1::interface().name,
+                            version: 5,
+                            privileged: false,
+                        });
 
                         tracing::info!(
                             "Created dmabuf global for device {:?} with {} formats",
@@ -717,36 +940,19 @@ impl State {
                     &mut kms.gpu_manager,
                     self.shell.clone(),
                     self.seat.clone(),
+                    &self.globals,
                 ) {
                     Ok(outputs) => {
-                        // add outputs to the shell's space
-                        for output in &outputs {
-                            self.shell.write().unwrap().add_output(output);
-                        }
+                        // add outputs to the shell's space, with the virtual output
+                        // configuration from the environment
+                        let vout_config = std::env::var("SWL_VIRTUAL_OUTPUTS").ok();
+                        self.shell
+                            .write()
+                            .unwrap()
+                            .add_outputs(&outputs, vout_config.as_deref());
                         // add outputs to our state
                         self.outputs.extend(outputs.clone());
 
-                        // load virtual output configuration from environment and update
-                        {
-                            let mut shell = self.shell.write().unwrap();
-                            shell.virtual_output_manager.load_config(&self.outputs);
-                            shell.virtual_output_manager.update_all(&self.outputs);
-
-                            // ensure all virtual outputs have initial active workspaces
-                            let vout_ids: Vec<_> =
-                                shell.virtual_output_manager.all().map(|vo| vo.id).collect();
-                            for (i, vout_id) in vout_ids.iter().enumerate() {
-                                if let Some(vout) = shell.virtual_output_manager.get(*vout_id) {
-                                    if vout.active_workspace().is_none() {
-                                        // assign different workspaces to different virtual outputs
-                                        let workspace_name = (i + 1).to_string();
-                                        shell
-                                            .switch_workspace_on_virtual(*vout_id, &workspace_name);
-                                    }
-                                }
-                            }
-                        }
-
                         // register outputs with output configuration protocol
                         self.output_configuration_state.add_heads(outputs.iter());
                         self.output_configuration_state.update();
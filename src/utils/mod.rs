@@ -1,3 +1,7 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 pub mod coordinates;
+#[cfg(test)]
+pub mod test_client;
+#[cfg(test)]
+pub mod testing;
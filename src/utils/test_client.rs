@@ -0,0 +1,515 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! A wayland client for unit tests needing real windows.
+//!
+//! The client is connected to a test state over a socket pair, both ends get
+//! dispatched from the test thread by `Harness::roundtrip`. Toplevel configures
+//! are recorded per window and acked right away.
+
+use std::{
+    fs::File,
+    io::ErrorKind,
+    os::{
+        fd::{AsFd, FromRawFd},
+        unix::net::UnixStream,
+    },
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use smithay::{
+    desktop::Window,
+    input::pointer::MotionEvent,
+    output::Output,
+    reexports::{
+        calloop::EventLoop,
+        wayland_server::{Display, Resource},
+    },
+    utils::{Logical, Point, SERIAL_COUNTER},
+};
+use wayland_client::{
+    backend::WaylandError,
+    delegate_noop,
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_callback::{self, WlCallback},
+        wl_compositor::WlCompositor,
+        wl_keyboard::{self, WlKeyboard},
+        wl_pointer::{self, WlPointer},
+        wl_registry::{self, WlRegistry},
+        wl_seat::{self, WlSeat},
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::xdg::shell::client::{
+    xdg_surface::{self, XdgSurface},
+    xdg_toplevel::{self, XdgToplevel},
+    xdg_wm_base::{self, XdgWmBase},
+};
+
+use super::testing;
+use crate::{wayland::handlers::ClientState, State};
+
+/// Size of the buffers windows get mapped with
+const BUFFER_SIZE: (i32, i32) = (100, 100);
+
+/// A toplevel configure as seen by the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Configure {
+    pub size: (i32, i32),
+    pub states: Vec<xdg_toplevel::State>,
+}
+
+impl Configure {
+    pub fn has(&self, state: xdg_toplevel::State) -> bool {
+        self.states.contains(&state)
+    }
+}
+
+/// Keyboard focus change of a window, by index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusChange {
+    Enter(usize),
+    Leave(usize),
+}
+
+/// A toplevel of the client
+pub struct ClientWindow {
+    surface: WlSurface,
+    xdg_surface: XdgSurface,
+    toplevel: XdgToplevel,
+    /// Configures received and not taken yet
+    configures: Vec<Configure>,
+}
+
+/// The client side of the connection
+#[derive(Default)]
+pub struct Client {
+    compositor: Option<WlCompositor>,
+    shm: Option<WlShm>,
+    wm_base: Option<XdgWmBase>,
+    seat: Option<WlSeat>,
+    pointer: Option<WlPointer>,
+    keyboard: Option<WlKeyboard>,
+    windows: Vec<ClientWindow>,
+    /// Windows the pointer entered, not taken yet
+    pointer_enters: Vec<usize>,
+    /// Keyboard focus changes, not taken yet
+    keyboard_focus: Vec<FocusChange>,
+}
+
+impl Client {
+    fn window_index(&self, surface: &WlSurface) -> Option<usize> {
+        self.windows
+            .iter()
+            .position(|window| &window.surface == surface)
+    }
+}
+
+/// A test state without backend, showing the given outputs to a connected client
+pub struct Harness {
+    pub event_loop: EventLoop<'static, State>,
+    pub display: Display<State>,
+    pub state: State,
+    pub client: Client,
+    connection: Connection,
+    queue: EventQueue<Client>,
+}
+
+impl Harness {
+    /// Add the outputs, with virtual outputs laid out by a SWL_VIRTUAL_OUTPUTS config if
+    /// given, and connect the client
+    pub fn new(outputs: &[Output], vout_config: Option<&str>) -> Self {
+        let (event_loop, display, mut state) = testing::state();
+        state
+            .shell
+            .write()
+            .unwrap()
+            .add_outputs(outputs, vout_config);
+        state.outputs.extend(outputs.iter().cloned());
+
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        server_stream.set_nonblocking(true).unwrap();
+        client_stream.set_nonblocking(true).unwrap();
+        let client_state = ClientState::new(state.client_disconnects.clone());
+        display
+            .handle()
+            .insert_client(server_stream, Arc::new(client_state))
+            .unwrap();
+
+        let connection = Connection::from_socket(client_stream).unwrap();
+        let queue = connection.new_event_queue();
+        connection.display().get_registry(&queue.handle(), ());
+
+        let mut harness = Self {
+            event_loop,
+            display,
+            state,
+            client: Client::default(),
+            connection,
+            queue,
+        };
+        harness.roundtrip();
+        assert!(harness.client.compositor.is_some());
+        assert!(harness.client.shm.is_some());
+        assert!(harness.client.wm_base.is_some());
+        assert!(harness.client.seat.is_some());
+        // the pointer and keyboard are requested once the seat announced them
+        harness.roundtrip();
+        assert!(harness.client.pointer.is_some());
+        assert!(harness.client.keyboard.is_some());
+        harness
+    }
+
+    /// Dispatch both ends until the server handled every request sent so far and the
+    /// client every event sent in return
+    pub fn roundtrip(&mut self) {
+        let done = Arc::new(AtomicBool::new(false));
+        self.connection
+            .display()
+            .sync(&self.queue.handle(), done.clone());
+
+        for _ in 0..100 {
+            self.queue.flush().unwrap();
+            self.display.dispatch_clients(&mut self.state).unwrap();
+            self.display.flush_clients().unwrap();
+            self.event_loop
+                .dispatch(Duration::ZERO, &mut self.state)
+                .unwrap();
+            if let Some(guard) = self.queue.prepare_read() {
+                match guard.read() {
+                    Ok(_) => {}
+                    Err(WaylandError::Io(err)) if err.kind() == ErrorKind::WouldBlock => {}
+                    Err(err) => panic!("Failed to read events: {}", err),
+                }
+            }
+            self.queue.dispatch_pending(&mut self.client).unwrap();
+            if done.load(Ordering::Relaxed) {
+                return;
+            }
+        }
+        panic!("Roundtrip did not complete");
+    }
+
+    /// Create a toplevel, map it with a buffer once configured and return its index
+    pub fn map_window(&mut self) -> usize {
+        let qh = self.queue.handle();
+        let index = self.client.windows.len();
+        let surface = self
+            .client
+            .compositor
+            .as_ref()
+            .unwrap()
+            .create_surface(&qh, ());
+        let xdg_surface = self
+            .client
+            .wm_base
+            .as_ref()
+            .unwrap()
+            .get_xdg_surface(&surface, &qh, ());
+        let toplevel = xdg_surface.get_toplevel(&qh, index);
+        surface.commit();
+        self.client.windows.push(ClientWindow {
+            surface,
+            xdg_surface,
+            toplevel,
+            configures: Vec::new(),
+        });
+        self.roundtrip();
+
+        self.attach_buffer(index);
+        self.roundtrip();
+        index
+    }
+
+    /// Unmap a window by committing a null buffer
+    pub fn unmap_window(&mut self, index: usize) {
+        let surface = &self.client.windows[index].surface;
+        surface.attach(None, 0, 0);
+        surface.commit();
+        self.roundtrip();
+    }
+
+    /// Map an unmapped window again, going through the initial commit like a new one
+    pub fn remap_window(&mut self, index: usize) {
+        self.client.windows[index].surface.commit();
+        self.roundtrip();
+
+        self.attach_buffer(index);
+        self.roundtrip();
+    }
+
+    /// Destroy a window, as a client closing it does
+    pub fn close_window(&mut self, index: usize) {
+        let window = &self.client.windows[index];
+        window.toplevel.destroy();
+        window.xdg_surface.destroy();
+        window.surface.destroy();
+        self.roundtrip();
+    }
+
+    /// Move the pointer to a global location, as a motion event would
+    pub fn move_pointer(&mut self, location: Point<f64, Logical>) {
+        let pointer = self.state.seat.get_pointer().unwrap();
+        let focus = self.state.pointer_focus_at(location);
+        pointer.motion(
+            &mut self.state,
+            focus,
+            &MotionEvent {
+                location,
+                serial: SERIAL_COUNTER.next_serial(),
+                time: State::synthetic_event_time(),
+            },
+        );
+        pointer.frame(&mut self.state);
+        self.state.shell.write().unwrap().cursor_position = location;
+        self.roundtrip();
+    }
+
+    /// Arrange every output, as rendering them would
+    pub fn arrange(&mut self) {
+        let mut shell = self.state.shell.write().unwrap();
+        for output in &self.state.outputs {
+            shell.arrange_windows_on_output(output);
+        }
+        drop(shell);
+        self.roundtrip();
+    }
+
+    /// Take the configures a window received since the last call
+    pub fn take_configures(&mut self, index: usize) -> Vec<Configure> {
+        std::mem::take(&mut self.client.windows[index].configures)
+    }
+
+    /// Take the windows the pointer entered since the last call
+    pub fn take_pointer_enters(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.client.pointer_enters)
+    }
+
+    /// Take the keyboard focus changes since the last call
+    pub fn take_keyboard_focus(&mut self) -> Vec<FocusChange> {
+        std::mem::take(&mut self.client.keyboard_focus)
+    }
+
+    /// The compositor side of a window of the client
+    pub fn window(&self, index: usize) -> Window {
+        let id = self.client.windows[index].surface.id().protocol_id();
+        let shell = self.state.shell.read().unwrap();
+        shell
+            .workspaces
+            .values()
+            .flat_map(|workspace| workspace.windows.iter())
+            .find(|window| {
+                window
+                    .toplevel()
+                    .is_some_and(|toplevel| toplevel.wl_surface().id().protocol_id() == id)
+            })
+            .cloned()
+            .expect("window is not mapped")
+    }
+
+    fn attach_buffer(&mut self, index: usize) {
+        let (width, height) = BUFFER_SIZE;
+        let stride = width * 4;
+        let size = stride * height;
+
+        // SAFETY: the fd is new, the file is its only owner
+        let file = unsafe {
+            let fd = libc::memfd_create(c"swl-test".as_ptr(), libc::MFD_CLOEXEC);
+            assert!(fd >= 0, "memfd_create failed");
+            File::from_raw_fd(fd)
+        };
+        file.set_len(size as u64).unwrap();
+
+        let qh = self.queue.handle();
+        let shm = self.client.shm.as_ref().unwrap();
+        let pool = shm.create_pool(file.as_fd(), size, &qh, ());
+        let buffer: WlBuffer =
+            pool.create_buffer(0, width, height, stride, wl_shm::Format::Argb8888, &qh, ());
+        pool.destroy();
+
+        let surface = &self.client.windows[index].surface;
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage_buffer(0, 0, width, height);
+        surface.commit();
+    }
+}
+
+impl Dispatch<WlRegistry, ()> for Client {
+    fn event(
+        client: &mut Self,
+        registry: &WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            match interface.as_str() {
+                "wl_compositor" => {
+                    client.compositor = Some(registry.bind(name, version.min(6), qh, ()));
+                }
+                "wl_shm" => client.shm = Some(registry.bind(name, 1, qh, ())),
+                "xdg_wm_base" => {
+                    client.wm_base = Some(registry.bind(name, version.min(6), qh, ()));
+                }
+                "wl_seat" => {
+                    client.seat = Some(registry.bind(name, version.min(7), qh, ()));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<WlCallback, Arc<AtomicBool>> for Client {
+    fn event(
+        _: &mut Self,
+        _: &WlCallback,
+        event: wl_callback::Event,
+        done: &Arc<AtomicBool>,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_callback::Event::Done { .. } = event {
+            done.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Dispatch<WlSeat, ()> for Client {
+    fn event(
+        client: &mut Self,
+        seat: &WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            if capabilities.contains(wl_seat::Capability::Pointer) && client.pointer.is_none() {
+                client.pointer = Some(seat.get_pointer(qh, ()));
+            }
+            if capabilities.contains(wl_seat::Capability::Keyboard) && client.keyboard.is_none() {
+                client.keyboard = Some(seat.get_keyboard(qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<WlPointer, ()> for Client {
+    fn event(
+        client: &mut Self,
+        _: &WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_pointer::Event::Enter { surface, .. } = event {
+            if let Some(index) = client.window_index(&surface) {
+                client.pointer_enters.push(index);
+            }
+        }
+    }
+}
+
+impl Dispatch<WlKeyboard, ()> for Client {
+    fn event(
+        client: &mut Self,
+        _: &WlKeyboard,
+        event: wl_keyboard::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let change = match event {
+            wl_keyboard::Event::Enter { surface, .. } => {
+                client.window_index(&surface).map(FocusChange::Enter)
+            }
+            wl_keyboard::Event::Leave { surface, .. } => {
+                client.window_index(&surface).map(FocusChange::Leave)
+            }
+            _ => None,
+        };
+        client.keyboard_focus.extend(change);
+    }
+}
+
+impl Dispatch<XdgWmBase, ()> for Client {
+    fn event(
+        _: &mut Self,
+        wm_base: &XdgWmBase,
+        event: xdg_wm_base::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<XdgSurface, ()> for Client {
+    fn event(
+        _: &mut Self,
+        xdg_surface: &XdgSurface,
+        event: xdg_surface::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_surface::Event::Configure { serial } = event {
+            xdg_surface.ack_configure(serial);
+        }
+    }
+}
+
+impl Dispatch<XdgToplevel, usize> for Client {
+    fn event(
+        client: &mut Self,
+        _: &XdgToplevel,
+        event: xdg_toplevel::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_toplevel::Event::Configure {
+            width,
+            height,
+            states,
+        } = event
+        {
+            let states = states
+                .chunks_exact(4)
+                .map(|raw| u32::from_ne_bytes(raw.try_into().unwrap()))
+                .filter_map(|raw| xdg_toplevel::State::try_from(raw).ok())
+                .collect();
+            client.windows[*index].configures.push(Configure {
+                size: (width, height),
+                states,
+            });
+        }
+    }
+}
+
+delegate_noop!(Client: WlCompositor);
+delegate_noop!(Client: WlShmPool);
+delegate_noop!(Client: ignore WlShm);
+delegate_noop!(Client: ignore WlSurface);
+delegate_noop!(Client: ignore WlBuffer);
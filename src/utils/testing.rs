@@ -0,0 +1,71 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Helpers shared by unit tests.
+
+use smithay::{
+    backend::{
+        egl::{EGLContext, EGLDevice, EGLDisplay},
+        renderer::glow::GlowRenderer,
+    },
+    output::{Mode, Output, PhysicalProperties, Scale, Subpixel},
+    reexports::{calloop::EventLoop, wayland_server::Display},
+    utils::Transform,
+};
+
+use crate::State;
+
+/// A state without backend, socket or outputs, along with the loop and display it runs on
+pub fn state() -> (EventLoop<'static, State>, Display<State>, State) {
+    let event_loop = EventLoop::try_new().unwrap();
+    let display = Display::new().unwrap();
+    let state = State::new(
+        display.handle(),
+        String::new(),
+        event_loop.handle(),
+        event_loop.get_signal(),
+    );
+    (event_loop, display, state)
+}
+
+/// An output without a global, in the given mode, transform and scale, placed at `location`
+pub fn output(
+    name: &str,
+    size: (i32, i32),
+    transform: Transform,
+    scale: f64,
+    location: (i32, i32),
+) -> Output {
+    let output = Output::new(
+        name.to_string(),
+        PhysicalProperties {
+            size: (0, 0).into(),
+            subpixel: Subpixel::Unknown,
+            make: String::from("swl"),
+            model: String::from("test"),
+            serial_number: String::from("0"),
+        },
+    );
+    let mode = Mode {
+        size: size.into(),
+        refresh: 60_000,
+    };
+    output.change_current_state(
+        Some(mode),
+        Some(transform),
+        Some(Scale::Fractional(scale)),
+        Some(location.into()),
+    );
+    output.set_preferred(mode);
+    output
+}
+
+/// A renderer on the first EGL device usable without a display, e.g. llvmpipe
+pub fn headless_renderer() -> Option<GlowRenderer> {
+    EGLDevice::enumerate().ok()?.find_map(|device| {
+        // SAFETY: the device is moved into the display, which keeps it alive
+        let display = unsafe { EGLDisplay::new(device) }.ok()?;
+        let context = EGLContext::new(&display).ok()?;
+        // SAFETY: the context is new and only used by this renderer
+        unsafe { GlowRenderer::new(context) }.ok()
+    })
+}
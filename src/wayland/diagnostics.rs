@@ -0,0 +1,377 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Per-client bookkeeping of protocol violations and failed validations.
+//!
+//! Every site that detects a misbehaving client reports through
+//! `State::report_client_issue`, which counts the issue, rate-limits the log
+//! line and flags clients crossing a configurable threshold. The registry is
+//! listed by the D-Bus service and the diagnostics keybinding, and a client's
+//! entry goes away when it disconnects.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use smithay::{
+    reexports::wayland_server::{backend::ClientId, Client, Resource},
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceData},
+};
+use tracing::warn;
+
+use crate::State;
+
+/// Identical issues from the same client are logged at most once per window
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Maximum number of clients tracked, the least recently seen one is evicted first
+const MAX_TRACKED_CLIENTS: usize = 64;
+
+/// Default issue count after which a client gets flagged (SWL_CLIENT_ISSUE_THRESHOLD)
+const DEFAULT_THRESHOLD: u64 = 50;
+
+/// Category of client misbehavior
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ClientIssueKind {
+    /// Buffer that could not be imported or used
+    InvalidBuffer,
+    /// Popup that can't be configured or lacks a valid parent
+    InvalidPopup,
+    /// Popup grab requested without a valid serial or parent
+    InvalidGrab,
+    /// Layer surface requesting an exclusive zone larger than allowed
+    ExclusiveZone,
+}
+
+impl ClientIssueKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::InvalidBuffer => "invalid_buffer",
+            Self::InvalidPopup => "invalid_popup",
+            Self::InvalidGrab => "invalid_grab",
+            Self::ExclusiveZone => "exclusive_zone",
+        }
+    }
+}
+
+/// Issue counters for a single client
+#[derive(Debug)]
+pub struct ClientIssues {
+    pub pid: Option<i32>,
+    pub app_id: Option<String>,
+    pub counts: HashMap<ClientIssueKind, u64>,
+    pub total: u64,
+    last_seen: Instant,
+    /// last log time and number of reports suppressed since, per kind
+    last_logged: HashMap<ClientIssueKind, (Instant, u64)>,
+    flagged: bool,
+}
+
+impl ClientIssues {
+    fn new(pid: Option<i32>, now: Instant) -> Self {
+        Self {
+            pid,
+            app_id: None,
+            counts: HashMap::new(),
+            total: 0,
+            last_seen: now,
+            last_logged: HashMap::new(),
+            flagged: false,
+        }
+    }
+
+    /// Human readable client name for logs
+    fn display_name(&self) -> String {
+        match (&self.app_id, self.pid) {
+            (Some(app_id), Some(pid)) => format!("{} (pid {})", app_id, pid),
+            (Some(app_id), None) => app_id.clone(),
+            (None, Some(pid)) => format!("pid {}", pid),
+            (None, None) => "unknown client".to_string(),
+        }
+    }
+
+    /// Count an issue and decide whether it should be surfaced
+    fn record(&mut self, kind: ClientIssueKind, now: Instant, threshold: u64) -> IssueOutcome {
+        self.last_seen = now;
+        self.total += 1;
+        *self.counts.entry(kind).or_default() += 1;
+
+        let log = match self.last_logged.get_mut(&kind) {
+            Some((last, suppressed)) if now.duration_since(*last) < RATE_WINDOW => {
+                *suppressed += 1;
+                None
+            }
+            Some((last, suppressed)) => {
+                let previously_suppressed = *suppressed;
+                *last = now;
+                *suppressed = 0;
+                Some(previously_suppressed)
+            }
+            None => {
+                self.last_logged.insert(kind, (now, 0));
+                Some(0)
+            }
+        };
+
+        let crossed_threshold = !self.flagged && self.total >= threshold;
+        if crossed_threshold {
+            self.flagged = true;
+        }
+
+        IssueOutcome {
+            log,
+            crossed_threshold,
+        }
+    }
+
+    /// Listing line: `<name> total=<n> <kind>=<n> ...`, kinds in declaration order
+    fn summary(&self) -> String {
+        let mut line = format!("{} total={}", self.display_name(), self.total);
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort();
+        for (kind, count) in counts {
+            let _ = write!(line, " {}={}", kind.as_str(), count);
+        }
+        line
+    }
+}
+
+/// Outcome of recording an issue, tells the caller what to surface
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IssueOutcome {
+    /// the issue should be logged, with this many identical reports suppressed before it
+    pub log: Option<u64>,
+    /// the client just crossed the threshold
+    pub crossed_threshold: bool,
+}
+
+/// Registry of misbehaving clients, shared with the D-Bus service
+pub type SharedClientDiagnostics = Arc<Mutex<ClientDiagnostics>>;
+
+/// Registry of misbehaving clients
+#[derive(Debug)]
+pub struct ClientDiagnostics {
+    clients: HashMap<ClientId, ClientIssues>,
+    threshold: u64,
+}
+
+impl ClientDiagnostics {
+    pub fn new() -> Self {
+        let threshold = std::env::var("SWL_CLIENT_ISSUE_THRESHOLD")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_THRESHOLD);
+
+        Self {
+            clients: HashMap::new(),
+            threshold,
+        }
+    }
+
+    /// Count an issue for a client and decide whether it should be surfaced
+    pub fn record(
+        &mut self,
+        client_id: ClientId,
+        pid: Option<i32>,
+        kind: ClientIssueKind,
+        now: Instant,
+    ) -> IssueOutcome {
+        if !self.clients.contains_key(&client_id) && self.clients.len() >= MAX_TRACKED_CLIENTS {
+            self.evict_oldest();
+        }
+
+        self.clients
+            .entry(client_id)
+            .or_insert_with(|| ClientIssues::new(pid, now))
+            .record(kind, now, self.threshold)
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some(oldest) = self
+            .clients
+            .iter()
+            .min_by_key(|(_, issues)| issues.last_seen)
+            .map(|(id, _)| id.clone())
+        {
+            self.clients.remove(&oldest);
+        }
+    }
+
+    /// Forget a client, e.g. when it disconnects
+    pub fn remove(&mut self, client_id: &ClientId) {
+        self.clients.remove(client_id);
+    }
+
+    fn get_mut(&mut self, client_id: &ClientId) -> Option<&mut ClientIssues> {
+        self.clients.get_mut(client_id)
+    }
+
+    /// List clients with recorded issues, one line per client, most issues first
+    ///
+    /// Format: `<name> total=<n> <kind>=<n> ...`
+    pub fn list(&self) -> Vec<String> {
+        let mut clients: Vec<_> = self.clients.values().collect();
+        clients.sort_by(|a, b| b.total.cmp(&a.total));
+        clients.into_iter().map(ClientIssues::summary).collect()
+    }
+}
+
+impl State {
+    /// Report a misbehaving client
+    ///
+    /// Counts the issue in the per-client registry and logs it, deduplicated and
+    /// rate-limited per client and kind.
+    pub fn report_client_issue(
+        &mut self,
+        client: &Client,
+        kind: ClientIssueKind,
+        details: impl AsRef<str>,
+    ) {
+        let pid = client
+            .get_credentials(&self.display_handle)
+            .ok()
+            .map(|credentials| credentials.pid);
+        let outcome =
+            self.client_diagnostics
+                .lock()
+                .unwrap()
+                .record(client.id(), pid, kind, Instant::now());
+
+        if outcome.log.is_none() && !outcome.crossed_threshold {
+            return;
+        }
+
+        // resolve the app_id lazily, only when something gets surfaced
+        let app_id = self.client_app_id(client);
+        let (name, total) = {
+            let mut diagnostics = self.client_diagnostics.lock().unwrap();
+            let Some(issues) = diagnostics.get_mut(&client.id()) else {
+                return;
+            };
+            if issues.app_id.is_none() {
+                issues.app_id = app_id;
+            }
+            (issues.display_name(), issues.total)
+        };
+
+        if let Some(suppressed) = outcome.log {
+            if suppressed > 0 {
+                warn!(
+                    "Client {} {}: {} ({} similar reports suppressed)",
+                    name,
+                    kind.as_str(),
+                    details.as_ref(),
+                    suppressed
+                );
+            } else {
+                warn!("Client {} {}: {}", name, kind.as_str(), details.as_ref());
+            }
+        }
+
+        if outcome.crossed_threshold {
+            warn!("{} triggered {} protocol warnings", name, total);
+        }
+    }
+
+    /// app_id of the first toplevel owned by the client, if any
+    fn client_app_id(&self, client: &Client) -> Option<String> {
+        let shell = self.shell.read().unwrap();
+        shell
+            .space
+            .elements()
+            .filter_map(|window| window.toplevel())
+            .filter(|toplevel| toplevel.wl_surface().client().as_ref() == Some(client))
+            .find_map(|toplevel| {
+                with_states(toplevel.wl_surface(), |states| {
+                    states
+                        .data_map
+                        .get::<XdgToplevelSurfaceData>()
+                        .and_then(|data| data.lock().unwrap().app_id.clone())
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_every_issue() {
+        let now = Instant::now();
+        let mut issues = ClientIssues::new(Some(42), now);
+        issues.record(ClientIssueKind::InvalidBuffer, now, 50);
+        issues.record(ClientIssueKind::InvalidBuffer, now, 50);
+        issues.record(ClientIssueKind::InvalidGrab, now, 50);
+
+        assert_eq!(issues.total, 3);
+        assert_eq!(issues.counts[&ClientIssueKind::InvalidBuffer], 2);
+        assert_eq!(issues.counts[&ClientIssueKind::InvalidGrab], 1);
+    }
+
+    #[test]
+    fn identical_issues_are_logged_once_per_rate_window() {
+        let start = Instant::now();
+        let mut issues = ClientIssues::new(None, start);
+        let kind = ClientIssueKind::InvalidBuffer;
+
+        assert_eq!(issues.record(kind, start, 50).log, Some(0));
+        assert_eq!(
+            issues.record(kind, start + Duration::from_secs(1), 50).log,
+            None
+        );
+        assert_eq!(
+            issues.record(kind, start + Duration::from_secs(2), 50).log,
+            None
+        );
+
+        // the first report after the window tells how many were suppressed
+        let later = start + RATE_WINDOW;
+        assert_eq!(issues.record(kind, later, 50).log, Some(2));
+        assert_eq!(issues.record(kind, later, 50).log, None);
+    }
+
+    #[test]
+    fn kinds_are_rate_limited_separately() {
+        let now = Instant::now();
+        let mut issues = ClientIssues::new(None, now);
+
+        assert_eq!(
+            issues.record(ClientIssueKind::InvalidBuffer, now, 50).log,
+            Some(0)
+        );
+        assert_eq!(
+            issues.record(ClientIssueKind::InvalidGrab, now, 50).log,
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn threshold_is_crossed_once() {
+        let now = Instant::now();
+        let mut issues = ClientIssues::new(None, now);
+        let kind = ClientIssueKind::ExclusiveZone;
+
+        assert!(!issues.record(kind, now, 3).crossed_threshold);
+        assert!(!issues.record(kind, now, 3).crossed_threshold);
+        assert!(issues.record(kind, now, 3).crossed_threshold);
+        assert!(!issues.record(kind, now, 3).crossed_threshold);
+    }
+
+    #[test]
+    fn summary_lists_kinds_in_order() {
+        let now = Instant::now();
+        let mut issues = ClientIssues::new(Some(42), now);
+        issues.app_id = Some(String::from("firefox"));
+        issues.record(ClientIssueKind::ExclusiveZone, now, 50);
+        issues.record(ClientIssueKind::InvalidBuffer, now, 50);
+        issues.record(ClientIssueKind::InvalidBuffer, now, 50);
+
+        assert_eq!(
+            issues.summary(),
+            "firefox (pid 42) total=3 invalid_buffer=2 exclusive_zone=1"
+        );
+    }
+}
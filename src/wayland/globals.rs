@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Inventory of the globals the compositor advertises.
+//!
+//! Every created global is registered here by its id, its interface and
+//! version are read back from the display, so it is known what a build
+//! offers: `swl --list-protocols` prints the globals created at startup,
+//! org.swl.Compositor1 lists them at runtime, including the ones showing up
+//! with devices and outputs. Debug builds and a test compare the startup
+//! globals with the checked-in globals.expected, dropping a global or a
+//! smithay version bump then fails instead of silently shipping.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use smithay::reexports::wayland_server::{backend::GlobalId, Client, DisplayHandle};
+use tracing::warn;
+
+/// Globals created at startup, one "interface version [privileged]" per line
+pub const EXPECTED: &str = include_str!("globals.expected");
+
+/// Client filter of a global, None advertises it to every client
+pub type GlobalFilter = Option<fn(&Client) -> bool>;
+
+/// The filter to create a global with
+pub fn client_filter(
+    filter: GlobalFilter,
+) -> impl for<'c> Fn(&'c Client) -> bool + Clone + Send + Sync + 'static {
+    move |client: &Client| filter.is_none_or(|filter| filter(client))
+}
+
+/// A global offered to clients
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalEntry {
+    pub interface: &'static str,
+    pub version: u32,
+    /// only advertised to clients passing a filter
+    pub privileged: bool,
+}
+
+/// Globals registered so far, shared with the D-Bus service
+#[derive(Debug, Clone, Default)]
+pub struct Globals(Arc<Mutex<Vec<GlobalEntry>>>);
+
+impl Globals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a created global, privileged when created with a filter
+    pub fn register(&self, display: &DisplayHandle, global: &GlobalId, filter: GlobalFilter) {
+        match display.backend_handle().global_info(global.clone()) {
+            Ok(info) => self.insert(GlobalEntry {
+                interface: info.interface.name,
+                version: info.version,
+                privileged: filter.is_some(),
+            }),
+            Err(err) => warn!("Failed to look up created global: {}", err),
+        }
+    }
+
+    /// Record a global, globals created once per output or device are listed once
+    ///
+    /// Only identical entries are merged, instances of an interface differing in
+    /// version or privilege are all listed.
+    pub fn insert(&self, entry: GlobalEntry) {
+        let mut entries = self.0.lock().unwrap();
+        if !entries.contains(&entry) {
+            entries.push(entry);
+        }
+    }
+
+    /// Registered globals, sorted by interface, version and privilege
+    pub fn entries(&self) -> Vec<GlobalEntry> {
+        let mut entries = self.0.lock().unwrap().clone();
+        entries.sort_by_key(|entry| (entry.interface, entry.version, entry.privileged));
+        entries
+    }
+
+    /// Whether the registered globals are the ones listed in globals.expected
+    pub fn match_expected(&self) -> bool {
+        self.to_string().trim_end() == EXPECTED.trim_end()
+    }
+}
+
+impl fmt::Display for Globals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in self.entries() {
+            write!(f, "{} {}", entry.interface, entry.version)?;
+            if entry.privileged {
+                write!(f, " privileged")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing;
+
+    #[test]
+    fn startup_globals_match_the_snapshot() {
+        let (_event_loop, _display, state) = testing::state();
+        assert_eq!(
+            state.globals.to_string().trim_end(),
+            EXPECTED.trim_end(),
+            "advertised globals differ from src/wayland/globals.expected, update it if intended"
+        );
+    }
+
+    #[test]
+    fn only_identical_globals_are_merged() {
+        let globals = Globals::new();
+        let entry = |version, privileged| GlobalEntry {
+            interface: "zwp_linux_dmabuf_v1",
+            version,
+            privileged,
+        };
+        // one per device
+        globals.insert(entry(5, false));
+        globals.insert(entry(5, false));
+        globals.insert(entry(4, false));
+        globals.insert(entry(5, true));
+        assert_eq!(
+            globals.entries(),
+            [entry(4, false), entry(5, false), entry(5, true)]
+        );
+    }
+}
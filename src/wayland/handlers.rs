@@ -3,28 +3,33 @@
 pub mod dmabuf;
 
 use smithay::{
-    reexports::wayland_server::backend::ClientData, wayland::compositor::CompositorClientState,
+    reexports::{
+        calloop::channel::Sender,
+        wayland_server::backend::{ClientData, ClientId, DisconnectReason},
+    },
+    wayland::compositor::CompositorClientState,
 };
 
 /// Client data stored for each connected client
 pub struct ClientState {
     pub compositor_state: CompositorClientState,
+    /// tells the state about the disconnect, see State::client_disconnected
+    disconnects: Sender<ClientId>,
 }
 
 impl ClientData for ClientState {
-    fn initialized(&self, _client_id: smithay::reexports::wayland_server::backend::ClientId) {}
-    fn disconnected(
-        &self,
-        _client_id: smithay::reexports::wayland_server::backend::ClientId,
-        _reason: smithay::reexports::wayland_server::backend::DisconnectReason,
-    ) {
+    fn initialized(&self, _client_id: ClientId) {}
+    fn disconnected(&self, client_id: ClientId, _reason: DisconnectReason) {
+        // the loop is gone when shutting down, nothing left to clean up then
+        let _ = self.disconnects.send(client_id);
     }
 }
 
 impl ClientState {
-    pub fn new() -> Self {
+    pub fn new(disconnects: Sender<ClientId>) -> Self {
         Self {
             compositor_state: CompositorClientState::default(),
+            disconnects,
         }
     }
 }
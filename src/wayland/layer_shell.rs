@@ -1,20 +1,244 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex, OnceLock,
+};
+
 use smithay::{
     delegate_layer_shell,
     desktop::{layer_map_for_output, LayerSurface, WindowSurfaceType},
     output::Output,
-    reexports::wayland_server::protocol::wl_output::WlOutput,
-    wayland::shell::{
-        wlr_layer::{
-            Layer, LayerSurface as WlrLayerSurface, WlrLayerShellHandler, WlrLayerShellState,
+    reexports::wayland_server::{
+        protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+        Resource,
+    },
+    utils::{Logical, Size, Transform},
+    wayland::{
+        compositor::with_states,
+        shell::{
+            wlr_layer::{
+                Anchor, ExclusiveZone, Layer, LayerSurface as WlrLayerSurface,
+                LayerSurfaceCachedState, WlrLayerShellHandler, WlrLayerShellState,
+            },
+            xdg::PopupSurface,
         },
-        xdg::PopupSurface,
     },
 };
 use tracing::{debug, info};
 
-use crate::State;
+use crate::{wayland::diagnostics::ClientIssueKind, State};
+
+/// Default fraction of an output dimension that the exclusive zones of one edge may reserve
+const DEFAULT_MAX_EXCLUSIVE_FRACTION: f64 = 0.5;
+
+/// Monotonic counter used to order exclusive zone requests by commit
+static EXCLUSIVE_ZONE_ORDER: AtomicU64 = AtomicU64::new(0);
+
+/// Exclusive zone requested by a layer surface, before any clamping
+#[derive(Debug, Default)]
+struct ExclusiveZoneRequest {
+    requested: u32,
+    order: u64,
+}
+
+/// Output edge on which a layer surface reserves its exclusive zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExclusiveEdge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl ExclusiveEdge {
+    /// Same edge selection as smithay's layer map arrangement
+    fn from_anchor(anchor: Anchor) -> Option<Self> {
+        if anchor.contains(Anchor::LEFT) && !anchor.contains(Anchor::RIGHT) {
+            Some(Self::Left)
+        } else if anchor.contains(Anchor::TOP) && !anchor.contains(Anchor::BOTTOM) {
+            Some(Self::Top)
+        } else if anchor.contains(Anchor::RIGHT) && !anchor.contains(Anchor::LEFT) {
+            Some(Self::Right)
+        } else if anchor.contains(Anchor::BOTTOM) && !anchor.contains(Anchor::TOP) {
+            Some(Self::Bottom)
+        } else {
+            None
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Output dimension the exclusive zone is taken from
+    fn dimension(self, output_size: Size<i32, Logical>) -> i32 {
+        match self {
+            Self::Top | Self::Bottom => output_size.h,
+            Self::Left | Self::Right => output_size.w,
+        }
+    }
+}
+
+/// Maximum fraction of an output dimension one edge may reserve (SWL_MAX_EXCLUSIVE_ZONE)
+fn max_exclusive_fraction() -> f64 {
+    static FRACTION: OnceLock<f64> = OnceLock::new();
+    *FRACTION.get_or_init(|| {
+        std::env::var("SWL_MAX_EXCLUSIVE_ZONE")
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .map(|f| f.clamp(0.0, 1.0))
+            .unwrap_or(DEFAULT_MAX_EXCLUSIVE_FRACTION)
+    })
+}
+
+/// Exclusive zones granted to requests sorted by commit order
+///
+/// Each edge reserves at most `fraction` of the output dimension, requests are honored
+/// in order until their edge reaches the cap.
+fn grant_exclusive_zones(
+    output_size: Size<i32, Logical>,
+    fraction: f64,
+    requests: &[(ExclusiveEdge, u32)],
+) -> Vec<u32> {
+    let mut reserved = [0u32; 4];
+    requests
+        .iter()
+        .map(|(edge, requested)| {
+            let cap = (edge.dimension(output_size).max(0) as f64 * fraction) as u32;
+            let granted = (*requested).min(cap.saturating_sub(reserved[edge.index()]));
+            reserved[edge.index()] += granted;
+            granted
+        })
+        .collect()
+}
+
+fn output_logical_size(output: &Output) -> Option<Size<i32, Logical>> {
+    let mode = output.current_mode()?;
+    let scale = output.current_scale().fractional_scale();
+    Some(
+        Transform::from(output.current_transform())
+            .transform_size(mode.size)
+            .to_f64()
+            .to_logical(scale)
+            .to_i32_round(),
+    )
+}
+
+/// Remember the exclusive zone a layer surface asked for in its latest commit
+///
+/// Must be called on commit, before the zones are clamped, as clamping overwrites
+/// the current cached state of the surface.
+pub fn record_exclusive_zone_request(surface: &WlSurface) {
+    with_states(surface, |states| {
+        let requested = match states
+            .cached_state
+            .get::<LayerSurfaceCachedState>()
+            .current()
+            .exclusive_zone
+        {
+            ExclusiveZone::Exclusive(amount) => amount,
+            _ => 0,
+        };
+
+        states
+            .data_map
+            .insert_if_missing_threadsafe(|| Mutex::new(ExclusiveZoneRequest::default()));
+        let mut request = states
+            .data_map
+            .get::<Mutex<ExclusiveZoneRequest>>()
+            .unwrap()
+            .lock()
+            .unwrap();
+
+        // a surface keeps its place in the commit order for as long as it stays exclusive
+        if requested > 0 && request.requested == 0 {
+            request.order = EXCLUSIVE_ZONE_ORDER.fetch_add(1, Ordering::Relaxed);
+        }
+        request.requested = requested;
+    });
+}
+
+impl State {
+    /// Clamp the exclusive zones of the layer surfaces on an output
+    ///
+    /// Each edge may reserve at most SWL_MAX_EXCLUSIVE_ZONE (default 50%) of the output
+    /// dimension. Surfaces sharing an edge are honored in commit order until the cap is
+    /// reached, the remaining ones are still rendered but no longer reserve space.
+    /// Must be called before arranging the layer map.
+    pub fn clamp_exclusive_zones(&mut self, output: &Output) {
+        let Some(output_size) = output_logical_size(output) else {
+            return;
+        };
+        let fraction = max_exclusive_fraction();
+
+        let mut requests: Vec<_> = {
+            let layer_map = layer_map_for_output(output);
+            layer_map
+                .layers()
+                .filter_map(|layer| {
+                    let edge = ExclusiveEdge::from_anchor(layer.cached_state().anchor)?;
+                    let (requested, order) = with_states(layer.wl_surface(), |states| {
+                        states
+                            .data_map
+                            .get::<Mutex<ExclusiveZoneRequest>>()
+                            .map(|request| {
+                                let request = request.lock().unwrap();
+                                (request.requested, request.order)
+                            })
+                    })?;
+                    (requested > 0).then(|| (layer.clone(), edge, requested, order))
+                })
+                .collect()
+        };
+        requests.sort_by_key(|(_, _, _, order)| *order);
+        let granted = grant_exclusive_zones(
+            output_size,
+            fraction,
+            &requests
+                .iter()
+                .map(|(_, edge, requested, _)| (*edge, *requested))
+                .collect::<Vec<_>>(),
+        );
+
+        let mut clamped = Vec::new();
+        for ((layer, edge, requested, _), granted) in requests.into_iter().zip(granted) {
+            with_states(layer.wl_surface(), |states| {
+                states
+                    .cached_state
+                    .get::<LayerSurfaceCachedState>()
+                    .current()
+                    .exclusive_zone = if granted > 0 {
+                    ExclusiveZone::Exclusive(granted)
+                } else {
+                    ExclusiveZone::Neutral
+                };
+            });
+
+            if granted < requested {
+                clamped.push((layer, edge, requested, granted));
+            }
+        }
+
+        for (layer, edge, requested, granted) in clamped {
+            let Some(client) = layer.wl_surface().client() else {
+                continue;
+            };
+            self.report_client_issue(
+                &client,
+                ClientIssueKind::ExclusiveZone,
+                format!(
+                    "layer surface {} requested a {}px exclusive zone on the {:?} edge of output {}, clamped to {}px",
+                    layer.namespace(),
+                    requested,
+                    edge,
+                    output.name(),
+                    granted
+                ),
+            );
+        }
+    }
+}
 
 impl WlrLayerShellHandler for State {
     fn shell_state(&mut self) -> &mut WlrLayerShellState {
@@ -110,17 +334,22 @@ impl WlrLayerShellHandler for State {
         if let Some(output) = maybe_output {
             info!("Found output for layer surface");
             // unmap the layer
-            let mut map = layer_map_for_output(&output);
-            if let Some(layer) =
-                map.layer_for_surface(surface.wl_surface(), WindowSurfaceType::TOPLEVEL)
             {
-                let layer = layer.clone();
-                map.unmap_layer(&layer);
-                info!("Layer surface unmapped from output {}", output.name());
+                let mut map = layer_map_for_output(&output);
+                if let Some(layer) =
+                    map.layer_for_surface(surface.wl_surface(), WindowSurfaceType::TOPLEVEL)
+                {
+                    let layer = layer.clone();
+                    map.unmap_layer(&layer);
+                    info!("Layer surface unmapped from output {}", output.name());
+                }
             }
 
+            // surfaces ignored because of the exclusive zone cap may fit again
+            self.clamp_exclusive_zones(&output);
+
             // re-arrange layers and windows if exclusive zones changed
-            map.arrange();
+            layer_map_for_output(&output).arrange();
 
             // Always mark windows for re-arrangement when a layer surface is destroyed
             // as it may have had exclusive zones that affected window layout
@@ -147,3 +376,47 @@ impl WlrLayerShellHandler for State {
 }
 
 delegate_layer_shell!(State);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fhd() -> Size<i32, Logical> {
+        (1920, 1080).into()
+    }
+
+    #[test]
+    fn hostile_exclusive_zone_is_clamped_to_half_the_output() {
+        let granted = grant_exclusive_zones(fhd(), 0.5, &[(ExclusiveEdge::Top, 10000)]);
+        assert_eq!(granted, vec![540]);
+
+        // the workspaces keep the lower half of the output
+        let available_height = fhd().h - granted[0] as i32;
+        assert_eq!(available_height, 540);
+    }
+
+    #[test]
+    fn zones_sharing_an_edge_are_honored_in_commit_order() {
+        let granted = grant_exclusive_zones(
+            fhd(),
+            0.5,
+            &[
+                (ExclusiveEdge::Top, 30),
+                (ExclusiveEdge::Top, 10000),
+                (ExclusiveEdge::Top, 30),
+                (ExclusiveEdge::Left, 100),
+            ],
+        );
+        assert_eq!(granted, vec![30, 510, 0, 100]);
+    }
+
+    #[test]
+    fn reasonable_zones_are_untouched() {
+        let granted = grant_exclusive_zones(
+            fhd(),
+            0.5,
+            &[(ExclusiveEdge::Top, 30), (ExclusiveEdge::Bottom, 30)],
+        );
+        assert_eq!(granted, vec![30, 30]);
+    }
+}
@@ -1,11 +1,14 @@
 // SPDX-License-Identifier: GPL-3.0-only
 
 pub mod data_control;
+pub mod diagnostics;
 pub mod fractional_scale;
+pub mod globals;
 pub mod handlers;
 pub mod layer_shell;
 pub mod output_configuration;
 pub mod primary_selection;
+pub mod responsiveness;
 pub mod xdg_activation;
 
 use smithay::{
@@ -26,7 +29,7 @@ use smithay::{
         protocol::{
             wl_buffer::WlBuffer, wl_output::WlOutput, wl_seat::WlSeat, wl_surface::WlSurface,
         },
-        Client,
+        Client, Resource,
     },
     utils::Serial,
     utils::Size,
@@ -41,15 +44,16 @@ use smithay::{
             SelectionHandler,
         },
         shell::xdg::{
-            decoration::XdgDecorationHandler, PopupSurface, PositionerState, ToplevelSurface,
-            XdgShellHandler, XdgShellState,
+            decoration::XdgDecorationHandler, PopupSurface, PositionerState, ShellClient,
+            ToplevelSurface, XdgShellHandler, XdgShellState,
         },
         shm::{ShmHandler, ShmState},
     },
 };
 
+use self::diagnostics::ClientIssueKind;
 use self::handlers::ClientState;
-use crate::State;
+use crate::{backend::kms::surface::jank, State};
 use tracing::debug;
 
 impl CompositorHandler for State {
@@ -64,6 +68,7 @@ impl CompositorHandler for State {
     fn commit(&mut self, surface: &WlSurface) {
         // first load the buffer for various smithay helper functions (which also initializes the RendererSurfaceState)
         on_commit_buffer_handler::<Self>(surface);
+        jank::record_commit(surface);
 
         // check if this is a layer surface commit
         let outputs = self.outputs.clone();
@@ -86,6 +91,8 @@ impl CompositorHandler for State {
                     "Layer surface committing on output {}, re-arranging",
                     output.name()
                 );
+                layer_shell::record_exclusive_zone_request(surface);
+                self.clamp_exclusive_zones(output);
                 let changed = {
                     let mut layer_map = smithay::desktop::layer_map_for_output(output);
                     layer_map.arrange()
@@ -125,9 +132,8 @@ impl CompositorHandler for State {
 
                 if wants_focus {
                     //tracing::debug!("Layer surface requests keyboard focus");
-                    let keyboard = self.seat.get_keyboard().unwrap();
                     let serial = smithay::utils::SERIAL_COUNTER.next_serial();
-                    keyboard.set_focus(self, Some(surface.clone()), serial);
+                    self.set_keyboard_focus(Some(surface.clone()), serial);
                 }
 
                 // Don't send frame callbacks here - let the rendering pipeline handle it
@@ -199,12 +205,15 @@ impl CompositorHandler for State {
                         tracing::debug!("Window is fullscreen, updating shell state");
                         shell.set_fullscreen(window.clone(), true, &output);
                     }
+                    // a tag rule may have hidden the window right away
+                    let focus_new_window = shell.focused_window.as_ref() == Some(&window);
                     drop(shell); // release lock before setting keyboard focus
 
                     // set keyboard focus to the new window
-                    let keyboard = self.seat.get_keyboard().unwrap();
-                    let serial = smithay::utils::SERIAL_COUNTER.next_serial();
-                    keyboard.set_focus(self, Some(toplevel.wl_surface().clone()), serial);
+                    if focus_new_window {
+                        let serial = smithay::utils::SERIAL_COUNTER.next_serial();
+                        self.set_keyboard_focus(Some(toplevel.wl_surface().clone()), serial);
+                    }
                     //tracing::debug!("Set keyboard focus to new window");
 
                     // Don't send frame callbacks here - let the rendering pipeline handle it
@@ -222,6 +231,12 @@ impl CompositorHandler for State {
             }
         }
 
+        // a client may unmap its toplevel (null buffer) and map it again later,
+        // the window keeps its slot in between
+        if !mapped && self.handle_toplevel_unmap_cycle(surface) {
+            return;
+        }
+
         if !mapped {
             // handle regular window surface commits
             let output = {
@@ -272,6 +287,8 @@ impl CompositorHandler for State {
 
                 // refresh the space to update damage tracking
                 shell.refresh();
+                // a hidden window done resizing can be suspended now
+                shell.retry_deferred_suspension(surface);
 
                 output
             };
@@ -287,6 +304,76 @@ impl CompositorHandler for State {
     }
 }
 
+impl State {
+    /// Track client-side unmap/remap of mapped toplevels
+    ///
+    /// Returns true if the commit was fully handled.
+    fn handle_toplevel_unmap_cycle(&mut self, surface: &WlSurface) -> bool {
+        let has_buffer = with_renderer_surface_state(surface, |state| state.buffer().is_some())
+            .unwrap_or(false);
+
+        let mut shell = self.shell.write().unwrap();
+
+        if let Some(window) = shell.unmapped_window(surface) {
+            let outputs = if has_buffer {
+                tracing::debug!("Toplevel mapped again, restoring its previous slot");
+                window.on_commit();
+                shell.remap_window(&window)
+            } else {
+                // the client restarts the initial commit sequence after unmapping,
+                // answer it with the size of the slot it is going back to
+                if shell.take_initial_configure(&window) {
+                    if let Some(toplevel) = window.toplevel() {
+                        let size = shell
+                            .workspace_containing_window_mut(&window)
+                            .and_then(|workspace| workspace.window_rectangles.get(&window))
+                            .map(|rect| rect.size());
+                        if let Some(size) = size {
+                            toplevel.with_pending_state(|state| state.size = Some(size));
+                        }
+                        toplevel.send_configure();
+                    }
+                }
+                Vec::new()
+            };
+            drop(shell);
+
+            for output in outputs {
+                self.backend.schedule_render(&output);
+            }
+            return true;
+        }
+
+        if has_buffer {
+            return false;
+        }
+
+        // hidden workspaces and tag views hold mapped windows outside of the space too
+        let Some(window) = shell.toplevel_window(surface) else {
+            return false;
+        };
+
+        tracing::debug!("Toplevel unmapped by its client, keeping its slot");
+        let was_focused = shell.focused_window.as_ref() == Some(&window);
+        let outputs = shell.unmap_window(&window);
+        drop(shell);
+
+        // same as destruction, keyboard focus moves on until the window comes back
+        if was_focused {
+            self.set_keyboard_focus(
+                Option::<WlSurface>::None,
+                smithay::utils::SERIAL_COUNTER.next_serial(),
+            );
+            self.needs_focus_refresh = true;
+        }
+
+        for output in outputs {
+            self.backend.schedule_render(&output);
+        }
+        true
+    }
+}
+
 impl BufferHandler for State {
     fn buffer_destroyed(&mut self, _buffer: &WlBuffer) {}
 }
@@ -465,28 +552,37 @@ impl XdgShellHandler for State {
         });
 
         // Send the configure event to acknowledge the popup
-        if let Err(err) = surface.send_configure() {
-            tracing::warn!("Failed to configure popup: {:?}", err);
-        } else {
-            // Track the popup for proper rendering and input handling
-            if let Err(err) = self.popups.track_popup(PopupKind::from(surface)) {
-                tracing::warn!("Failed to track popup: {:?}", err);
-            }
+        let client = surface.wl_surface().client();
+        let result = surface
+            .send_configure()
+            .map_err(|err| format!("failed to configure popup: {:?}", err))
+            .and_then(|_| {
+                // Track the popup for proper rendering and input handling
+                self.popups
+                    .track_popup(PopupKind::from(surface))
+                    .map_err(|err| format!("failed to track popup: {:?}", err))
+            });
+        if let (Err(details), Some(client)) = (result, client) {
+            self.report_client_issue(&client, ClientIssueKind::InvalidPopup, details);
         }
     }
 
-    fn move_request(&mut self, _surface: ToplevelSurface, _seat: WlSeat, _serial: Serial) {
-        // we'll handle move requests later
+    fn move_request(&mut self, surface: ToplevelSurface, seat: WlSeat, serial: Serial) {
+        if let Some(seat) = Seat::from_resource(&seat) {
+            self.start_move_grab(&surface, &seat, serial);
+        }
     }
 
     fn resize_request(
         &mut self,
-        _surface: ToplevelSurface,
-        _seat: WlSeat,
-        _serial: Serial,
-        _edges: xdg_toplevel::ResizeEdge,
+        surface: ToplevelSurface,
+        seat: WlSeat,
+        serial: Serial,
+        edges: xdg_toplevel::ResizeEdge,
     ) {
-        // we'll handle resize requests later
+        if let Some(seat) = Seat::from_resource(&seat) {
+            self.start_resize_grab(&surface, &seat, serial, edges);
+        }
     }
 
     fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
@@ -505,7 +601,8 @@ impl XdgShellHandler for State {
                 .space
                 .elements()
                 .find(|w| w.toplevel().map_or(false, |t| t == &surface))
-                .cloned();
+                .cloned()
+                .or_else(|| shell.unmapped_window(surface.wl_surface()));
 
             if let Some(window) = window_to_remove {
                 // check if focused window was destroyed
@@ -521,9 +618,7 @@ impl XdgShellHandler for State {
         // if the destroyed window was focused, clear keyboard focus and mark for refresh
         if was_focused {
             // clear keyboard focus immediately to ensure refresh_focus works properly
-            let keyboard = self.seat.get_keyboard().unwrap();
-            keyboard.set_focus(
-                self,
+            self.set_keyboard_focus(
                 Option::<WlSurface>::None,
                 smithay::utils::SERIAL_COUNTER.next_serial(),
             );
@@ -655,12 +750,19 @@ impl XdgShellHandler for State {
 
     fn grab(&mut self, surface: PopupSurface, seat: WlSeat, serial: Serial) {
         let seat = Seat::from_resource(&seat).unwrap();
+        let client = surface.wl_surface().client();
         let kind = PopupKind::Xdg(surface);
 
         // Find the root surface for this popup
         let maybe_root = find_popup_root_surface(&kind).ok();
         if maybe_root.is_none() {
-            tracing::warn!("No root surface found for popup grab");
+            if let Some(client) = client {
+                self.report_client_issue(
+                    &client,
+                    ClientIssueKind::InvalidGrab,
+                    "no root surface found for popup grab",
+                );
+            }
             return;
         }
 
@@ -700,7 +802,13 @@ impl XdgShellHandler for State {
                 }
             }
             Err(err) => {
-                tracing::warn!("Failed to grab popup: {:?}", err);
+                if let Some(client) = client {
+                    self.report_client_issue(
+                        &client,
+                        ClientIssueKind::InvalidGrab,
+                        format!("failed to grab popup: {:?}", err),
+                    );
+                }
             }
         }
     }
@@ -713,6 +821,10 @@ impl XdgShellHandler for State {
     ) {
         // we'll handle repositioning later
     }
+
+    fn client_pong(&mut self, client: ShellClient) {
+        self.client_pong_received(&client);
+    }
 }
 
 // delegate protocol handling to smithay
@@ -34,7 +34,7 @@ pub struct OutputConfigurationState {
     outputs: Vec<Output>,
     instances: Vec<OutputMngrInstance>,
     serial_counter: u32,
-    _global: GlobalId, // kept alive to maintain global
+    global: GlobalId,
     dh: DisplayHandle,
 }
 
@@ -125,11 +125,15 @@ impl OutputConfigurationState {
             outputs: Vec::new(),
             instances: Vec::new(),
             serial_counter: 0,
-            _global: global,
+            global,
             dh: dh.clone(),
         }
     }
 
+    pub fn global(&self) -> GlobalId {
+        self.global.clone()
+    }
+
     pub fn add_heads<'a>(&mut self, outputs: impl Iterator<Item = &'a Output>) {
         let new_outputs = outputs
             .filter(|o| !self.outputs.contains(o))
@@ -0,0 +1,411 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+//! Detection of clients that stopped responding.
+//!
+//! Clients showing a window that is mapped, visible and not suspended get an
+//! xdg_wm_base ping every few seconds. Clients may stall on purpose while
+//! nothing of them is shown (our Suspended state, SIGSTOP, background
+//! throttling), so the pending ping of a client without such a window is
+//! dropped and its timer paused until one shows up again. A missed pong only
+//! makes a client suspect, a second one marks it unresponsive, and never
+//! within a grace period after the session came back from a VT switch. The
+//! windows of unresponsive clients are dimmed until they answer again.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use smithay::{
+    reexports::{
+        calloop::{
+            timer::{TimeoutAction, Timer},
+            LoopHandle,
+        },
+        wayland_server::{backend::ClientId, Resource},
+    },
+    utils::SERIAL_COUNTER,
+    wayland::shell::xdg::ShellClient,
+};
+use tracing::{info, warn};
+
+use crate::State;
+
+/// How often clients with visible windows get pinged
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time a client has to answer a ping
+const PONG_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Clients are never marked unresponsive this long after the session resumed
+const RESUME_GRACE: Duration = Duration::from_secs(10);
+
+/// How often pings are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether a client answers its pings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Responsiveness {
+    Responsive,
+    /// missed one pong, may just have been busy
+    Suspect {
+        since: Instant,
+    },
+    Unresponsive,
+}
+
+impl Responsiveness {
+    /// A pong arrived
+    fn pong(self) -> Self {
+        Self::Responsive
+    }
+
+    /// A ping timed out while the client had a visible window
+    fn missed_pong(self, now: Instant, in_resume_grace: bool) -> Self {
+        match self {
+            Self::Responsive => Self::Suspect { since: now },
+            Self::Suspect { since } if in_resume_grace => Self::Suspect { since },
+            Self::Suspect { .. } | Self::Unresponsive => Self::Unresponsive,
+        }
+    }
+}
+
+/// Ping timing and verdict of a single client
+#[derive(Debug, Clone, Copy)]
+struct PingTimer {
+    responsiveness: Responsiveness,
+    /// when the ping waiting for its pong was sent
+    pending_since: Option<Instant>,
+    last_ping: Option<Instant>,
+}
+
+impl PingTimer {
+    fn new() -> Self {
+        Self {
+            responsiveness: Responsiveness::Responsive,
+            pending_since: None,
+            last_ping: None,
+        }
+    }
+
+    /// Advance on a periodic check, returns whether a ping is due
+    ///
+    /// A client without a visible window has its pending ping dropped, it is only
+    /// judged on pings sent while it shows something.
+    fn check(&mut self, visible: bool, now: Instant, in_resume_grace: bool) -> bool {
+        if !visible {
+            self.pending_since = None;
+            return false;
+        }
+
+        match self.pending_since {
+            Some(sent) if now.duration_since(sent) >= PONG_TIMEOUT => {
+                self.pending_since = None;
+                self.responsiveness = self.responsiveness.missed_pong(now, in_resume_grace);
+                false
+            }
+            Some(_) => false,
+            None => self
+                .last_ping
+                .is_none_or(|last| now.duration_since(last) >= PING_INTERVAL),
+        }
+    }
+
+    fn ping_sent(&mut self, now: Instant) {
+        self.pending_since = Some(now);
+        self.last_ping = Some(now);
+    }
+
+    fn pong(&mut self) {
+        self.pending_since = None;
+        self.responsiveness = self.responsiveness.pong();
+    }
+}
+
+/// Ping bookkeeping of a single client
+#[derive(Debug)]
+struct ClientPing {
+    shell_client: ShellClient,
+    timer: PingTimer,
+}
+
+/// Ping state of all clients with windows
+#[derive(Debug)]
+pub struct ClientPings {
+    clients: HashMap<ClientId, ClientPing>,
+    resumed_at: Option<Instant>,
+}
+
+impl ClientPings {
+    pub fn new() -> Self {
+        Self {
+            clients: HashMap::new(),
+            resumed_at: None,
+        }
+    }
+
+    /// Clients legitimately stall across a VT switch, start over and hold back verdicts
+    pub fn session_resumed(&mut self, now: Instant) {
+        self.resumed_at = Some(now);
+        for client in self.clients.values_mut() {
+            client.timer.pending_since = None;
+        }
+    }
+
+    fn in_resume_grace(&self, now: Instant) -> bool {
+        self.resumed_at
+            .is_some_and(|resumed_at| now.duration_since(resumed_at) < RESUME_GRACE)
+    }
+
+    /// Whether a client stopped answering its pings
+    pub fn is_unresponsive(&self, client_id: &ClientId) -> bool {
+        self.clients
+            .get(client_id)
+            .is_some_and(|client| client.timer.responsiveness == Responsiveness::Unresponsive)
+    }
+}
+
+/// Start checking client pings periodically
+pub fn start(handle: &LoopHandle<'static, State>) {
+    let timer = handle.insert_source(Timer::from_duration(CHECK_INTERVAL), |_, _, state| {
+        state.check_client_pings(Instant::now());
+        TimeoutAction::ToDuration(CHECK_INTERVAL)
+    });
+    if let Err(err) = timer {
+        warn!("Failed to start client ping timer: {}", err);
+    }
+}
+
+impl State {
+    /// Ping clients with visible windows and judge the ones missing their pong
+    fn check_client_pings(&mut self, now: Instant) {
+        // clients with a window that is mapped (in the space), visible and not suspended
+        let eligible: HashMap<ClientId, ShellClient> = {
+            let shell = self.shell.read().unwrap();
+            shell
+                .space
+                .elements()
+                .filter(|window| !shell.is_suspended(window))
+                .filter_map(|window| window.toplevel())
+                .filter_map(|toplevel| {
+                    let client = toplevel.wl_surface().client()?;
+                    Some((client.id(), toplevel.client()))
+                })
+                .collect()
+        };
+
+        let in_resume_grace = self.client_pings.in_resume_grace(now);
+        let pings = &mut self.client_pings.clients;
+        pings.retain(|_, client| client.shell_client.alive());
+
+        for (client_id, shell_client) in &eligible {
+            pings
+                .entry(client_id.clone())
+                .or_insert_with(|| ClientPing {
+                    shell_client: shell_client.clone(),
+                    timer: PingTimer::new(),
+                });
+        }
+
+        for (client_id, client) in pings.iter_mut() {
+            let previous = client.timer.responsiveness;
+            let visible = eligible.contains_key(client_id);
+            if client.timer.check(visible, now, in_resume_grace)
+                && client
+                    .shell_client
+                    .send_ping(SERIAL_COUNTER.next_serial())
+                    .is_ok()
+            {
+                client.timer.ping_sent(now);
+            }
+
+            match client.timer.responsiveness {
+                Responsiveness::Unresponsive if previous != Responsiveness::Unresponsive => {
+                    warn!("Client {:?} is not responding", client_id);
+                }
+                Responsiveness::Suspect { .. } if previous == Responsiveness::Responsive => {
+                    info!("Client {:?} missed a ping", client_id);
+                }
+                _ => {}
+            }
+        }
+
+        self.publish_unresponsive_clients();
+    }
+
+    /// A client answered its ping
+    pub fn client_pong_received(&mut self, shell_client: &ShellClient) {
+        let Some((client_id, client)) = self
+            .client_pings
+            .clients
+            .iter_mut()
+            .find(|(_, client)| client.shell_client == *shell_client)
+        else {
+            return;
+        };
+
+        if client.timer.responsiveness == Responsiveness::Unresponsive {
+            info!("Client {:?} is responding again", client_id);
+        }
+        client.timer.pong();
+        self.publish_unresponsive_clients();
+    }
+
+    /// Hand the unresponsive clients to the shell, which dims their windows
+    fn publish_unresponsive_clients(&mut self) {
+        let unresponsive: HashSet<ClientId> = self
+            .client_pings
+            .clients
+            .keys()
+            .filter(|client_id| self.client_pings.is_unresponsive(client_id))
+            .cloned()
+            .collect();
+
+        let changed = self
+            .shell
+            .write()
+            .unwrap()
+            .set_unresponsive_clients(unresponsive);
+        if changed {
+            for output in self.outputs.clone() {
+                self.backend.schedule_render(&output);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missed_pong_makes_a_responsive_client_suspect() {
+        let now = Instant::now();
+        assert_eq!(
+            Responsiveness::Responsive.missed_pong(now, false),
+            Responsiveness::Suspect { since: now }
+        );
+        // a first miss is only suspicious, grace or not
+        assert_eq!(
+            Responsiveness::Responsive.missed_pong(now, true),
+            Responsiveness::Suspect { since: now }
+        );
+    }
+
+    #[test]
+    fn second_missed_pong_makes_a_suspect_client_unresponsive() {
+        let since = Instant::now();
+        let now = since + PING_INTERVAL;
+        assert_eq!(
+            Responsiveness::Suspect { since }.missed_pong(now, false),
+            Responsiveness::Unresponsive
+        );
+        assert_eq!(
+            Responsiveness::Unresponsive.missed_pong(now, false),
+            Responsiveness::Unresponsive
+        );
+    }
+
+    #[test]
+    fn suspect_client_is_not_judged_during_resume_grace() {
+        let since = Instant::now();
+        let now = since + PING_INTERVAL;
+        assert_eq!(
+            Responsiveness::Suspect { since }.missed_pong(now, true),
+            Responsiveness::Suspect { since }
+        );
+    }
+
+    #[test]
+    fn pong_makes_any_client_responsive() {
+        let since = Instant::now();
+        for responsiveness in [
+            Responsiveness::Responsive,
+            Responsiveness::Suspect { since },
+            Responsiveness::Unresponsive,
+        ] {
+            assert_eq!(responsiveness.pong(), Responsiveness::Responsive);
+        }
+    }
+
+    #[test]
+    fn resume_grace_ends_after_a_while() {
+        let resumed_at = Instant::now();
+        let mut pings = ClientPings::new();
+        assert!(!pings.in_resume_grace(resumed_at));
+
+        pings.session_resumed(resumed_at);
+        assert!(pings.in_resume_grace(resumed_at));
+        assert!(pings.in_resume_grace(resumed_at + RESUME_GRACE - CHECK_INTERVAL));
+        assert!(!pings.in_resume_grace(resumed_at + RESUME_GRACE));
+    }
+
+    #[test]
+    fn pings_are_sent_every_interval() {
+        let start = Instant::now();
+        let mut timer = PingTimer::new();
+        assert!(timer.check(true, start, false));
+        timer.ping_sent(start);
+
+        // no second ping while one is pending
+        assert!(!timer.check(true, start + CHECK_INTERVAL, false));
+        timer.pong();
+        assert!(!timer.check(true, start + CHECK_INTERVAL, false));
+        assert!(timer.check(true, start + PING_INTERVAL, false));
+    }
+
+    #[test]
+    fn unanswered_pings_lead_to_a_verdict() {
+        let start = Instant::now();
+        let mut timer = PingTimer::new();
+        timer.check(true, start, false);
+        timer.ping_sent(start);
+
+        assert!(!timer.check(true, start + PONG_TIMEOUT - CHECK_INTERVAL, false));
+        assert_eq!(timer.responsiveness, Responsiveness::Responsive);
+
+        let missed = start + PONG_TIMEOUT;
+        timer.check(true, missed, false);
+        assert_eq!(
+            timer.responsiveness,
+            Responsiveness::Suspect { since: missed }
+        );
+
+        let next = start + PING_INTERVAL;
+        assert!(timer.check(true, next, false));
+        timer.ping_sent(next);
+        timer.check(true, next + PONG_TIMEOUT, false);
+        assert_eq!(timer.responsiveness, Responsiveness::Unresponsive);
+
+        timer.pong();
+        assert_eq!(timer.responsiveness, Responsiveness::Responsive);
+    }
+
+    #[test]
+    fn hidden_client_is_not_judged() {
+        let start = Instant::now();
+        let mut timer = PingTimer::new();
+        timer.check(true, start, false);
+        timer.ping_sent(start);
+
+        // hidden long past the timeout, the pending ping is dropped without a verdict
+        let hidden = start + PONG_TIMEOUT * 10;
+        assert!(!timer.check(false, hidden, false));
+        assert_eq!(timer.pending_since, None);
+        assert_eq!(timer.responsiveness, Responsiveness::Responsive);
+
+        // shown again, only a new ping can time out
+        let shown = hidden + CHECK_INTERVAL;
+        assert!(timer.check(true, shown, false));
+        timer.ping_sent(shown);
+        timer.check(true, shown + CHECK_INTERVAL, false);
+        assert_eq!(timer.responsiveness, Responsiveness::Responsive);
+        timer.check(true, shown + PONG_TIMEOUT, false);
+        assert_eq!(
+            timer.responsiveness,
+            Responsiveness::Suspect {
+                since: shown + PONG_TIMEOUT
+            }
+        );
+    }
+}